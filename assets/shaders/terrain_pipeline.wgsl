@@ -13,13 +13,15 @@
 #import bevy_pbr::mesh_view_bindings view
 #import bevy_core_pipeline::tonemapping tone_mapping
 
-#import "shaders/voxel_data.wgsl" voxel_data_extract_normal, voxel_data_extract_material_index
-#import "shaders/terrain_uniforms.wgsl" VoxelMat, voxel_materials, render_distance, TERRAIN_CHUNK_LENGTH
+#import "shaders/voxel_data.wgsl" voxel_data_extract_normal, voxel_data_extract_position, voxel_data_extract_material_index, voxel_data_is_chunk_border
+#import "shaders/terrain_uniforms.wgsl" VoxelMat, voxel_materials, fog_distance, tint, voxel_scale, TERRAIN_CHUNK_LENGTH
 #import "shaders/noise.wgsl" hash
 #import "shaders/fog.wgsl" ffog_apply_fog
 
 struct Vertex {
+#ifndef PACKED_POSITIONS
     @location(0) position: vec3<f32>,
+#endif
     @location(1) voxel_data: u32,
 };
 
@@ -32,7 +34,12 @@ struct VertexOutput {
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
-    let world_position = bevy_pbr::mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+#ifdef PACKED_POSITIONS
+    let position = voxel_data_extract_position(vertex.voxel_data) * voxel_scale;
+#else
+    let position = vertex.position;
+#endif
+    let world_position = bevy_pbr::mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(position, 1.0));
 
     var out: VertexOutput;
     out.clip_position = bevy_pbr::mesh_functions::mesh_position_world_to_clip(world_position);
@@ -57,6 +64,10 @@ struct Fragment {
 fn prepare_pbr_input_from_voxel_mat(voxel_mat: VoxelMat, frag: Fragment) -> PbrInput {
     var base_color: vec4<f32> = voxel_mat.base_color;
     base_color = base_color + hash(vec4<f32>(floor(frag.world_position - frag.voxel_normal * 0.5), 1.0)) * 0.0226;
+    if voxel_data_is_chunk_border(frag.voxel_data) {
+        base_color = mix(base_color, vec4<f32>(1.0, 0.0, 1.0, 1.0), 0.6);
+    }
+    base_color = base_color * tint;
 
     var pbr_input: PbrInput = pbr_input_new();
     pbr_input.material.metallic = voxel_mat.metallic;
@@ -87,6 +98,6 @@ fn fragment(frag: Fragment) -> @location(0) vec4<f32> {
     // @todo: switch to bevy_pbr::fog
 
     //fragment distance from camera, used to determine amount of fog to apply.
-    let fog_distance = distance(frag.world_position, view.world_position);
-    return ffog_apply_fog(fog_distance, f32(render_distance) * f32(TERRAIN_CHUNK_LENGTH), f32(TERRAIN_CHUNK_LENGTH), pbr_colour);
+    let camera_distance = distance(frag.world_position, view.world_position);
+    return ffog_apply_fog(camera_distance, fog_distance, f32(TERRAIN_CHUNK_LENGTH), pbr_colour);
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+#import bevy_pbr::prepass_bindings
+#import bevy_pbr::mesh_functions
+#import bevy_pbr::mesh_bindings mesh
+
+#import "shaders/voxel_data.wgsl" voxel_data_extract_normal, voxel_data_extract_position
+#import "shaders/terrain_uniforms.wgsl" voxel_scale
+
+// The vertex stage of the terrain prepasses, including the shadow passes, reading the terrain vertex layout.
+// The outputs match the inputs of bevy's default prepass fragment shader.
+
+struct Vertex {
+#ifndef PACKED_POSITIONS
+    @location(0) position: vec3<f32>,
+#endif
+    @location(1) voxel_data: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+
+#ifdef VERTEX_UVS
+    @location(0) uv: vec2<f32>,
+#endif // VERTEX_UVS
+
+#ifdef NORMAL_PREPASS
+    @location(1) world_normal: vec3<f32>,
+#endif // NORMAL_PREPASS
+
+#ifdef MOTION_VECTOR_PREPASS
+    @location(3) world_position: vec4<f32>,
+    @location(4) previous_world_position: vec4<f32>,
+#endif // MOTION_VECTOR_PREPASS
+
+#ifdef DEPTH_CLAMP_ORTHO
+    @location(5) clip_position_unclamped: vec4<f32>,
+#endif // DEPTH_CLAMP_ORTHO
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+#ifdef PACKED_POSITIONS
+    let position = vec4<f32>(voxel_data_extract_position(vertex.voxel_data) * voxel_scale, 1.0);
+#else
+    let position = vec4<f32>(vertex.position, 1.0);
+#endif
+
+    var out: VertexOutput;
+    out.clip_position = bevy_pbr::mesh_functions::mesh_position_local_to_clip(mesh.model, position);
+#ifdef DEPTH_CLAMP_ORTHO
+    out.clip_position_unclamped = out.clip_position;
+    out.clip_position.z = min(out.clip_position.z, 1.0);
+#endif // DEPTH_CLAMP_ORTHO
+
+#ifdef VERTEX_UVS
+    // the terrain isn't textured yet.
+    out.uv = vec2<f32>(0.0);
+#endif // VERTEX_UVS
+
+#ifdef NORMAL_PREPASS
+    out.world_normal = bevy_pbr::mesh_functions::mesh_normal_local_to_world(voxel_data_extract_normal(vertex.voxel_data));
+#endif // NORMAL_PREPASS
+
+#ifdef MOTION_VECTOR_PREPASS
+    out.world_position = bevy_pbr::mesh_functions::mesh_position_local_to_world(mesh.model, position);
+    out.previous_world_position = bevy_pbr::mesh_functions::mesh_position_local_to_world(mesh.previous_model, position);
+#endif // MOTION_VECTOR_PREPASS
+
+    return out;
+}
@@ -10,9 +10,18 @@ struct VoxelMat {
     reflectance: f32,
 };
 
+// The distance from the camera at which the terrain is fully fogged, in world units.
 @group(1) @binding(0)
-var<uniform> render_distance: u32;
+var<uniform> fog_distance: f32;
 
 // A GPU-suited representation of voxel materials.
 @group(1) @binding(1)
-var<uniform> voxel_materials: array<VoxelMat, 256>;
\ No newline at end of file
+var<uniform> voxel_materials: array<VoxelMat, 256>;
+
+// The color multiplying the base color of every voxel, to tell chunks apart when debugging.
+@group(1) @binding(2)
+var<uniform> tint: vec4<f32>;
+
+// The size of a voxel in world units, scaling the positions packed in the voxel data.
+@group(1) @binding(3)
+var<uniform> voxel_scale: f32;
\ No newline at end of file
@@ -4,15 +4,16 @@
 // Layout of voxel information encoded into a single u32
 //
 //  00000000    00000000    00000000    00000000    
-//  XXXXXYYY    YYZZZZZ          NNN    MATERIAL
+//  XXXXXXYY    YYYYZZZZ    ZZ  BNNN    MATERIAL
 //
-// X: X position
-// Y: Y position
-// Z: Z position
+// X: X position, only set in meshes with packed positions
+// Y: Y position, only set in meshes with packed positions
+// Z: Z position, only set in meshes with packed positions
+// B: set on the faces of the outermost voxel layer of a chunk, when debugging chunk borders
 // N: normal index in the VOXEL_NORMALS array
 // MATERIAL: material index in the palette
 // 
-// The remaining 5 free bits could be used to store UV data or additional info or even extend voxel material id size.
+// The remaining 2 free bits could be used to store UV data or additional info or even extend voxel material id size.
 
 // An array of voxel face normals 
 var<private> VOXEL_NORMALS: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
@@ -29,13 +30,19 @@ fn voxel_data_extract_normal(voxel_data: u32) -> vec3<f32> {
     return VOXEL_NORMALS[voxel_data >> 8u & 7u];
 }
 
-// fn voxel_data_extract_position(voxel_data: u32) -> vec3<f32> {
-//     return vec3<f32>(
-//         f32(voxel_data >> 27u),
-//         f32(voxel_data >> 22u & 31u),
-//         f32(voxel_data >> 17u & 31u)
-//     );
-// }
+// Extracts the packed vertex position from the encoded voxel data, in voxels
+fn voxel_data_extract_position(voxel_data: u32) -> vec3<f32> {
+    return vec3<f32>(
+        f32(voxel_data >> 26u),
+        f32(voxel_data >> 20u & 63u),
+        f32(voxel_data >> 14u & 63u)
+    );
+}
+
+// Checks whether the voxel is flagged as part of a chunk border
+fn voxel_data_is_chunk_border(voxel_data: u32) -> bool {
+    return (voxel_data >> 11u & 1u) == 1u;
+}
 
 // Extracts the material index from the encoded voxel data
 fn voxel_data_extract_material_index(voxel_data: u32) -> u32 {
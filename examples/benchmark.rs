@@ -0,0 +1,206 @@
+//! A headless benchmark scene: generates and meshes a fixed square region of chunk columns from a fixed seed, then
+//! reports the time spent in each step, the geometry output and the peak memory allocated.
+//!
+//! The chunks are generated and meshed one after the other on the main thread, with the same terrain generator and
+//! meshing functions as the world systems, so the numbers can be compared across commits. The scene is run several
+//! times and the counts must match between runs.
+//!
+//! `cargo run --release --example benchmark -- [--size <columns>] [--seed <seed>] [--runs <runs>]`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use bevy::{math::IVec3, prelude::Mesh, render::render_resource::PrimitiveTopology};
+use vx_bevy::voxel::{
+    render::{mesh_buffer, MeshBuffers, MeshingOptions},
+    storage::VoxelBuffer,
+    terraingen::TERRAIN_GENERATOR,
+    ChunkShape, Voxel, VoxelScale, WorldHeightLimits, CHUNK_LENGTH,
+};
+
+/// Tracks the bytes currently allocated and their peak since the last reset.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+struct BenchmarkSettings {
+    /// The side of the square region of chunk columns, in chunks.
+    size: i32,
+    seed: u32,
+    runs: usize,
+}
+
+impl BenchmarkSettings {
+    fn from_args() -> Self {
+        let mut settings = Self {
+            size: 8,
+            seed: 0,
+            runs: 2,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| panic!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--size" => settings.size = value().parse().expect("invalid size"),
+                "--seed" => settings.seed = value().parse().expect("invalid seed"),
+                "--runs" => settings.runs = value().parse().expect("invalid number of runs"),
+                _ => panic!("unknown argument {arg}"),
+            }
+        }
+
+        settings
+    }
+}
+
+/// The outputs of a run which must be the same for every run of the same scene.
+#[derive(Debug, PartialEq, Eq)]
+struct SceneCounts {
+    chunks: usize,
+    /// A hash of the voxels of all the generated chunks.
+    voxels_hash: u64,
+    vertices: usize,
+    triangles: usize,
+}
+
+struct SceneReport {
+    counts: SceneCounts,
+    generation_time: Duration,
+    meshing_time: Duration,
+    peak_bytes: usize,
+}
+
+fn run_scene(settings: &BenchmarkSettings) -> SceneReport {
+    let height_limits = WorldHeightLimits::default();
+    let options = MeshingOptions {
+        scale: VoxelScale::default().0,
+        ..Default::default()
+    };
+
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+    PEAK_ALLOCATED.store(baseline, Ordering::Relaxed);
+
+    // the region is centered on the origin, where the player spawns.
+    let half = settings.size / 2;
+    let keys: Vec<_> = (-half..settings.size - half)
+        .flat_map(|z| (-half..settings.size - half).map(move |x| (x, z)))
+        .flat_map(|(x, z)| {
+            (height_limits.lowest_chunk()..height_limits.ceiling)
+                .step_by(CHUNK_LENGTH as usize)
+                .map(move |y| IVec3::new(x, 0, z) * CHUNK_LENGTH as i32 + IVec3::Y * y)
+        })
+        .collect();
+
+    let start = Instant::now();
+    let buffers: Vec<_> = {
+        let generator = TERRAIN_GENERATOR.read().unwrap();
+        keys.iter()
+            .map(|&key| {
+                let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+                generator.generate(key, &mut buffer, &height_limits);
+                buffer
+            })
+            .collect()
+    };
+    let generation_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut mesh_buffers = MeshBuffers::<Voxel, ChunkShape>::new(ChunkShape {});
+    let meshes: Vec<_> = buffers
+        .iter()
+        .map(|buffer| {
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            mesh_buffer(buffer, &mut mesh_buffers, &mut mesh, &options);
+            mesh
+        })
+        .collect();
+    let meshing_time = start.elapsed();
+
+    let mut hasher = DefaultHasher::new();
+    for buffer in &buffers {
+        buffer.encode_rle().hash(&mut hasher);
+    }
+
+    let counts = SceneCounts {
+        chunks: keys.len(),
+        voxels_hash: hasher.finish(),
+        vertices: meshes.iter().map(Mesh::count_vertices).sum(),
+        triangles: meshes
+            .iter()
+            .map(|mesh| mesh.indices().map_or(0, |indices| indices.len() / 3))
+            .sum(),
+    };
+
+    SceneReport {
+        counts,
+        generation_time,
+        meshing_time,
+        peak_bytes: PEAK_ALLOCATED.load(Ordering::Relaxed) - baseline,
+    }
+}
+
+fn main() {
+    let settings = BenchmarkSettings::from_args();
+    TERRAIN_GENERATOR
+        .write()
+        .unwrap()
+        .register_default_biomes()
+        .set_seed(settings.seed);
+
+    println!(
+        "benchmark scene: {size}x{size} chunk columns, seed {seed}, {runs} runs",
+        size = settings.size,
+        seed = settings.seed,
+        runs = settings.runs
+    );
+
+    let mut first_counts = None;
+    for run in 1..=settings.runs {
+        let report = run_scene(&settings);
+        println!(
+            "run {run}: generation {:.1?}, meshing {:.1?}, {} chunks, {} vertices, {} triangles, peak memory {:.1} MiB",
+            report.generation_time,
+            report.meshing_time,
+            report.counts.chunks,
+            report.counts.vertices,
+            report.counts.triangles,
+            report.peak_bytes as f64 / (1024.0 * 1024.0),
+        );
+
+        match &first_counts {
+            None => first_counts = Some(report.counts),
+            Some(counts) => assert_eq!(
+                counts, &report.counts,
+                "the scene output differs between runs"
+            ),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! A minimal voxel world: procedurally generated terrain streamed around a free flying player.
+//!
+//! Click in the window to grab the cursor, move with WASD, go up and down with space and left shift and press escape
+//! to release the cursor.
+
+use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
+use vx_bevy::voxel::{
+    player::{PlayerController, PlayerSpawnSettings},
+    ChunkLoadRadius, ChunkLoadShape, VoxelWorldPlugin,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        // the player controller checks whether egui wants the pointer before grabbing the cursor.
+        .add_plugins(EguiPlugin)
+        .add_plugins(VoxelWorldPlugin)
+        // inserted after the world plugin, which sets up its own default radius.
+        .insert_resource(ChunkLoadRadius {
+            shape: ChunkLoadShape::Cylinder,
+            horizontal: 8,
+            vertical: 4,
+            unload_horizontal: 10,
+            unload_vertical: 5,
+        })
+        .insert_resource(PlayerSpawnSettings {
+            position: Vec3::new(0.0, 160.0, 0.0),
+            pitch: 0.3,
+            on_ground: true,
+            ..Default::default()
+        })
+        .add_systems(Startup, spawn_player)
+        .run();
+}
+
+fn spawn_player(mut commands: Commands) {
+    // the spawn settings take care of placing the player on the terrain.
+    commands.spawn((
+        Camera3dBundle::default(),
+        PlayerController::default(),
+        bevy_atmosphere::plugin::AtmosphereCamera::default(),
+    ));
+}
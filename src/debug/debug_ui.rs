@@ -1,10 +1,12 @@
 use bevy::{
     diagnostic::{DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
     input::{keyboard::KeyboardInput, ButtonState},
+    math::IVec3,
     prelude::{
-        Color, EventReader, IntoSystemConfigs, IntoSystemSetConfigs, KeyCode, Plugin, Res, ResMut,
-        Resource, SystemSet, Update,
+        Color, EventReader, IntoSystemConfigs, IntoSystemSetConfigs, KeyCode, Local, Plugin, Res,
+        ResMut, Resource, SystemSet, Update, World,
     },
+    utils::Duration,
 };
 
 use bevy_egui::{
@@ -13,8 +15,22 @@ use bevy_egui::{
 };
 
 use crate::voxel::{
-    material::VoxelMaterialRegistry, ChunkCommandQueue, ChunkEntities, ChunkLoadRadius,
-    CurrentLocalPlayerChunk, DirtyChunks,
+    diagnostics::VoxelWorldDiagnosticsPlugin,
+    editing::{MetadataPolicy, VoxelEditor},
+    interaction::{VoxelInteractionSettings, VoxelPickMode},
+    material::VoxelMaterialRegistry,
+    player::{CameraProjectionMode, CameraProjectionSettings},
+    render::{count_mesh_output, MeshBuffers, MeshingAlgorithm, MeshingOptions},
+    storage::ChunkMap,
+    terrain::force_load_chunk,
+    terraingen::{
+        DensityTerrainGenerator, FlatWorldGenerator, HeightmapEdge, HeightmapTerrainSettings,
+        TerrainSource,
+    },
+    ChunkCommandQueue, ChunkEntities, ChunkLoadRadius, ChunkLoadShape, ChunkMeshStatsQuery,
+    ChunkMeshingBacklog, ChunkMeshingBudget, ChunkMeshingSettings, ChunkShape, ChunkTintMode,
+    ChunkTintSettings, CurrentLocalPlayerChunk, DirtyChunks, SunShadowSettings, TerrainGenBudget,
+    Voxel, CHUNK_LENGTH,
 };
 
 fn display_debug_stats(mut egui: EguiContexts, diagnostics: Res<DiagnosticsStore>) {
@@ -35,6 +51,28 @@ fn display_debug_stats(mut egui: EguiContexts, diagnostics: Res<DiagnosticsStore
                 .average()
                 .unwrap_or_default()
         ));
+        ui.label(format!(
+            "Chunk data: {:.02} MiB in {} chunks",
+            diagnostics
+                .get(VoxelWorldDiagnosticsPlugin::CHUNK_DATA_SIZE)
+                .and_then(|diagnostic| diagnostic.value())
+                .unwrap_or_default(),
+            diagnostics
+                .get(VoxelWorldDiagnosticsPlugin::LOADED_CHUNK_COUNT)
+                .and_then(|diagnostic| diagnostic.value())
+                .unwrap_or_default()
+        ));
+        ui.label(format!(
+            "Meshing: {:.02} tasks/frame, {:.02} ms/task",
+            diagnostics
+                .get(VoxelWorldDiagnosticsPlugin::MESHING_TASKS_SPAWNED)
+                .and_then(|diagnostic| diagnostic.average())
+                .unwrap_or_default(),
+            diagnostics
+                .get(VoxelWorldDiagnosticsPlugin::MESHING_TASK_TIME)
+                .and_then(|diagnostic| diagnostic.average())
+                .unwrap_or_default()
+        ));
     });
 }
 
@@ -43,8 +81,22 @@ fn display_chunk_stats(
     dirty_chunks: Res<DirtyChunks>,
     player_pos: Res<CurrentLocalPlayerChunk>,
     mut chunk_loading_radius: ResMut<ChunkLoadRadius>,
+    mut meshing_budget: ResMut<ChunkMeshingBudget>,
+    mut meshing_settings: ResMut<ChunkMeshingSettings>,
+    mut tint_settings: ResMut<ChunkTintSettings>,
+    mut interaction_settings: ResMut<VoxelInteractionSettings>,
+    mut shadow_settings: ResMut<SunShadowSettings>,
+    mut projection_settings: ResMut<CameraProjectionSettings>,
+    mut terrain_gen_budget: ResMut<TerrainGenBudget>,
     mut chunk_command_queue: ResMut<ChunkCommandQueue>,
     loaded_chunks: Res<ChunkEntities>,
+    mesh_stats: ChunkMeshStatsQuery,
+    meshing_backlog: Res<ChunkMeshingBacklog>,
+    // grouped to stay within the number of parameters a system can take.
+    (chunks, mut mesh_buffers): (
+        Res<ChunkMap<Voxel, ChunkShape>>,
+        Local<Option<MeshBuffers<Voxel, ChunkShape>>>,
+    ),
 ) {
     egui::Window::new("voxel world stuff").show(egui.ctx_mut(), |ui| {
         ui.heading("Chunks");
@@ -53,11 +105,139 @@ fn display_chunk_stats(
             dirty_chunks.num_dirty()
         ));
         ui.label(format!("Loaded chunk count: {}", loaded_chunks.len()));
+        ui.label(format!(
+            "Chunks waiting for meshing: {}",
+            meshing_backlog.len()
+        ));
         ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Loading shape");
+            ui.radio_value(
+                &mut chunk_loading_radius.shape,
+                ChunkLoadShape::Cylinder,
+                "Cylinder",
+            );
+            ui.radio_value(
+                &mut chunk_loading_radius.shape,
+                ChunkLoadShape::Sphere,
+                "Sphere",
+            );
+            ui.radio_value(
+                &mut chunk_loading_radius.shape,
+                ChunkLoadShape::Cube,
+                "Cube",
+            );
+        });
         ui.label("Horizontal chunk loading radius");
         ui.add(Slider::new(&mut chunk_loading_radius.horizontal, 8..=32));
         ui.label("Vertical chunk loading radius");
         ui.add(Slider::new(&mut chunk_loading_radius.vertical, 2..=10));
+        ui.label("Horizontal chunk unloading radius");
+        let min_horizontal = chunk_loading_radius.horizontal;
+        ui.add(Slider::new(
+            &mut chunk_loading_radius.unload_horizontal,
+            min_horizontal..=40,
+        ));
+        ui.label("Vertical chunk unloading radius");
+        let min_vertical = chunk_loading_radius.vertical;
+        ui.add(Slider::new(
+            &mut chunk_loading_radius.unload_vertical,
+            min_vertical..=12,
+        ));
+        ui.separator();
+        // only touch the settings on change as any change remeshes all the loaded chunks.
+        let mut algorithm = meshing_settings.algorithm;
+        ui.horizontal(|ui| {
+            ui.label("Meshing algorithm");
+            ui.radio_value(&mut algorithm, MeshingAlgorithm::Greedy, "Greedy");
+            ui.radio_value(&mut algorithm, MeshingAlgorithm::PerVoxelCubes, "Per voxel");
+        });
+        if algorithm != meshing_settings.algorithm {
+            meshing_settings.algorithm = algorithm;
+        }
+        let mut border_tint = meshing_settings.border_tint;
+        if ui
+            .checkbox(&mut border_tint, "Tint chunk borders (F6)")
+            .changed()
+        {
+            meshing_settings.border_tint = border_tint;
+        }
+        let mut tint_mode = tint_settings.mode;
+        ui.horizontal(|ui| {
+            ui.label("Chunk tint");
+            ui.radio_value(&mut tint_mode, ChunkTintMode::None, "None");
+            ui.radio_value(&mut tint_mode, ChunkTintMode::ByChunk, "By chunk");
+            ui.radio_value(&mut tint_mode, ChunkTintMode::ByBiome, "By biome");
+        });
+        if tint_mode != tint_settings.mode {
+            tint_settings.mode = tint_mode;
+        }
+        ui.label("Meshing tasks started per frame");
+        ui.add(Slider::new(&mut meshing_budget.meshes_per_frame, 1..=256));
+        ui.label("Meshes applied per frame");
+        ui.add(Slider::new(
+            &mut meshing_budget.applied_meshes_per_frame,
+            1..=256,
+        ));
+        ui.label("Max. concurrent meshing tasks");
+        ui.add(Slider::new(
+            &mut meshing_budget.max_concurrent_tasks,
+            1..=32,
+        ));
+        ui.label("Chunk remesh cooldown (ms)");
+        let mut cooldown_ms = meshing_budget.remesh_cooldown.as_secs_f32() * 1000.0;
+        if ui
+            .add(Slider::new(&mut cooldown_ms, 0.0..=1000.0))
+            .changed()
+        {
+            meshing_budget.remesh_cooldown = Duration::from_secs_f32(cooldown_ms / 1000.0);
+        }
+        ui.label("Meshing budget reserved for the oldest chunks");
+        ui.add(Slider::new(
+            &mut meshing_budget.oldest_first_fraction,
+            0.0..=1.0,
+        ));
+        ui.label("Terrain generation frame time budget (ms)");
+        let mut frame_time_ms = terrain_gen_budget.frame_time.as_secs_f32() * 1000.0;
+        if ui
+            .add(Slider::new(&mut frame_time_ms, 0.5..=16.0))
+            .changed()
+        {
+            terrain_gen_budget.frame_time = Duration::from_secs_f32(frame_time_ms / 1000.0);
+        }
+        ui.separator();
+
+        ui.label("Reach distance (voxels)");
+        ui.add(Slider::new(
+            &mut interaction_settings.reach_distance,
+            1.0..=32.0,
+        ));
+        ui.label("Break time (s)");
+        ui.add(Slider::new(&mut interaction_settings.break_time, 0.0..=3.0));
+        ui.horizontal(|ui| {
+            ui.label("Target");
+            ui.radio_value(
+                &mut interaction_settings.pick_mode,
+                VoxelPickMode::Solid,
+                "Solid voxels",
+            );
+            ui.radio_value(
+                &mut interaction_settings.pick_mode,
+                VoxelPickMode::NonEmpty,
+                "Any voxel",
+            );
+        });
+        let mut shadows = shadow_settings.enabled;
+        if ui.checkbox(&mut shadows, "Sun shadows").changed() {
+            shadow_settings.enabled = shadows;
+        }
+        let mut orthographic = projection_settings.mode == CameraProjectionMode::Orthographic;
+        if ui
+            .checkbox(&mut orthographic, "Orthographic camera (F8)")
+            .changed()
+        {
+            projection_settings.toggle_mode();
+        }
         ui.separator();
 
         if ui.button("Clear loaded chunks").clicked() {
@@ -68,9 +248,93 @@ fn display_chunk_stats(
         ui.heading("Current player position");
         ui.label(format!("Current position : {}", player_pos.world_pos));
         ui.label(format!("Current chunk : {:?}", player_pos.chunk_min));
+
+        if let Some(stats) = mesh_stats.chunk_mesh_stats(player_pos.chunk_min) {
+            ui.label(format!(
+                "Current chunk mesh : {} vertices, {} triangles{}",
+                stats.vertex_count,
+                stats.triangle_count,
+                if stats.needs_meshing {
+                    " (pending)"
+                } else {
+                    ""
+                }
+            ));
+        }
+
+        if let Some(buffer) = chunks.buffer_at(player_pos.chunk_min) {
+            let mesh_buffers = mesh_buffers.get_or_insert_with(|| MeshBuffers::new(ChunkShape {}));
+            let counts = count_mesh_output(buffer, mesh_buffers, &MeshingOptions::default());
+            ui.label(format!(
+                "Current chunk meshing estimate : {} quads, {} vertices, {} indices",
+                counts.quads, counts.vertices, counts.indices
+            ));
+        }
     });
 }
 
+fn display_terrain_source(
+    mut egui: EguiContexts,
+    mut ui_state: ResMut<DebugUIState>,
+    mut source: ResMut<TerrainSource>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+) {
+    egui::Window::new("terrain source").show(egui.ctx_mut(), |ui| {
+        ui.label(format!(
+            "Current source : {}",
+            match &*source {
+                TerrainSource::Noise => "noise",
+                TerrainSource::Heightmap(_) => "heightmap",
+                TerrainSource::Flat(_) => "flat",
+                TerrainSource::Density(_) => "density",
+            }
+        ));
+        ui.separator();
+
+        ui.label("Heightmap image asset path");
+        ui.text_edit_singleline(&mut ui_state.heightmap_path);
+        ui.horizontal(|ui| {
+            ui.label("Beyond the image");
+            ui.radio_value(&mut ui_state.heightmap_edge, HeightmapEdge::Tile, "Tile");
+            ui.radio_value(&mut ui_state.heightmap_edge, HeightmapEdge::Clamp, "Clamp");
+        });
+
+        // any change of the source reloads all the chunks.
+        ui.horizontal(|ui| {
+            if ui.button("Generate from the heightmap").clicked() {
+                *source = TerrainSource::Heightmap(HeightmapTerrainSettings {
+                    heightmap: ui_state.heightmap_path.clone(),
+                    surface: None,
+                    base_height: 0,
+                    max_height: 128,
+                    edge: ui_state.heightmap_edge,
+                });
+            }
+            if ui.button("Generate from noise").clicked() {
+                *source = TerrainSource::Noise;
+            }
+            if ui.button("Generate a flat world").clicked() {
+                *source = TerrainSource::Flat(FlatWorldGenerator::default());
+            }
+            if ui.button("Generate from a density field").clicked() {
+                *source = TerrainSource::Density(DensityTerrainGenerator::default());
+            }
+        });
+        ui.separator();
+
+        if ui.button("Force load the current chunk").clicked() {
+            ui_state.force_load_chunk = Some(player_pos.chunk_min);
+        }
+    });
+}
+
+/// Generates the chunk requested from the debug UI right away, stalling the frame.
+fn force_load_requested_chunk(world: &mut World) {
+    if let Some(chunk_key) = world.resource_mut::<DebugUIState>().force_load_chunk.take() {
+        force_load_chunk(world, chunk_key);
+    }
+}
+
 fn display_debug_ui_criteria(ui_state: Res<DebugUIState>) -> bool {
     ui_state.display_debug_info
 }
@@ -82,9 +346,17 @@ fn display_mat_debug_ui_criteria(ui_state: Res<DebugUIState>) -> bool {
 fn toggle_debug_ui_displays(
     mut inputs: EventReader<KeyboardInput>,
     mut ui_state: ResMut<DebugUIState>,
+    mut meshing_settings: ResMut<ChunkMeshingSettings>,
+    mut projection_settings: ResMut<CameraProjectionSettings>,
 ) {
     for input in inputs.iter() {
         match input.key_code {
+            Some(key_code) if key_code == KeyCode::F8 && input.state == ButtonState::Pressed => {
+                projection_settings.toggle_mode();
+            }
+            Some(key_code) if key_code == KeyCode::F6 && input.state == ButtonState::Pressed => {
+                meshing_settings.border_tint = !meshing_settings.border_tint;
+            }
             Some(key_code) if key_code == KeyCode::F3 && input.state == ButtonState::Pressed => {
                 ui_state.display_debug_info = !ui_state.display_debug_info;
             }
@@ -100,6 +372,8 @@ fn display_material_editor(
     mut egui: EguiContexts,
     mut ui_state: ResMut<DebugUIState>,
     mut materials: ResMut<VoxelMaterialRegistry>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    mut editor: VoxelEditor,
 ) {
     egui::Window::new("material editor").show(egui.ctx_mut(), |ui| {
         ui.heading("Select material");
@@ -166,6 +440,45 @@ fn display_material_editor(
             egui::color_picker::Alpha::Opaque,
         );
         selected_mat.emissive = Color::from(editable_emissive.to_array());
+
+        ui.heading("Replace material");
+        egui::containers::ComboBox::from_label("Replacement")
+            .selected_text(
+                materials
+                    .get_by_id(ui_state.replacement_mat)
+                    .unwrap()
+                    .name
+                    .to_string(),
+            )
+            .show_ui(ui, |content| {
+                materials
+                    .iter_mats()
+                    .enumerate()
+                    .for_each(|(mat_index, mat)| {
+                        content.selectable_value(
+                            &mut ui_state.replacement_mat,
+                            mat_index as u8,
+                            mat.name,
+                        );
+                    })
+            });
+        ui.checkbox(&mut ui_state.keep_metadata, "Keep metadata");
+
+        if ui.button("Replace around the player").clicked() {
+            // the chunks surrounding the one the player is in.
+            let chunk_len = CHUNK_LENGTH as i32;
+            editor.replace_in_region(
+                player_pos.chunk_min - IVec3::splat(chunk_len),
+                player_pos.chunk_min + IVec3::splat(2 * chunk_len - 1),
+                Voxel::new(ui_state.selected_mat),
+                Voxel::new(ui_state.replacement_mat),
+                if ui_state.keep_metadata {
+                    MetadataPolicy::Preserve
+                } else {
+                    MetadataPolicy::Reset
+                },
+            );
+        }
     });
 }
 
@@ -194,10 +507,18 @@ impl Plugin for DebugUIPlugins {
             )
             .add_systems(
                 Update,
-                (display_debug_stats, display_chunk_stats)
+                (
+                    display_debug_stats,
+                    display_chunk_stats,
+                    display_terrain_source,
+                )
                     .in_set(DebugUISet::Display)
                     .distributive_run_if(display_debug_ui_criteria),
             )
+            .add_systems(
+                Update,
+                force_load_requested_chunk.after(DebugUISet::Display),
+            )
             .configure_sets(
                 Update,
                 (DebugUISet::Toggle, DebugUISet::Display)
@@ -215,4 +536,9 @@ struct DebugUIState {
 
     // DD
     pub selected_mat: u8,
+    replacement_mat: u8,
+    keep_metadata: bool,
+    heightmap_path: String,
+    heightmap_edge: HeightmapEdge,
+    force_load_chunk: Option<IVec3>,
 }
@@ -0,0 +1,10 @@
+#![allow(
+    clippy::type_complexity,
+    clippy::manual_clamp,
+    clippy::module_inception,
+    clippy::too_many_arguments
+)]
+
+pub mod debug;
+pub mod platform;
+pub mod voxel;
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+
+/// Settings for the presentation of the frames to the primary window.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlatformSettings {
+    /// Whether the frames wait for the display refresh (vsync), see [`PresentMode`].
+    pub present_mode: PresentMode,
+    /// The maximum number of frames rendered per second, on top of the limit set by the present mode.
+    /// Capping the frame rate saves power and keeps the frame time steady while tuning the per-frame budgets.
+    pub frame_rate_limit: Option<f64>,
+}
+
+impl Default for PlatformSettings {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::Fifo,
+            frame_rate_limit: None,
+        }
+    }
+}
+
+impl PlatformSettings {
+    /// Returns the shortest time a frame may take under the frame rate limit, if any.
+    pub fn min_frame_time(&self) -> Option<Duration> {
+        self.frame_rate_limit
+            .filter(|limit| *limit > 0.0)
+            .map(|limit| Duration::from_secs_f64(limit.recip()))
+    }
+
+    /// Applies the window related settings to a window, e.g. before building it.
+    pub fn apply_to_window(&self, window: &mut Window) {
+        window.present_mode = self.present_mode;
+    }
+}
+
+/// Applies the platform settings to the primary window whenever they change.
+fn apply_platform_settings(
+    settings: Res<PlatformSettings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in &mut windows {
+        if window.present_mode != settings.present_mode {
+            settings.apply_to_window(&mut window);
+        }
+    }
+}
+
+/// Sleeps at the end of the frames which took less time than allowed by the frame rate limit.
+fn limit_frame_rate(settings: Res<PlatformSettings>, mut frame_end: Local<Option<Instant>>) {
+    if let (Some(min_frame_time), Some(last_frame_end)) = (settings.min_frame_time(), *frame_end) {
+        let elapsed = last_frame_end.elapsed();
+        if elapsed < min_frame_time {
+            std::thread::sleep(min_frame_time - elapsed);
+        }
+    }
+
+    *frame_end = Some(Instant::now());
+}
+
+/// Handles the window presentation and frame rate limit, configured through the [`PlatformSettings`] resource.
+pub struct PlatformPlugin;
+
+impl Plugin for PlatformPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlatformSettings>()
+            .add_systems(PreStartup, apply_platform_settings)
+            .add_systems(
+                Update,
+                apply_platform_settings.run_if(resource_changed::<PlatformSettings>()),
+            )
+            .add_systems(Last, limit_frame_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_limit(frame_rate_limit: Option<f64>) -> PlatformSettings {
+        PlatformSettings {
+            frame_rate_limit,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frame_rate_limits_give_the_minimum_frame_time() {
+        assert_eq!(with_limit(None).min_frame_time(), None);
+        assert_eq!(with_limit(Some(0.0)).min_frame_time(), None);
+        assert_eq!(with_limit(Some(-30.0)).min_frame_time(), None);
+
+        let frame_time = with_limit(Some(60.0)).min_frame_time().unwrap();
+        assert!((frame_time.as_secs_f64() - 1.0 / 60.0).abs() < 1e-9);
+    }
+}
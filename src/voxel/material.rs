@@ -26,7 +26,7 @@ pub trait VoxelMaterial {
     const ID: u8;
 
     fn into_voxel() -> Voxel {
-        Voxel(Self::ID)
+        Voxel::new(Self::ID)
     }
 }
 
@@ -48,6 +48,7 @@ bitflags! {
         const SOLID = 0;
         const LIQUID = 1 << 1;
         const UNBREAKABLE = 1 << 2;
+        const NO_MERGE = 1 << 3;
     }
 }
 
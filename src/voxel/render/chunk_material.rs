@@ -1,4 +1,7 @@
-use crate::voxel::material::VoxelMaterialRegistry;
+// the `ShaderType` derive generates size check functions which are never called on the CPU side.
+#![allow(dead_code)]
+
+use crate::voxel::{material::VoxelMaterialRegistry, VoxelScale, CHUNK_LENGTH};
 use bevy::{
     prelude::*,
     reflect::{TypePath, TypeUuid},
@@ -31,17 +34,26 @@ pub struct GpuVoxelMaterial {
 #[derive(AsBindGroup, ShaderType, Clone, TypePath, TypeUuid)]
 #[uuid = "1e31e29e-73d8-419c-8293-876ae81d2636"]
 pub struct GpuTerrainUniforms {
+    /// The distance from the camera at which the terrain is fully fogged, in world units.
     #[uniform(0)]
-    pub render_distance: u32,
+    pub fog_distance: f32,
     #[uniform(1)]
     pub materials: [GpuVoxelMaterial; 256],
+    /// The color multiplying the base color of every voxel, white leaving them untouched.
+    #[uniform(2)]
+    pub tint: Color,
+    /// The size of a voxel in world units, scaling the vertex positions of meshes with packed positions.
+    #[uniform(3)]
+    pub voxel_scale: f32,
 }
 
 impl Default for GpuTerrainUniforms {
     fn default() -> Self {
         Self {
-            render_distance: 16,
+            fog_distance: 16.0 * CHUNK_LENGTH as f32,
             materials: [default(); 256],
+            tint: Color::WHITE,
+            voxel_scale: 1.0,
         }
     }
 }
@@ -55,16 +67,30 @@ impl Material for GpuTerrainUniforms {
         "shaders/terrain_pipeline.wgsl".into()
     }
 
+    // the default prepass shader reads the float position attribute, which meshes with packed positions lack.
+    fn prepass_vertex_shader() -> bevy::render::render_resource::ShaderRef {
+        "shaders/terrain_prepass.wgsl".into()
+    }
+
     fn specialize(
         _pipeline: &bevy::pbr::MaterialPipeline<Self>,
         descriptor: &mut bevy::render::render_resource::RenderPipelineDescriptor,
         layout: &bevy::render::mesh::MeshVertexBufferLayout,
         _key: bevy::pbr::MaterialPipelineKey<Self>,
     ) -> Result<(), bevy::render::render_resource::SpecializedMeshPipelineError> {
-        let vertex_layout = layout.get_layout(&[
-            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
-            VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(1),
-        ])?;
+        // meshes with packed positions only have the voxel data attribute, see `MeshingOptions::packed_positions`.
+        let vertex_layout = if layout.contains(Mesh::ATTRIBUTE_POSITION) {
+            layout.get_layout(&[
+                Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+                VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(1),
+            ])?
+        } else {
+            descriptor
+                .vertex
+                .shader_defs
+                .push("PACKED_POSITIONS".into());
+            layout.get_layout(&[VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(1)])?
+        };
         descriptor.vertex.buffers = vec![vertex_layout];
         Ok(())
     }
@@ -75,16 +101,26 @@ fn update_chunk_material_singleton(
     mut materials: ResMut<Assets<GpuTerrainUniforms>>,
     chunk_material: ResMut<ChunkMaterialSingleton>,
     voxel_materials: Res<VoxelMaterialRegistry>,
+    scale: Res<VoxelScale>,
     mut chunk_entities: Query<(Entity, &mut Handle<GpuTerrainUniforms>)>,
 ) {
     if chunk_material.is_changed() {
+        // keep the fog distance maintained by the player camera systems.
+        let fog_distance = materials
+            .get(&chunk_material.0)
+            .map_or(GpuTerrainUniforms::default().fog_distance, |uniforms| {
+                uniforms.fog_distance
+            });
+
         let mut gpu_mats = GpuTerrainUniforms {
             materials: [GpuVoxelMaterial {
                 base_color: Color::WHITE,
                 flags: 0,
                 ..Default::default()
             }; 256],
-            render_distance: 32,
+            fog_distance,
+            tint: Color::WHITE,
+            voxel_scale: scale.0,
         };
 
         voxel_materials
@@ -108,6 +144,16 @@ fn update_chunk_material_singleton(
     }
 }
 
+fn update_chunk_material_voxel_scale(
+    mut materials: ResMut<Assets<GpuTerrainUniforms>>,
+    chunk_material: Res<ChunkMaterialSingleton>,
+    scale: Res<VoxelScale>,
+) {
+    if let Some(uniforms) = materials.get_mut(&chunk_material.0) {
+        uniforms.voxel_scale = scale.0;
+    }
+}
+
 #[derive(Resource, Deref, DerefMut)]
 pub struct ChunkMaterialSingleton(Handle<GpuTerrainUniforms>);
 
@@ -131,8 +177,11 @@ impl Plugin for ChunkMaterialPlugin {
             .init_resource::<ChunkMaterialSingleton>()
             .add_systems(
                 Update,
-                update_chunk_material_singleton
-                    .run_if(resource_changed::<VoxelMaterialRegistry>())
+                (
+                    update_chunk_material_singleton
+                        .run_if(resource_changed::<VoxelMaterialRegistry>()),
+                    update_chunk_material_voxel_scale.run_if(resource_changed::<VoxelScale>()),
+                )
                     .in_set(ChunkMaterialSet),
             );
     }
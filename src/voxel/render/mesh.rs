@@ -1,12 +1,17 @@
 use std::marker::PhantomData;
 
 use crate::voxel::{storage::VoxelBuffer, MaterialVoxel};
+use bevy::tasks::TaskPool;
 use bevy::{
+    math::{IVec3, UVec3, Vec2, Vec3},
     prelude::Mesh,
     render::mesh::{Indices, VertexAttributeValues},
 };
-use block_mesh::{greedy_quads, GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG};
-use ndcopy::copy3;
+use block_mesh::{
+    greedy_quads_with_merge_strategy, visible_block_faces, FaceStrides, GreedyQuadsBuffer,
+    MergeStrategy, MergeVoxel, OrientedBlockFace, UnitQuadBuffer, UnorientedQuad,
+    Voxel as MeshableVoxel, VoxelMerger, VoxelVisibility, RIGHT_HANDED_Y_UP_CONFIG,
+};
 use ndshape::{RuntimeShape, Shape};
 
 use super::VoxelTerrainMesh;
@@ -17,8 +22,15 @@ where
     T: Copy + Default + MaterialVoxel,
 {
     // A padded buffer to run greedy meshing algorithm on
-    scratch_buffer: VoxelBuffer<T, RuntimeShape<u32, 3>>,
+    scratch_buffer: VoxelBuffer<MeshVoxel<T>, RuntimeShape<u32, 3>>,
     greedy_buffer: GreedyQuadsBuffer,
+    // Buffers for meshing slabs in parallel, laid out as [axis * num_slabs + slab].
+    slab_buffers: Vec<GreedyQuadsBuffer>,
+    unit_buffer: UnitQuadBuffer,
+    // The unit quads of the per voxel algorithm, converted to the greedy quads layout.
+    unit_quads: [Vec<UnorientedQuad>; 6],
+    // The quads of each face direction split to the maximum quad size and without the culled ones.
+    filtered_quads: [Vec<UnorientedQuad>; 6],
     _phantom: PhantomData<S>,
 }
 
@@ -31,90 +43,1787 @@ where
 
         Self {
             greedy_buffer: GreedyQuadsBuffer::new(padded_shape.size() as usize),
-            scratch_buffer: VoxelBuffer::<T, RuntimeShape<u32, 3>>::new_empty(padded_shape),
+            scratch_buffer: VoxelBuffer::<MeshVoxel<T>, RuntimeShape<u32, 3>>::new_empty(
+                padded_shape,
+            ),
+            slab_buffers: Vec::new(),
+            unit_buffer: UnitQuadBuffer::new(),
+            unit_quads: Default::default(),
+            filtered_quads: Default::default(),
             _phantom: Default::default(),
         }
     }
+
+    /// Returns the number of bytes allocated for the output quads, which grows with the most complex chunk meshed so far.
+    pub fn retained_bytes(&self) -> usize {
+        fn quads_bytes<Q>(groups: &[Vec<Q>]) -> usize {
+            groups.iter().map(Vec::capacity).sum::<usize>() * std::mem::size_of::<Q>()
+        }
+
+        quads_bytes(&self.greedy_buffer.quads.groups)
+            + self
+                .slab_buffers
+                .iter()
+                .map(|buffer| quads_bytes(&buffer.quads.groups))
+                .sum::<usize>()
+            + quads_bytes(&self.unit_buffer.groups)
+            + quads_bytes(&self.unit_quads)
+            + quads_bytes(&self.filtered_quads)
+    }
+
+    /// Releases the quad buffers if they retain more than `max_bytes`, they are reallocated on demand by the next meshing.
+    pub fn shrink_to(&mut self, max_bytes: usize) {
+        if self.retained_bytes() <= max_bytes {
+            return;
+        }
+
+        self.greedy_buffer.quads = Default::default();
+        self.slab_buffers = Vec::new();
+        self.unit_buffer = UnitQuadBuffer::new();
+        self.unit_quads = Default::default();
+        self.filtered_quads = Default::default();
+    }
+}
+
+/// A voxel copied into the meshing scratch buffer along with its merging rule.
+#[derive(Clone, Copy, Default)]
+struct MeshVoxel<T> {
+    voxel: T,
+    mergeable: bool,
+    // the metadata bits relevant to merging, see [`MeshingOptions::merge_metadata_mask`].
+    metadata: u8,
+    // only set when tinting chunk borders, so the border faces never merge with the inner ones.
+    border: bool,
+}
+
+impl<T: MeshableVoxel> MeshableVoxel for MeshVoxel<T> {
+    #[inline]
+    fn get_visibility(&self) -> VoxelVisibility {
+        self.voxel.get_visibility()
+    }
+}
+
+impl<T: MergeVoxel> MergeVoxel for MeshVoxel<T> {
+    type MergeValue = (T::MergeValue, u8, bool);
+
+    #[inline]
+    fn merge_value(&self) -> Self::MergeValue {
+        (self.voxel.merge_value(), self.metadata, self.border)
+    }
+}
+
+/// Set in the vertex data of the faces of the outermost voxel layer of a chunk when [`MeshingOptions::border_tint`] is on.
+const BORDER_FLAG: u32 = 1 << 11;
+
+/// The bit offsets of the vertex coordinates packed in the vertex data with [`MeshingOptions::packed_positions`].
+const PACKED_POSITION_SHIFTS: [u32; 3] = [26, 20, 14];
+/// The largest coordinate, in voxels, which can be packed in the vertex data.
+const PACKED_POSITION_MAX: u32 = 63;
+/// The bits of the vertex data holding the packed position.
+const PACKED_POSITION_MASK: u32 = !((1 << PACKED_POSITION_SHIFTS[2]) - 1);
+
+/// Checks whether the voxel at the specified local position lies on the outer layer of a buffer.
+#[inline]
+fn is_border_voxel(pos: [u32; 3], size: [u32; 3]) -> bool {
+    pos.iter()
+        .zip(size.iter())
+        .any(|(&pos, &len)| pos == 0 || pos + 1 == len)
+}
+
+/// A merge strategy emitting a quad per face for the voxels flagged as non mergeable.
+/// Other voxels are greedily merged with those of an equal merge value.
+struct MaterialMerger<T>(PhantomData<T>);
+
+impl<T: MergeVoxel> MergeStrategy for MaterialMerger<T> {
+    type Voxel = MeshVoxel<T>;
+
+    unsafe fn find_quad(
+        min_index: u32,
+        max_width: u32,
+        max_height: u32,
+        face_strides: &FaceStrides,
+        voxels: &[Self::Voxel],
+        visited: &[bool],
+    ) -> (u32, u32) {
+        if !voxels.get_unchecked(min_index as usize).mergeable {
+            return (1, 1);
+        }
+
+        // a mergeable voxel never has the same merge value as a non mergeable one as the rule depends on the material.
+        VoxelMerger::<MeshVoxel<T>>::find_quad(
+            min_index,
+            max_width,
+            max_height,
+            face_strides,
+            voxels,
+            visited,
+        )
+    }
+}
+
+/// A compact set of voxel material ids.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaterialIdSet([u64; 4]);
+
+impl MaterialIdSet {
+    #[inline]
+    pub fn insert(&mut self, id: u8) {
+        self.0[id as usize / 64] |= 1 << (id % 64);
+    }
+
+    #[inline]
+    pub const fn contains(&self, id: u8) -> bool {
+        self.0[id as usize / 64] & (1 << (id % 64)) != 0
+    }
+}
+
+/// The algorithm used to turn voxels into quads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshingAlgorithm {
+    /// Merges the adjacent visible faces of a same material into bigger quads.
+    #[default]
+    Greedy,
+    /// Emits a quad for every visible voxel face.
+    PerVoxelCubes,
+}
+
+/// The winding order of the triangles of the outward facing voxel faces, looking at them from the outside.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaceWinding {
+    /// The winding expected by bevy's default pipelines, including the terrain material.
+    #[default]
+    CounterClockwise,
+    /// For custom pipelines using clockwise front faces.
+    Clockwise,
+}
+
+/// Options controlling the output of [`mesh_buffer`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshingOptions {
+    pub algorithm: MeshingAlgorithm,
+    /// The size of a voxel in mesh space.
+    pub scale: f32,
+    /// Whether to emit texture coordinates and tangents for normal-mapped materials.
+    pub tangents: bool,
+    /// Chunks with a side at least this long are split into slabs meshed in parallel, when meshed with a task pool
+    /// through [`mesh_buffer_cancellable`]. The other meshing functions always mesh in a single pass.
+    pub parallel_threshold: Option<u32>,
+    /// The number of slabs along each axis when meshing in parallel.
+    pub parallel_slabs: u32,
+    /// Materials whose faces are never merged into bigger quads.
+    pub unmerged_materials: MaterialIdSet,
+    /// The voxel metadata bits which must match for faces of a same material to be merged, e.g. orientation bits.
+    /// All bits are considered by default, clear the ones which don't change how a face looks to merge more.
+    pub merge_metadata_mask: u8,
+    /// Flag the faces of the outermost voxel layer so the terrain shader tints them, to debug chunk seams.
+    pub border_tint: bool,
+    /// The winding of the emitted triangles, the terrain material culls back faces assuming the default one.
+    pub winding: FaceWinding,
+    /// The maximum length in voxels of either side of a merged quad, longer quads are split into tiles.
+    /// Big quads interpolate their vertex attributes and tile their texture coordinates over large areas.
+    pub max_quad_size: Option<u32>,
+    /// Skip the downward faces of the bottom voxel layer, for chunks at the world floor which nothing is below.
+    pub cull_bottom_layer: bool,
+    /// Pack the vertex positions into the voxel data and leave out the position and normal attributes, cutting the
+    /// vertex size from 28 to 4 bytes. Only the terrain material can render such meshes, and only buffers whose padded
+    /// size fits in the 6 bits of each coordinate are packed, bigger ones keep the float attributes.
+    pub packed_positions: bool,
+}
+
+impl Default for MeshingOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: MeshingAlgorithm::default(),
+            scale: 1.0,
+            tangents: false,
+            parallel_threshold: None,
+            parallel_slabs: 2,
+            unmerged_materials: MaterialIdSet::default(),
+            merge_metadata_mask: u8::MAX,
+            border_tint: false,
+            winding: FaceWinding::default(),
+            max_quad_size: None,
+            cull_bottom_layer: false,
+            packed_positions: false,
+        }
+    }
+}
+
+/// Runs greedy meshing over slabs of the padded voxel buffer in parallel.
+///
+/// Greedy quads never extend across slices along their face normal, so each face direction is meshed from slabs split
+/// along its own normal axis. Concatenating the slabs in order gives back the exact quads of a single pass.
+fn greedy_quads_parallel<T>(
+    voxels: &VoxelBuffer<MeshVoxel<T>, RuntimeShape<u32, 3>>,
+    slab_buffers: &mut Vec<GreedyQuadsBuffer>,
+    num_slabs: u32,
+    task_pool: &TaskPool,
+) where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+{
+    let padded_shape = voxels.shape().as_array();
+    slab_buffers.resize_with(3 * num_slabs as usize, || {
+        GreedyQuadsBuffer::new(voxels.slice().len())
+    });
+
+    task_pool.scope(|scope| {
+        for (index, slab_buffer) in slab_buffers.iter_mut().enumerate() {
+            let axis = index / num_slabs as usize;
+            let slab = index as u32 % num_slabs;
+
+            // the interior (non padding) voxels along the axis, split into slabs sharing their padding layers.
+            let interior_len = padded_shape[axis] - 2;
+            let slab_min = 1 + slab * interior_len / num_slabs;
+            let slab_max = 1 + (slab + 1) * interior_len / num_slabs;
+
+            let mut min = [0; 3];
+            let mut max = padded_shape.map(|x| x - 1);
+            min[axis] = slab_min - 1;
+            max[axis] = slab_max;
+
+            scope.spawn(async move {
+                greedy_quads_with_merge_strategy::<_, _, MaterialMerger<T>>(
+                    voxels.slice(),
+                    voxels.shape(),
+                    min,
+                    max,
+                    &RIGHT_HANDED_Y_UP_CONFIG.faces,
+                    slab_buffer,
+                );
+            });
+        }
+    });
+}
+
+/// Computes the tangents of a quad from its corner positions and texture coordinates.
+fn quad_mesh_tangents(
+    positions: &[[f32; 3]; 4],
+    uvs: &[[f32; 2]; 4],
+    normal: [f32; 3],
+) -> [[f32; 4]; 4] {
+    let [p0, p1, p2, _] = positions.map(Vec3::from);
+    let [uv0, uv1, uv2, _] = uvs.map(Vec2::from);
+
+    let (edge1, edge2) = (p1 - p0, p2 - p0);
+    let (duv1, duv2) = (uv1 - uv0, uv2 - uv0);
+    let r = 1.0 / duv1.perp_dot(duv2);
+
+    let tangent = ((edge1 * duv2.y - edge2 * duv1.y) * r).normalize();
+    let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+    let handedness = Vec3::from(normal).cross(tangent).dot(bitangent).signum();
+
+    [tangent.extend(handedness).to_array(); 4]
+}
+
+// Runs face culling and greedy meshing on the voxel data, returning the quads of each face direction.
+// Buffers are only split into slabs meshed in parallel when a task pool to run them on is given.
+fn greedy_mesh_quads<'a, T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    mesh_buffers: &'a mut MeshBuffers<T, S>,
+    options: &MeshingOptions,
+    task_pool: Option<&TaskPool>,
+) -> [Vec<&'a [UnorientedQuad]>; 6]
+where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    let dst_shape = mesh_buffers.scratch_buffer.shape().clone();
+    let [size_x, size_y, size_z] = buffer.shape().as_array();
+
+    // copy the voxels into the padded scratch buffer along with their merging rule.
+    for z in 0..size_z {
+        for y in 0..size_y {
+            for x in 0..size_x {
+                let voxel = buffer.slice()[buffer.shape().linearize([x, y, z]) as usize];
+                mesh_buffers.scratch_buffer.slice_mut()
+                    [dst_shape.linearize([x + 1, y + 1, z + 1]) as usize] = MeshVoxel {
+                    voxel,
+                    mergeable: !options.unmerged_materials.contains(voxel.as_mat_id()),
+                    metadata: voxel.metadata() & options.merge_metadata_mask,
+                    border: options.border_tint
+                        && is_border_voxel([x, y, z], [size_x, size_y, size_z]),
+                };
+            }
+        }
+    }
+
+    let parallel_pool = task_pool.filter(|_| {
+        options.parallel_threshold.is_some_and(|threshold| {
+            buffer
+                .shape()
+                .as_array()
+                .iter()
+                .any(|&len| len >= threshold)
+        }) && options.parallel_slabs > 1
+    });
+
+    // the quads of each face direction, possibly split across several slabs.
+    let mut face_quads: [Vec<&[UnorientedQuad]>; 6] = Default::default();
+
+    if options.algorithm == MeshingAlgorithm::PerVoxelCubes {
+        // unlike the greedy meshing, this appends to the output buffer.
+        mesh_buffers.unit_buffer.reset();
+        visible_block_faces(
+            mesh_buffers.scratch_buffer.slice(),
+            mesh_buffers.scratch_buffer.shape(),
+            [0; 3],
+            mesh_buffers
+                .scratch_buffer
+                .shape()
+                .as_array()
+                .map(|axis| axis - 1),
+            &RIGHT_HANDED_Y_UP_CONFIG.faces,
+            &mut mesh_buffers.unit_buffer,
+        );
+
+        for ((quads, unit_quads), group) in face_quads
+            .iter_mut()
+            .zip(mesh_buffers.unit_quads.iter_mut())
+            .zip(mesh_buffers.unit_buffer.groups.iter())
+        {
+            unit_quads.clear();
+            unit_quads.extend(group.iter().copied().map(UnorientedQuad::from));
+            quads.push(unit_quads.as_slice());
+        }
+    } else if let Some(task_pool) = parallel_pool {
+        let num_slabs = options.parallel_slabs;
+        greedy_quads_parallel(
+            &mesh_buffers.scratch_buffer,
+            &mut mesh_buffers.slab_buffers,
+            num_slabs,
+            task_pool,
+        );
+
+        for (face_index, (quads, face)) in face_quads
+            .iter_mut()
+            .zip(RIGHT_HANDED_Y_UP_CONFIG.faces.iter())
+            .enumerate()
+        {
+            let normal = face.signed_normal().abs();
+            let axis = if normal.x != 0 {
+                0
+            } else if normal.y != 0 {
+                1
+            } else {
+                2
+            };
+
+            quads.extend(
+                mesh_buffers.slab_buffers[axis * num_slabs as usize..][..num_slabs as usize]
+                    .iter()
+                    .map(|slab| slab.quads.groups[face_index].as_slice()),
+            );
+        }
+    } else {
+        greedy_quads_with_merge_strategy::<_, _, MaterialMerger<T>>(
+            mesh_buffers.scratch_buffer.slice(),
+            mesh_buffers.scratch_buffer.shape(),
+            [0; 3],
+            mesh_buffers
+                .scratch_buffer
+                .shape()
+                .as_array()
+                .map(|axis| axis - 1),
+            &RIGHT_HANDED_Y_UP_CONFIG.faces,
+            &mut mesh_buffers.greedy_buffer,
+        );
+
+        for (quads, group) in face_quads
+            .iter_mut()
+            .zip(mesh_buffers.greedy_buffer.quads.groups.iter())
+        {
+            quads.push(group.as_slice());
+        }
+    }
+
+    let max_size = options
+        .max_quad_size
+        .map_or(u32::MAX, |max_size| max_size.max(1));
+    for ((quads, filtered), face) in face_quads
+        .iter_mut()
+        .zip(mesh_buffers.filtered_quads.iter_mut())
+        .zip(RIGHT_HANDED_Y_UP_CONFIG.faces.iter())
+    {
+        // the quads are positioned in the padded buffer, so the bottom layer lies at a height of one.
+        let cull_bottom = options.cull_bottom_layer && face.signed_normal().y < 0;
+        let kept = |quad: &&UnorientedQuad| !cull_bottom || quad.minimum[1] != 1;
+
+        let needs_filtering = quads
+            .iter()
+            .flat_map(|quads| quads.iter())
+            .any(|quad| quad.width > max_size || quad.height > max_size || !kept(&quad));
+        if needs_filtering {
+            filtered.clear();
+            split_quads(
+                face,
+                quads.iter().flat_map(|quads| quads.iter()).filter(kept),
+                max_size,
+                filtered,
+            );
+            *quads = vec![filtered.as_slice()];
+        }
+    }
+
+    face_quads
+}
+
+// Splits the quads with a side longer than `max_size` into tiles covering the same faces.
+fn split_quads<'a>(
+    face: &OrientedBlockFace,
+    quads: impl Iterator<Item = &'a UnorientedQuad>,
+    max_size: u32,
+    output: &mut Vec<UnorientedQuad>,
+) {
+    // the axes along the quad width and height.
+    let unit = face.quad_corners(&UnorientedQuad {
+        minimum: [0; 3],
+        width: 1,
+        height: 1,
+    });
+    let (u, v) = (unit[1] - unit[0], unit[2] - unit[0]);
+
+    for quad in quads {
+        for v_start in (0..quad.height).step_by(max_size as usize) {
+            for u_start in (0..quad.width).step_by(max_size as usize) {
+                let minimum = UVec3::from(quad.minimum)
+                    + UVec3::from(u.to_array()) * u_start
+                    + UVec3::from(v.to_array()) * v_start;
+                output.push(UnorientedQuad {
+                    minimum: minimum.to_array(),
+                    width: (quad.width - u_start).min(max_size),
+                    height: (quad.height - v_start).min(max_size),
+                });
+            }
+        }
+    }
+}
+
+/// The amount of geometry [`mesh_buffer`] outputs for a voxel buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshOutputCounts {
+    pub quads: usize,
+    pub vertices: usize,
+    pub indices: usize,
+}
+
+/// Counts the geometry [`mesh_buffer`] would output for the voxel data buffer, without building a mesh.
+pub fn count_mesh_output<T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    options: &MeshingOptions,
+) -> MeshOutputCounts
+where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    let face_quads = greedy_mesh_quads(buffer, mesh_buffers, options, None);
+    let quads: usize = face_quads.iter().flatten().map(|quads| quads.len()).sum();
+
+    MeshOutputCounts {
+        quads,
+        vertices: quads * 4,
+        indices: quads * 6,
+    }
+}
+
+/// The vertex and index buffers output by [`mesh_buffer_raw`], for feeding the geometry to custom renderers.
+/// The buffers keep their allocations between uses, so they can be reused from one chunk to the next.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Only filled when [`MeshingOptions::tangents`] is set.
+    pub uvs: Vec<[f32; 2]>,
+    /// Only filled when [`MeshingOptions::tangents`] is set.
+    pub tangents: Vec<[f32; 4]>,
+    /// The packed voxel data of each vertex, see [`VoxelTerrainMesh::ATTRIBUTE_DATA`].
+    pub data: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+impl RawMesh {
+    /// Copies the buffers out of a bevy mesh built by [`RawMesh::insert_into`] with the specified options, `None` if the
+    /// mesh lacks some of the expected attributes.
+    pub fn from_mesh(mesh: &Mesh, options: &MeshingOptions) -> Option<Self> {
+        let float32x3 = |attribute| match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x3(values)) => Some(values.clone()),
+            _ => None,
+        };
+
+        let (uvs, tangents) = match options.tangents {
+            true => (
+                match mesh.attribute(Mesh::ATTRIBUTE_UV_0)? {
+                    VertexAttributeValues::Float32x2(values) => values.clone(),
+                    _ => return None,
+                },
+                match mesh.attribute(Mesh::ATTRIBUTE_TANGENT)? {
+                    VertexAttributeValues::Float32x4(values) => values.clone(),
+                    _ => return None,
+                },
+            ),
+            false => Default::default(),
+        };
+
+        let mut data = match mesh.attribute(VoxelTerrainMesh::ATTRIBUTE_DATA)? {
+            VertexAttributeValues::Uint32(values) => values.clone(),
+            _ => return None,
+        };
+
+        // packed meshes have no position attribute, the positions and normals are decoded back from the voxel data.
+        let (positions, normals) = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(_) => (
+                float32x3(Mesh::ATTRIBUTE_POSITION)?,
+                float32x3(Mesh::ATTRIBUTE_NORMAL)?,
+            ),
+            None if options.packed_positions => {
+                let mut positions = Vec::with_capacity(data.len());
+                let mut normals = Vec::with_capacity(data.len());
+                for value in data.iter_mut() {
+                    positions.push(PACKED_POSITION_SHIFTS.map(|shift| {
+                        (*value >> shift & PACKED_POSITION_MAX) as f32 * options.scale
+                    }));
+                    let face = RIGHT_HANDED_Y_UP_CONFIG
+                        .faces
+                        .get((*value >> 8 & 7) as usize)?;
+                    normals.push(face.quad_mesh_normals()[0]);
+                    *value &= !PACKED_POSITION_MASK;
+                }
+                (positions, normals)
+            }
+            None => return None,
+        };
+
+        Some(Self {
+            positions,
+            normals,
+            uvs,
+            tangents,
+            data,
+            indices: match mesh.indices()? {
+                Indices::U32(indices) => indices.clone(),
+                _ => return None,
+            },
+        })
+    }
+
+    /// Returns the positions of the vertices in voxels, if they all fit in the vertex data.
+    fn packable_positions(&self, scale: f32) -> Option<Vec<u32>> {
+        self.positions
+            .iter()
+            .map(|position| {
+                position.iter().zip(PACKED_POSITION_SHIFTS).try_fold(
+                    0,
+                    |packed, (&coord, shift)| {
+                        let voxels = (coord / scale).round();
+                        (0.0..=PACKED_POSITION_MAX as f32)
+                            .contains(&voxels)
+                            .then_some(packed | (voxels as u32) << shift)
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Empties all the buffers, keeping their allocations.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.uvs.clear();
+        self.tangents.clear();
+        self.data.clear();
+        self.indices.clear();
+    }
+
+    /// Moves the buffers into the attributes and indices of a bevy mesh, as read by the terrain material.
+    /// The options should be those the buffers were meshed with, so the mesh gets the expected attributes even if empty.
+    pub fn insert_into(mut self, render_mesh: &mut Mesh, options: &MeshingOptions) {
+        let packed = options
+            .packed_positions
+            .then(|| self.packable_positions(options.scale))
+            .flatten();
+
+        if let Some(packed) = packed {
+            for (value, position) in self.data.iter_mut().zip(packed) {
+                *value |= position;
+            }
+            render_mesh.remove_attribute(Mesh::ATTRIBUTE_POSITION);
+            render_mesh.remove_attribute(Mesh::ATTRIBUTE_NORMAL);
+        } else {
+            render_mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                VertexAttributeValues::Float32x3(self.positions),
+            );
+
+            // the terrain shader decodes normals from the voxel data, these are for other materials.
+            render_mesh.insert_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                VertexAttributeValues::Float32x3(self.normals),
+            );
+        }
+
+        if options.tangents {
+            render_mesh.insert_attribute(
+                Mesh::ATTRIBUTE_UV_0,
+                VertexAttributeValues::Float32x2(self.uvs),
+            );
+            render_mesh.insert_attribute(
+                Mesh::ATTRIBUTE_TANGENT,
+                VertexAttributeValues::Float32x4(self.tangents),
+            );
+        }
+
+        //todo: in the future we might want to encode all the information onto a single uint32
+        render_mesh.insert_attribute(
+            VoxelTerrainMesh::ATTRIBUTE_DATA,
+            VertexAttributeValues::Uint32(self.data),
+        );
+
+        render_mesh.set_indices(Some(Indices::U32(self.indices)));
+    }
 }
 
 // Processes the voxel data buffer specified as a parameter and generate.
-//todo: don't populate mesh directly, introduce a meshbuilding system.
 pub fn mesh_buffer<T, S>(
     buffer: &VoxelBuffer<T, S>,
     mesh_buffers: &mut MeshBuffers<T, S>,
     render_mesh: &mut Mesh,
-    scale: f32,
+    options: &MeshingOptions,
+) where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    mesh_buffer_cancellable(buffer, mesh_buffers, render_mesh, options, None, || false);
+}
+
+/// Like [`mesh_buffer`], but bails out as soon as `is_cancelled` returns `true` between the meshing steps.
+/// Returns whether the mesh was generated, a cancelled run leaving `render_mesh` untouched.
+/// Buffers reaching [`MeshingOptions::parallel_threshold`] are meshed in parallel slabs on `task_pool`, if any.
+pub fn mesh_buffer_cancellable<T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    render_mesh: &mut Mesh,
+    options: &MeshingOptions,
+    task_pool: Option<&TaskPool>,
+    is_cancelled: impl Fn() -> bool,
+) -> bool
+where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    let mut raw_mesh = RawMesh::default();
+    let meshed = fill_raw_mesh(
+        buffer,
+        mesh_buffers,
+        &mut raw_mesh,
+        options,
+        task_pool,
+        is_cancelled,
+    );
+
+    if meshed {
+        raw_mesh.insert_into(render_mesh, options);
+    }
+
+    meshed
+}
+
+/// Like [`mesh_buffer`], but outputs the geometry into plain vertex and index buffers instead of a bevy mesh.
+/// The buffers are cleared first.
+pub fn mesh_buffer_raw<T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    raw_mesh: &mut RawMesh,
+    options: &MeshingOptions,
+) where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    fill_raw_mesh(buffer, mesh_buffers, raw_mesh, options, None, || false);
+}
+
+// Meshes the voxel data into the raw buffers, returning false if cancelled midway.
+fn fill_raw_mesh<T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    raw_mesh: &mut RawMesh,
+    options: &MeshingOptions,
+    task_pool: Option<&TaskPool>,
+    is_cancelled: impl Fn() -> bool,
+) -> bool
+where
+    T: Copy + Default + MaterialVoxel + Send + Sync,
+    S: Shape<3, Coord = u32>,
+{
+    raw_mesh.clear();
+
+    if is_cancelled() {
+        return false;
+    }
+
+    // nothing to mesh in chunks only holding air.
+    if buffer
+        .slice()
+        .iter()
+        .all(|voxel| voxel.get_visibility() == VoxelVisibility::Empty)
+    {
+        return true;
+    }
+
+    let face_quads = greedy_mesh_quads(buffer, mesh_buffers, options, task_pool);
+
+    let num_quads: usize = face_quads.iter().flatten().map(|quads| quads.len()).sum();
+    let num_vertices = num_quads * 4;
+    raw_mesh.indices.reserve(num_quads * 6);
+    raw_mesh.positions.reserve(num_vertices);
+    raw_mesh.normals.reserve(num_vertices);
+    raw_mesh.data.reserve(num_vertices);
+    if options.tangents {
+        raw_mesh.uvs.reserve(num_vertices);
+        raw_mesh.tangents.reserve(num_vertices);
+    }
+
+    //normal face index depends on the quad orientation config
+    for (block_face_normal_index, group) in face_quads.iter().enumerate() {
+        if is_cancelled() {
+            return false;
+        }
+
+        for quad in group.iter().flat_map(|quads| quads.iter()) {
+            push_quad(raw_mesh, buffer, block_face_normal_index, quad, options);
+        }
+    }
+
+    true
+}
+
+// Appends the vertices and indices of a quad of the padded scratch buffer, facing the specified direction.
+fn push_quad<T, S>(
+    raw_mesh: &mut RawMesh,
+    buffer: &VoxelBuffer<T, S>,
+    face_index: usize,
+    quad: &UnorientedQuad,
+    options: &MeshingOptions,
 ) where
     T: Copy + Default + MaterialVoxel,
     S: Shape<3, Coord = u32>,
 {
-    mesh_buffers
-        .greedy_buffer
-        .reset(buffer.shape().size() as usize);
+    let face = &RIGHT_HANDED_Y_UP_CONFIG.faces[face_index];
 
-    let dst_shape = mesh_buffers.scratch_buffer.shape().clone();
+    let mut quad_indices = face.quad_mesh_indices(raw_mesh.positions.len() as u32);
+    if options.winding == FaceWinding::Clockwise {
+        quad_indices.swap(1, 2);
+        quad_indices.swap(4, 5);
+    }
+    raw_mesh.indices.extend_from_slice(&quad_indices);
+    let quad_positions = face.quad_mesh_positions(quad, options.scale);
+    raw_mesh.positions.extend_from_slice(&quad_positions);
+    raw_mesh
+        .normals
+        .extend_from_slice(&face.quad_mesh_normals());
 
-    copy3(
-        buffer.shape().as_array(),
-        buffer.slice(),
-        buffer.shape(),
-        [0; 3],
-        mesh_buffers.scratch_buffer.slice_mut(),
-        &dst_shape,
-        [1; 3],
+    if options.tangents {
+        let quad_uvs = face.tex_coords(RIGHT_HANDED_Y_UP_CONFIG.u_flip_face, true, quad);
+        raw_mesh.uvs.extend_from_slice(&quad_uvs);
+        raw_mesh.tangents.extend_from_slice(&quad_mesh_tangents(
+            &quad_positions,
+            &quad_uvs,
+            face.signed_normal().as_vec3().to_array(),
+        ));
+    }
+    let voxel_pos = quad.minimum.map(|x| x - 1);
+    let border = options.border_tint && is_border_voxel(voxel_pos, buffer.shape().as_array());
+    raw_mesh.data.extend_from_slice(
+        &[(face_index as u32) << 8u32
+            | if border { BORDER_FLAG } else { 0 }
+            | buffer.voxel_at(voxel_pos.into()).as_mat_id() as u32; 4],
     );
+}
 
-    greedy_quads(
-        mesh_buffers.scratch_buffer.slice(),
-        mesh_buffers.scratch_buffer.shape(),
-        [0; 3],
-        mesh_buffers
-            .scratch_buffer
-            .shape()
-            .as_array()
-            .map(|axis| axis - 1),
-        &RIGHT_HANDED_Y_UP_CONFIG.faces,
-        &mut mesh_buffers.greedy_buffer,
-    );
+/// Updates a mesh built with [`MeshingAlgorithm::PerVoxelCubes`] after the voxel at `pos` changed, replacing the faces
+/// of the voxel and of its six neighbors instead of meshing the whole buffer again.
+///
+/// Returns `false`, leaving the mesh untouched, when the buffer should be meshed again instead: greedy meshes merge
+/// faces across many voxels, and the patch scans all the quads of the mesh so a mesh with more quads than the buffer
+/// has voxels is cheaper to rebuild.
+pub fn patch_raw_mesh<T, S>(
+    buffer: &VoxelBuffer<T, S>,
+    raw_mesh: &mut RawMesh,
+    pos: UVec3,
+    options: &MeshingOptions,
+) -> bool
+where
+    T: Copy + Default + MaterialVoxel,
+    S: Shape<3, Coord = u32>,
+{
+    let num_quads = raw_mesh.positions.len() / 4;
+    let size = IVec3::from(buffer.shape().as_array().map(|x| x as i32));
+    let pos = pos.as_ivec3();
 
-    let num_indices = mesh_buffers.greedy_buffer.quads.num_quads() * 6;
-    let num_vertices = mesh_buffers.greedy_buffer.quads.num_quads() * 4;
-    let mut indices = Vec::with_capacity(num_indices);
-    let mut positions = Vec::with_capacity(num_vertices);
-    let mut data = Vec::with_capacity(num_vertices);
+    if options.algorithm != MeshingAlgorithm::PerVoxelCubes
+        || num_quads > buffer.slice().len()
+        || pos.cmpge(size).any()
+    {
+        return false;
+    }
 
-    //normal face index depends on the quad orientation config
-    for (block_face_normal_index, (group, face)) in mesh_buffers
-        .greedy_buffer
-        .quads
-        .groups
-        .as_ref()
-        .iter()
-        .zip(RIGHT_HANDED_Y_UP_CONFIG.faces.iter())
-        .enumerate()
+    let in_bounds = |voxel: IVec3| voxel.cmpge(IVec3::ZERO).all() && voxel.cmplt(size).all();
+    let voxel_at = |voxel: IVec3| buffer.voxel_at(voxel.as_uvec3().to_array().into());
+
+    // drop the faces of the changed voxel and its neighbors, compacting the remaining quads.
+    let mut kept = 0;
+    for quad in 0..num_quads {
+        let center = raw_mesh.positions[quad * 4..][..4]
+            .iter()
+            .copied()
+            .map(Vec3::from)
+            .sum::<Vec3>()
+            / (4.0 * options.scale);
+        // the quads are positioned in the padded buffer, one voxel off the voxel data.
+        let voxel = (center - Vec3::from(raw_mesh.normals[quad * 4]) * 0.5)
+            .floor()
+            .as_ivec3()
+            - IVec3::ONE;
+
+        if (voxel - pos).abs().to_array().iter().sum::<i32>() <= 1 {
+            continue;
+        }
+
+        if kept != quad {
+            let (src, dst) = (quad * 4..quad * 4 + 4, kept * 4);
+            raw_mesh.positions.copy_within(src.clone(), dst);
+            raw_mesh.normals.copy_within(src.clone(), dst);
+            raw_mesh.data.copy_within(src.clone(), dst);
+            if options.tangents {
+                raw_mesh.uvs.copy_within(src.clone(), dst);
+                raw_mesh.tangents.copy_within(src, dst);
+            }
+
+            for index in 0..6 {
+                raw_mesh.indices[kept * 6 + index] =
+                    raw_mesh.indices[quad * 6 + index] - (quad - kept) as u32 * 4;
+            }
+        }
+        kept += 1;
+    }
+
+    raw_mesh.positions.truncate(kept * 4);
+    raw_mesh.normals.truncate(kept * 4);
+    raw_mesh.data.truncate(kept * 4);
+    if options.tangents {
+        raw_mesh.uvs.truncate(kept * 4);
+        raw_mesh.tangents.truncate(kept * 4);
+    }
+    raw_mesh.indices.truncate(kept * 6);
+
+    // emit the visible faces of the changed voxel and its neighbors again, following the rules of the full meshing.
+    for voxel in [
+        IVec3::ZERO,
+        IVec3::NEG_X,
+        IVec3::X,
+        IVec3::NEG_Y,
+        IVec3::Y,
+        IVec3::NEG_Z,
+        IVec3::Z,
+    ]
+    .map(|offset| pos + offset)
+    .into_iter()
+    .filter(|voxel| in_bounds(*voxel))
     {
-        for quad in group.iter() {
-            indices.extend_from_slice(&face.quad_mesh_indices(positions.len() as u32));
-            positions.extend_from_slice(&face.quad_mesh_positions(quad, scale));
-            data.extend_from_slice(
-                &[(block_face_normal_index as u32) << 8u32
-                    | buffer
-                        .voxel_at(quad.minimum.map(|x| x - 1).into())
-                        .as_mat_id() as u32; 4],
+        let visibility = voxel_at(voxel).get_visibility();
+        if visibility == VoxelVisibility::Empty {
+            continue;
+        }
+
+        for (face_index, face) in RIGHT_HANDED_Y_UP_CONFIG.faces.iter().enumerate() {
+            let neighbor = voxel + IVec3::from(face.signed_normal().to_array());
+            // the padding around the voxel data is always empty.
+            let neighbor_visibility = match in_bounds(neighbor) {
+                true => voxel_at(neighbor).get_visibility(),
+                false => VoxelVisibility::Empty,
+            };
+
+            let visible = match neighbor_visibility {
+                VoxelVisibility::Empty => true,
+                VoxelVisibility::Translucent => visibility == VoxelVisibility::Opaque,
+                VoxelVisibility::Opaque => false,
+            };
+            let culled = options.cull_bottom_layer && voxel.y == 0 && face.signed_normal().y < 0;
+
+            if visible && !culled {
+                let quad = UnorientedQuad {
+                    minimum: (voxel + IVec3::ONE).as_uvec3().to_array(),
+                    width: 1,
+                    height: 1,
+                };
+                push_quad(raw_mesh, buffer, face_index, &quad, options);
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{ChunkShape, Voxel, CHUNK_LENGTH};
+    use bevy::{
+        math::Vec3,
+        render::{mesh::MeshVertexAttributeId, render_resource::PrimitiveTopology},
+        tasks::TaskPool,
+    };
+
+    const STONE: Voxel = Voxel::new(1);
+
+    // fills the voxels within `min..max` of a chunk, leaving the interior empty if `hollow` is set.
+    fn chunk_with_box(
+        min: [u32; 3],
+        max: [u32; 3],
+        hollow: bool,
+    ) -> VoxelBuffer<Voxel, ChunkShape> {
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        for z in min[2]..max[2] {
+            for y in min[1]..max[1] {
+                for x in min[0]..max[0] {
+                    let on_shell = [x, y, z]
+                        .iter()
+                        .zip(min.iter().zip(max.iter()))
+                        .any(|(&v, (&lo, &hi))| v == lo || v == hi - 1);
+                    if on_shell || !hollow {
+                        *buffer.voxel_at_mut([x, y, z].into()) = STONE;
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    fn mesh(buffer: &VoxelBuffer<Voxel, ChunkShape>, options: &MeshingOptions) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh_buffer(
+            buffer,
+            &mut MeshBuffers::new(ChunkShape {}),
+            &mut mesh,
+            options,
+        );
+        mesh
+    }
+
+    // returns the centers of the quads of a mesh, in voxels of the padded buffer.
+    fn quad_centers(mesh: &Mesh) -> Vec<Vec3> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the mesh has no positions");
+        };
+        positions
+            .chunks(4)
+            .map(|corners| corners.iter().copied().map(Vec3::from).sum::<Vec3>() / 4.0)
+            .collect()
+    }
+
+    #[test]
+    fn hollow_box_keeps_its_interior_faces() {
+        let last = CHUNK_LENGTH - 5;
+        for min in [[0; 3], [13; 3], [last; 3]] {
+            let max = min.map(|x| x + 5);
+            let mesh = mesh(&chunk_with_box(min, max, true), &MeshingOptions::default());
+
+            // a quad per outer face, and a quad per wall of the 3x3x3 hollow.
+            let centers = quad_centers(&mesh);
+            assert_eq!(centers.len(), 12);
+            assert_eq!(mesh.indices().unwrap().len(), 12 * 6);
+
+            // the mesh is one voxel off the voxel data: the hollow spans from 2 to 5 past the minimum.
+            let inner = centers
+                .iter()
+                .filter(|center| {
+                    center
+                        .to_array()
+                        .iter()
+                        .zip(min)
+                        .all(|(&x, min)| x >= (min + 2) as f32 && x <= (min + 5) as f32)
+                })
+                .count();
+            assert_eq!(inner, 6);
+        }
+    }
+
+    #[test]
+    fn hollow_box_unit_faces() {
+        let options = MeshingOptions {
+            algorithm: MeshingAlgorithm::PerVoxelCubes,
+            ..Default::default()
+        };
+        let mesh = mesh(&chunk_with_box([13; 3], [18; 3], true), &options);
+
+        // 25 voxel faces on each of the 6 outer sides, 9 on each of the 6 inner walls.
+        assert_eq!(quad_centers(&mesh).len(), 6 * 25 + 6 * 9);
+    }
+
+    fn positions(mesh: &Mesh) -> Vec<Vec3> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the mesh has no positions");
+        };
+        positions.iter().copied().map(Vec3::from).collect()
+    }
+
+    #[test]
+    fn positions_follow_the_voxel_scale() {
+        let buffer = chunk_with_box([3; 3], [6; 3], false);
+        let unit = positions(&mesh(&buffer, &MeshingOptions::default()));
+        assert!(!unit.is_empty());
+
+        let doubled: Vec<_> = unit.iter().map(|position| *position * 2.0).collect();
+        assert_eq!(
+            positions(&mesh(
+                &buffer,
+                &MeshingOptions {
+                    scale: 2.0,
+                    ..Default::default()
+                }
+            )),
+            doubled
+        );
+    }
+
+    #[test]
+    fn faces_carry_their_axis_normal() {
+        let mesh = mesh(
+            &chunk_with_box([4; 3], [5; 3], false),
+            &MeshingOptions::default(),
+        );
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("the mesh has no normals");
+        };
+
+        // one quad per side of the voxel, the four vertices of a quad sharing its normal.
+        let mut quad_normals: Vec<_> = normals
+            .chunks(4)
+            .map(|quad| {
+                assert!(quad.iter().all(|normal| normal == &quad[0]));
+                Vec3::from(quad[0])
+            })
+            .collect();
+        quad_normals.sort_by(|a, b| a.to_array().partial_cmp(&b.to_array()).unwrap());
+        assert_eq!(
+            quad_normals,
+            [
+                Vec3::NEG_X,
+                Vec3::NEG_Y,
+                Vec3::NEG_Z,
+                Vec3::Z,
+                Vec3::Y,
+                Vec3::X
+            ]
+        );
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_to_the_normals() {
+        let buffer = chunk_with_box([3; 3], [6; 3], false);
+        assert!(mesh(&buffer, &MeshingOptions::default())
+            .attribute(Mesh::ATTRIBUTE_TANGENT)
+            .is_none());
+
+        let mesh = mesh(
+            &buffer,
+            &MeshingOptions {
+                tangents: true,
+                ..Default::default()
+            },
+        );
+        let (
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x4(tangents)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_TANGENT),
+        )
+        else {
+            panic!("the mesh has no normals or tangents");
+        };
+        assert_eq!(normals.len(), tangents.len());
+
+        for (normal, tangent) in normals.iter().zip(tangents) {
+            let [x, y, z, handedness] = *tangent;
+            let tangent = Vec3::new(x, y, z);
+            assert!((tangent.length() - 1.0).abs() < 1e-5);
+            assert!(tangent.dot(Vec3::from(*normal)).abs() < 1e-5);
+            assert_eq!(handedness.abs(), 1.0);
+        }
+    }
+
+    // a chunk of hilly terrain of mixed materials and scattered holes, with quads of all sizes.
+    fn terrain_chunk() -> VoxelBuffer<Voxel, ChunkShape> {
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        for z in 0..CHUNK_LENGTH {
+            for y in 0..CHUNK_LENGTH {
+                for x in 0..CHUNK_LENGTH {
+                    let height = 12 + (x * 3 + z * 5) % 9;
+                    let hole = (x * 7 + y * 11 + z * 13) % 17 == 0;
+                    if y < height && !hole {
+                        let material = if y + 2 >= height {
+                            2
+                        } else {
+                            1 + (x / 8 + z / 8) % 3
+                        };
+                        *buffer.voxel_at_mut([x, y, z].into()) = Voxel::new(material as u8);
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    // the raw content of the vertex attributes and indices of a mesh.
+    fn mesh_data(mesh: &Mesh) -> (Vec<(MeshVertexAttributeId, Vec<u8>)>, Vec<usize>) {
+        let mut attributes: Vec<_> = mesh
+            .attributes()
+            .map(|(id, values)| (id, values.get_bytes().to_vec()))
+            .collect();
+        attributes.sort_by_key(|(id, _)| *id);
+        let indices = mesh
+            .indices()
+            .expect("the mesh has no indices")
+            .iter()
+            .collect();
+        (attributes, indices)
+    }
+
+    #[test]
+    fn parallel_meshing_matches_serial_meshing() {
+        let buffer = terrain_chunk();
+        let task_pool = TaskPool::new();
+
+        for algorithm in [MeshingAlgorithm::Greedy, MeshingAlgorithm::PerVoxelCubes] {
+            let serial_options = MeshingOptions {
+                algorithm,
+                ..Default::default()
+            };
+            let serial = mesh_data(&mesh(&buffer, &serial_options));
+            assert!(!serial.1.is_empty());
+
+            for parallel_slabs in [2, 3, 4] {
+                let options = MeshingOptions {
+                    parallel_threshold: Some(CHUNK_LENGTH),
+                    parallel_slabs,
+                    ..serial_options
+                };
+                let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+                let mut parallel = Mesh::new(PrimitiveTopology::TriangleList);
+                assert!(mesh_buffer_cancellable(
+                    &buffer,
+                    &mut mesh_buffers,
+                    &mut parallel,
+                    &options,
+                    Some(&task_pool),
+                    || false,
+                ));
+
+                // the per-voxel faces are emitted in a single pass, only the greedy quads are split in slabs.
+                if algorithm == MeshingAlgorithm::Greedy {
+                    assert_eq!(mesh_buffers.slab_buffers.len(), 3 * parallel_slabs as usize);
+                }
+                assert_eq!(mesh_data(&parallel), serial);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_meshing_needs_a_task_pool() {
+        let options = MeshingOptions {
+            parallel_threshold: Some(1),
+            ..Default::default()
+        };
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh_buffer(&terrain_chunk(), &mut mesh_buffers, &mut mesh, &options);
+
+        assert!(mesh_buffers.slab_buffers.is_empty());
+        assert!(!positions(&mesh).is_empty());
+    }
+
+    #[test]
+    fn unmerged_materials_get_a_quad_per_face() {
+        const FOLIAGE: Voxel = Voxel::new(11);
+
+        // a row of 4 foliage voxels lying on a row of 4 stone voxels.
+        let mut buffer = chunk_with_box([4, 4, 4], [8, 5, 5], false);
+        for x in 4..8 {
+            *buffer.voxel_at_mut([x, 5, 4].into()) = FOLIAGE;
+        }
+
+        let merged = mesh(&buffer, &MeshingOptions::default());
+        assert_eq!(positions(&merged).len(), 4 * (5 + 5));
+
+        let mut unmerged_materials = MaterialIdSet::default();
+        unmerged_materials.insert(FOLIAGE.id);
+        let unmerged = mesh(
+            &buffer,
+            &MeshingOptions {
+                unmerged_materials,
+                ..Default::default()
+            },
+        );
+        // the stone still has one quad per side, the foliage one per voxel face but its ends.
+        assert_eq!(positions(&unmerged).len(), 4 * (5 + 3 * 4 + 2));
+    }
+
+    #[test]
+    fn output_counts_match_the_meshes() {
+        let chunks = [
+            VoxelBuffer::new_empty(ChunkShape {}),
+            chunk_with_box([0; 3], [CHUNK_LENGTH; 3], false),
+            chunk_with_box([13; 3], [18; 3], true),
+            terrain_chunk(),
+        ];
+        let mut unmerged_materials = MaterialIdSet::default();
+        unmerged_materials.insert(2);
+        let option_sets = [
+            MeshingOptions::default(),
+            MeshingOptions {
+                unmerged_materials,
+                ..Default::default()
+            },
+            MeshingOptions {
+                algorithm: MeshingAlgorithm::PerVoxelCubes,
+                ..Default::default()
+            },
+            MeshingOptions {
+                max_quad_size: Some(4),
+                cull_bottom_layer: true,
+                ..Default::default()
+            },
+        ];
+
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        for buffer in &chunks {
+            for options in &option_sets {
+                let mesh = mesh(buffer, options);
+                let vertices = positions(&mesh).len();
+
+                assert_eq!(
+                    count_mesh_output(buffer, &mut mesh_buffers, options),
+                    MeshOutputCounts {
+                        quads: vertices / 4,
+                        vertices,
+                        indices: mesh_data(&mesh).1.len(),
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn per_voxel_cubes_have_a_quad_per_voxel_face() {
+        let buffer = chunk_with_box([13; 3], [18; 3], true);
+        let options = MeshingOptions {
+            algorithm: MeshingAlgorithm::PerVoxelCubes,
+            ..Default::default()
+        };
+
+        // 25 voxel faces on each of the 6 outer sides, 9 on each of the 6 walls of the hollow.
+        assert_eq!(
+            positions(&mesh(&buffer, &options)).len(),
+            4 * (6 * 25 + 6 * 9)
+        );
+        // the same box merges into a quad per side and per wall.
+        assert_eq!(
+            positions(&mesh(&buffer, &MeshingOptions::default())).len(),
+            4 * 12
+        );
+    }
+
+    fn voxel_data(mesh: &Mesh) -> Vec<u32> {
+        let Some(VertexAttributeValues::Uint32(data)) =
+            mesh.attribute(VoxelTerrainMesh::ATTRIBUTE_DATA)
+        else {
+            panic!("the mesh has no voxel data");
+        };
+        data.clone()
+    }
+
+    #[test]
+    fn border_tint_flags_the_outer_voxel_layer() {
+        // a row of 4 voxels starting on the -X border of the chunk.
+        let buffer = chunk_with_box([0, 4, 4], [4, 5, 5], false);
+
+        let untinted = voxel_data(&mesh(&buffer, &MeshingOptions::default()));
+        assert_eq!(untinted.len(), 6 * 4);
+        assert!(untinted.iter().all(|data| data & BORDER_FLAG == 0));
+
+        let tinted = mesh(
+            &buffer,
+            &MeshingOptions {
+                border_tint: true,
+                ..Default::default()
+            },
+        );
+        // the 4 long sides are split between the border voxel and the inner ones.
+        let data = voxel_data(&tinted);
+        assert_eq!(data.len(), (4 * 2 + 2) * 4);
+
+        // the border faces are those of the first voxel of the row.
+        let border_positions: Vec<_> = positions(&tinted)
+            .into_iter()
+            .zip(&data)
+            .filter(|(_, data)| *data & BORDER_FLAG != 0)
+            .map(|(position, _)| position)
+            .collect();
+        assert_eq!(border_positions.len(), 5 * 4);
+        assert!(border_positions.iter().all(|position| position.x <= 2.0));
+    }
+
+    fn normals(mesh: &Mesh) -> Vec<Vec3> {
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("the mesh has no normals");
+        };
+        normals.iter().copied().map(Vec3::from).collect()
+    }
+
+    #[test]
+    fn triangles_wind_around_their_face_normal() {
+        let buffer = terrain_chunk();
+        for algorithm in [MeshingAlgorithm::Greedy, MeshingAlgorithm::PerVoxelCubes] {
+            for (winding, sign) in [
+                (FaceWinding::CounterClockwise, 1.0),
+                (FaceWinding::Clockwise, -1.0),
+            ] {
+                let mesh = mesh(
+                    &buffer,
+                    &MeshingOptions {
+                        algorithm,
+                        winding,
+                        ..Default::default()
+                    },
+                );
+                let (positions, normals) = (positions(&mesh), normals(&mesh));
+                let indices: Vec<_> = mesh.indices().unwrap().iter().collect();
+                assert!(!indices.is_empty());
+
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i]]);
+                    let normal = normals[triangle[0]];
+                    assert!(
+                        sign * (b - a).cross(c - a).dot(normal) > 0.0,
+                        "{algorithm:?} {winding:?} triangle {triangle:?} doesn't face {normal}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_meshing_leaves_the_mesh_untouched() {
+        let buffer = chunk_with_box([4, 4, 4], [8, 8, 8], false);
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let options = MeshingOptions::default();
+
+        // cancelled before meshing, then between the face groups.
+        for checks_before_cancelling in [0, 3] {
+            let checks = std::cell::Cell::new(0);
+            let mut cancelled = Mesh::new(PrimitiveTopology::TriangleList);
+            assert!(!mesh_buffer_cancellable(
+                &buffer,
+                &mut mesh_buffers,
+                &mut cancelled,
+                &options,
+                None,
+                || {
+                    checks.set(checks.get() + 1);
+                    checks.get() > checks_before_cancelling
+                },
+            ));
+            assert!(cancelled.attribute(Mesh::ATTRIBUTE_POSITION).is_none());
+            assert!(cancelled.indices().is_none());
+        }
+
+        let mut meshed = Mesh::new(PrimitiveTopology::TriangleList);
+        assert!(mesh_buffer_cancellable(
+            &buffer,
+            &mut mesh_buffers,
+            &mut meshed,
+            &options,
+            None,
+            || false,
+        ));
+        assert_eq!(positions(&meshed).len(), 6 * 4);
+    }
+
+    #[test]
+    fn faces_only_merge_with_matching_metadata() {
+        // a row of 4 stone voxels, the last two of which have a different orientation bit.
+        let mut buffer = chunk_with_box([4, 4, 4], [8, 5, 5], false);
+        for x in 6..8 {
+            *buffer.voxel_at_mut([x, 4, 4].into()) = STONE.with_metadata(1);
+        }
+
+        // two halves of 5 faces each, rather than the 6 faces of the whole row.
+        let split = mesh(&buffer, &MeshingOptions::default());
+        assert_eq!(positions(&split).len(), 4 * 2 * 5);
+
+        let masked = MeshingOptions {
+            merge_metadata_mask: !1,
+            ..Default::default()
+        };
+        assert_eq!(positions(&mesh(&buffer, &masked)).len(), 4 * 6);
+
+        // the bits left in the mask still split the faces.
+        for x in 6..8 {
+            *buffer.voxel_at_mut([x, 4, 4].into()) = STONE.with_metadata(3);
+        }
+        assert_eq!(positions(&mesh(&buffer, &masked)).len(), 4 * 2 * 5);
+    }
+
+    #[test]
+    fn raw_meshes_build_the_same_meshes() {
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut raw_mesh = RawMesh::default();
+
+        for tangents in [false, true] {
+            let options = MeshingOptions {
+                tangents,
+                ..Default::default()
+            };
+            mesh_buffer_raw(&terrain_chunk(), &mut mesh_buffers, &mut raw_mesh, &options);
+            assert_eq!(
+                raw_mesh.uvs.len(),
+                raw_mesh.positions.len() * tangents as usize
+            );
+
+            let mut built = Mesh::new(PrimitiveTopology::TriangleList);
+            raw_mesh.clone().insert_into(&mut built, &options);
+            assert_eq!(
+                mesh_data(&built),
+                mesh_data(&mesh(&terrain_chunk(), &options))
             );
         }
+
+        // the buffers are cleared before meshing another chunk.
+        let options = MeshingOptions::default();
+        mesh_buffer_raw(
+            &chunk_with_box([4, 4, 4], [5, 5, 5], false),
+            &mut mesh_buffers,
+            &mut raw_mesh,
+            &options,
+        );
+        assert_eq!(raw_mesh.positions.len(), 6 * 4);
+        assert_eq!(raw_mesh.normals.len(), 6 * 4);
+        assert_eq!(raw_mesh.data.len(), 6 * 4);
+        assert_eq!(raw_mesh.indices.len(), 6 * 6);
+        assert!(raw_mesh.uvs.is_empty() && raw_mesh.tangents.is_empty());
     }
 
-    render_mesh.insert_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::Float32x3(positions),
-    );
+    #[test]
+    fn all_air_chunks_have_no_geometry() {
+        let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        *buffer.voxel_at_mut([4, 5, 6].into()) = Voxel::EMPTY_VOXEL.with_metadata(2);
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
 
-    //todo: in the future we might want to encode all the information onto a single uint32
-    render_mesh.insert_attribute(
-        VoxelTerrainMesh::ATTRIBUTE_DATA,
-        VertexAttributeValues::Uint32(data),
-    );
+        // the raw buffers left over from a previous chunk are cleared.
+        let mut raw_mesh = RawMesh::default();
+        let options = MeshingOptions::default();
+        mesh_buffer_raw(
+            &chunk_with_box([4, 4, 4], [5, 5, 5], false),
+            &mut mesh_buffers,
+            &mut raw_mesh,
+            &options,
+        );
+        mesh_buffer_raw(&buffer, &mut mesh_buffers, &mut raw_mesh, &options);
+        assert_eq!(raw_mesh, RawMesh::default());
 
-    render_mesh.set_indices(Some(Indices::U32(indices.clone())));
+        let empty = mesh(&buffer, &options);
+        assert!(positions(&empty).is_empty());
+        assert_eq!(empty.indices().map(|indices| indices.len()), Some(0));
+    }
+
+    #[test]
+    fn buried_voxels_dont_change_the_geometry() {
+        let solid = chunk_with_box([0; 3], [CHUNK_LENGTH; 3], false);
+        let mut buried = solid.clone();
+        for z in 1..CHUNK_LENGTH - 1 {
+            for y in 1..CHUNK_LENGTH - 1 {
+                for x in 1..CHUNK_LENGTH - 1 {
+                    *buried.voxel_at_mut([x, y, z].into()) =
+                        Voxel::new(2 + ((x + y + z) % 3) as u8);
+                }
+            }
+        }
+
+        for algorithm in [MeshingAlgorithm::Greedy, MeshingAlgorithm::PerVoxelCubes] {
+            let options = MeshingOptions {
+                algorithm,
+                ..Default::default()
+            };
+            let solid_mesh = mesh_data(&mesh(&solid, &options));
+            assert!(!solid_mesh.1.is_empty());
+            assert_eq!(mesh_data(&mesh(&buried, &options)), solid_mesh);
+        }
+    }
+
+    #[test]
+    fn retained_buffers_are_capped_between_chunks() {
+        const MAX_BYTES: usize = 1024 * 1024;
+        let mut checkerboard = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        for z in 0..CHUNK_LENGTH {
+            for y in 0..CHUNK_LENGTH {
+                for x in 0..CHUNK_LENGTH {
+                    if (x + y + z) % 2 == 0 {
+                        *checkerboard.voxel_at_mut([x, y, z].into()) = STONE;
+                    }
+                }
+            }
+        }
+        let small = chunk_with_box([4, 4, 4], [6, 6, 6], false);
+        let options = MeshingOptions::default();
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut output = Mesh::new(PrimitiveTopology::TriangleList);
+
+        mesh_buffer(&checkerboard, &mut mesh_buffers, &mut output, &options);
+        assert!(mesh_buffers.retained_bytes() > MAX_BYTES);
+        mesh_buffers.shrink_to(MAX_BYTES);
+        assert!(mesh_buffers.retained_bytes() <= MAX_BYTES);
+
+        // the released buffers only grow back as much as the next chunk needs, and still mesh it right.
+        mesh_buffer(&small, &mut mesh_buffers, &mut output, &options);
+        let retained = mesh_buffers.retained_bytes();
+        assert!(retained <= MAX_BYTES);
+        mesh_buffers.shrink_to(MAX_BYTES);
+        assert_eq!(mesh_buffers.retained_bytes(), retained);
+        assert_eq!(mesh_data(&output), mesh_data(&mesh(&small, &options)));
+    }
+
+    // returns the quads of a raw mesh in a canonical order, each with its indices relative to its first vertex.
+    fn sorted_quads(raw_mesh: &RawMesh) -> Vec<(Vec<u32>, Vec<u32>)> {
+        let mut quads: Vec<_> = (0..raw_mesh.positions.len() / 4)
+            .map(|quad| {
+                let vertices = quad * 4..quad * 4 + 4;
+                let attributes = raw_mesh.positions[vertices.clone()]
+                    .iter()
+                    .chain(&raw_mesh.normals[vertices.clone()])
+                    .flatten()
+                    .map(|x| x.to_bits())
+                    .chain(raw_mesh.data[vertices].iter().copied())
+                    .collect();
+                let indices = raw_mesh.indices[quad * 6..][..6]
+                    .iter()
+                    .map(|index| index - quad as u32 * 4)
+                    .collect();
+                (attributes, indices)
+            })
+            .collect();
+        quads.sort();
+        quads
+    }
+
+    #[test]
+    fn patched_meshes_match_full_remeshes() {
+        let options = MeshingOptions {
+            algorithm: MeshingAlgorithm::PerVoxelCubes,
+            ..Default::default()
+        };
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut buffer = terrain_chunk();
+        let mut raw_mesh = RawMesh::default();
+        mesh_buffer_raw(&buffer, &mut mesh_buffers, &mut raw_mesh, &options);
+
+        // removing a buried voxel, adding one on the surface and removing one at the chunk border.
+        for (pos, voxel) in [
+            ([10, 5, 10], Voxel::default()),
+            ([4, 25, 7], STONE),
+            ([0, 3, 31], Voxel::default()),
+        ] {
+            *buffer.voxel_at_mut(pos.into()) = voxel;
+            let before = sorted_quads(&raw_mesh);
+            assert!(patch_raw_mesh(&buffer, &mut raw_mesh, pos.into(), &options));
+
+            let mut remeshed = RawMesh::default();
+            mesh_buffer_raw(&buffer, &mut mesh_buffers, &mut remeshed, &options);
+            let after = sorted_quads(&raw_mesh);
+            assert_eq!(after, sorted_quads(&remeshed));
+
+            // only the faces of the voxel and its six neighbors may change.
+            let kept = before
+                .iter()
+                .filter(|quad| after.binary_search(quad).is_ok())
+                .count();
+            assert!(before.len() - kept <= 7 * 6);
+            assert!(after.len() - kept <= 7 * 6);
+        }
+    }
+
+    #[test]
+    fn greedy_meshes_are_not_patched() {
+        let options = MeshingOptions::default();
+        let buffer = terrain_chunk();
+        let mut raw_mesh = RawMesh::default();
+        mesh_buffer_raw(
+            &buffer,
+            &mut MeshBuffers::new(ChunkShape {}),
+            &mut raw_mesh,
+            &options,
+        );
+
+        let unpatched = raw_mesh.clone();
+        assert!(!patch_raw_mesh(
+            &buffer,
+            &mut raw_mesh,
+            [4, 4, 4].into(),
+            &options
+        ));
+        assert_eq!(raw_mesh, unpatched);
+    }
+
+    #[test]
+    fn capped_quads_are_split_into_tiles() {
+        // a bar 16 voxels long along the X axis.
+        let buffer = chunk_with_box([4, 4, 4], [20, 5, 5], false);
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut face_quads = |max_quad_size| -> Vec<Vec<UnorientedQuad>> {
+            let options = MeshingOptions {
+                max_quad_size,
+                ..Default::default()
+            };
+            greedy_mesh_quads(&buffer, &mut mesh_buffers, &options, None)
+                .iter()
+                .map(|quads| {
+                    quads
+                        .iter()
+                        .flat_map(|quads| quads.iter())
+                        .copied()
+                        .collect()
+                })
+                .collect()
+        };
+
+        let uncapped = face_quads(None);
+        assert_eq!(face_quads(Some(16)), uncapped);
+
+        let capped = face_quads(Some(8));
+        for (uncapped, capped) in uncapped.iter().zip(&capped) {
+            assert_eq!(uncapped.len(), 1);
+            let run = uncapped[0].width.max(uncapped[0].height);
+            // the 16 voxel long faces are split in two, the ends are kept as they are.
+            assert_eq!(capped.len(), if run == 16 { 2 } else { 1 });
+            assert!(capped
+                .iter()
+                .all(|quad| quad.width <= 8 && quad.height <= 8));
+            assert_eq!(
+                capped
+                    .iter()
+                    .map(|quad| quad.width * quad.height)
+                    .sum::<u32>(),
+                uncapped[0].width * uncapped[0].height
+            );
+        }
+    }
+
+    #[test]
+    fn culling_the_bottom_layer_drops_its_downward_faces() {
+        let mut buffer = chunk_with_box([0, 0, 0], [CHUNK_LENGTH, 4, CHUNK_LENGTH], false);
+        let mut mesh_buffers = MeshBuffers::new(ChunkShape {});
+        let mut quad_count =
+            |buffer: &VoxelBuffer<Voxel, ChunkShape>, algorithm, cull_bottom_layer| {
+                let options = MeshingOptions {
+                    algorithm,
+                    cull_bottom_layer,
+                    ..Default::default()
+                };
+                let mut raw_mesh = RawMesh::default();
+                mesh_buffer_raw(buffer, &mut mesh_buffers, &mut raw_mesh, &options);
+                raw_mesh.positions.len() / 4
+            };
+
+        for (algorithm, bottom_quads) in [
+            (
+                MeshingAlgorithm::PerVoxelCubes,
+                (CHUNK_LENGTH * CHUNK_LENGTH) as usize,
+            ),
+            (MeshingAlgorithm::Greedy, 1),
+        ] {
+            assert_eq!(
+                quad_count(&buffer, algorithm, false) - quad_count(&buffer, algorithm, true),
+                bottom_quads
+            );
+        }
+
+        // the faces uncovered by removing a floor voxel are kept, except the downward one.
+        let options = MeshingOptions {
+            algorithm: MeshingAlgorithm::PerVoxelCubes,
+            cull_bottom_layer: true,
+            ..Default::default()
+        };
+        let mut raw_mesh = RawMesh::default();
+        mesh_buffer_raw(&buffer, &mut mesh_buffers, &mut raw_mesh, &options);
+        *buffer.voxel_at_mut([5, 0, 5].into()) = Voxel::default();
+        assert!(patch_raw_mesh(
+            &buffer,
+            &mut raw_mesh,
+            [5, 0, 5].into(),
+            &options
+        ));
+        let mut remeshed = RawMesh::default();
+        mesh_buffer_raw(&buffer, &mut mesh_buffers, &mut remeshed, &options);
+        assert_eq!(sorted_quads(&raw_mesh), sorted_quads(&remeshed));
+    }
+
+    #[test]
+    fn packed_meshes_only_keep_the_voxel_data() {
+        for algorithm in [MeshingAlgorithm::Greedy, MeshingAlgorithm::PerVoxelCubes] {
+            let options = MeshingOptions {
+                algorithm,
+                packed_positions: true,
+                ..Default::default()
+            };
+            let mut raw_mesh = RawMesh::default();
+            mesh_buffer_raw(
+                &terrain_chunk(),
+                &mut MeshBuffers::new(ChunkShape {}),
+                &mut raw_mesh,
+                &options,
+            );
+
+            let mut packed = Mesh::new(PrimitiveTopology::TriangleList);
+            raw_mesh.clone().insert_into(&mut packed, &options);
+            assert!(packed.attribute(Mesh::ATTRIBUTE_POSITION).is_none());
+            assert!(packed.attribute(Mesh::ATTRIBUTE_NORMAL).is_none());
+            assert_eq!(
+                packed.get_vertex_buffer_data().len(),
+                4 * packed.count_vertices()
+            );
+            assert_eq!(RawMesh::from_mesh(&packed, &options), Some(raw_mesh));
+        }
+    }
+
+    #[test]
+    fn buffers_too_big_to_pack_keep_their_positions() {
+        type LongShape = ndshape::ConstShape3u32<80, 4, 4>;
+        let mut buffer = VoxelBuffer::<Voxel, LongShape>::new_empty(LongShape {});
+        for x in 1..79 {
+            *buffer.voxel_at_mut([x, 1, 1].into()) = STONE;
+        }
+        let options = MeshingOptions {
+            packed_positions: true,
+            ..Default::default()
+        };
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh_buffer(
+            &buffer,
+            &mut MeshBuffers::new(LongShape {}),
+            &mut mesh,
+            &options,
+        );
+        assert!(mesh.attribute(Mesh::ATTRIBUTE_POSITION).is_some());
+        assert_eq!(
+            mesh.get_vertex_buffer_data().len(),
+            28 * mesh.count_vertices()
+        );
+    }
 }
@@ -2,6 +2,8 @@ use ilattice::extent::Extent;
 use ilattice::glam::UVec3;
 use ndshape::Shape;
 
+use crate::voxel::Voxel;
+
 /// A buffer of typed voxel data stored as a contiguous array in memory.
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -61,6 +63,12 @@ where
         &self.shape
     }
 
+    /// Returns the size in bytes of the voxel data held by this buffer.
+    #[inline]
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.data)
+    }
+
     /// Fills an extent of this buffer with the specified value.
     #[inline]
     pub fn fill_extent(&mut self, extent: Extent<UVec3>, val: V) {
@@ -73,3 +81,86 @@ where
         );
     }
 }
+
+impl<S: Shape<3, Coord = u32>> VoxelBuffer<Voxel, S> {
+    /// Returns whether all the voxels of this buffer are air.
+    pub fn is_empty(&self) -> bool {
+        self.data.iter().all(Voxel::is_empty)
+    }
+
+    /// Encodes the voxels as runs of identical voxels, each run stored as its length (a little endian `u16`) followed
+    /// by the id and the metadata of its voxels.
+    pub fn encode_rle(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut voxels = self.data.iter().peekable();
+
+        while let Some(voxel) = voxels.next() {
+            let mut len: u16 = 1;
+            while len < u16::MAX && voxels.next_if_eq(&voxel).is_some() {
+                len += 1;
+            }
+
+            encoded.extend_from_slice(&len.to_le_bytes());
+            encoded.extend_from_slice(&[voxel.id, voxel.metadata]);
+        }
+
+        encoded
+    }
+
+    /// Decodes voxels encoded with [`Self::encode_rle`] into a buffer of the specified shape.
+    /// Returns `None` if the runs don't exactly fill the buffer.
+    pub fn decode_rle(shape: S, encoded: &[u8]) -> Option<Self> {
+        let mut data = Vec::with_capacity(shape.size() as usize);
+
+        for run in encoded.chunks(4) {
+            let &[len_low, len_high, id, metadata] = run else {
+                return None;
+            };
+            let len = u16::from_le_bytes([len_low, len_high]) as usize;
+            if data.len() + len > shape.size() as usize {
+                return None;
+            }
+            data.resize(data.len() + len, Voxel { id, metadata });
+        }
+
+        (data.len() == shape.size() as usize).then(|| Self {
+            data: data.into_boxed_slice(),
+            shape,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndshape::ConstShape3u32;
+
+    type TestShape = ConstShape3u32<4, 4, 4>;
+
+    #[test]
+    fn rle_round_trip() {
+        let mut buffer = VoxelBuffer::<Voxel, TestShape>::new_empty(TestShape {});
+        buffer.fill_extent(
+            Extent::from_min_and_shape(UVec3::ZERO, UVec3::new(4, 2, 4)),
+            Voxel::new(1),
+        );
+        *buffer.voxel_at_mut(UVec3::new(1, 3, 2)) = Voxel { id: 5, metadata: 9 };
+
+        let decoded = VoxelBuffer::decode_rle(TestShape {}, &buffer.encode_rle()).unwrap();
+        assert_eq!(decoded.slice(), buffer.slice());
+    }
+
+    #[test]
+    fn rle_runs_must_fill_the_buffer_exactly() {
+        let run = |len: u16| [len.to_le_bytes().as_slice(), &[1, 0]].concat();
+
+        assert!(VoxelBuffer::decode_rle(TestShape {}, &run(64)).is_some());
+        assert!(VoxelBuffer::decode_rle(TestShape {}, &run(63)).is_none());
+        assert!(VoxelBuffer::decode_rle(TestShape {}, &run(65)).is_none());
+        assert!(VoxelBuffer::decode_rle(TestShape {}, &run(64)[..3]).is_none());
+
+        // runs overflowing the buffer are rejected before being expanded.
+        let overflowing = [run(64), vec![0xff; 4 * 1024]].concat();
+        assert!(VoxelBuffer::decode_rle(TestShape {}, &overflowing).is_none());
+    }
+}
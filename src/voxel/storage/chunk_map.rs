@@ -1,7 +1,14 @@
 use ilattice::{morton::Morton3i32, vector::Map as VecMap};
-use std::{collections::BTreeMap, hash::Hash};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    hash::Hash,
+};
 
-use bevy::{math::IVec3, prelude::Resource};
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::Resource,
+    utils::HashSet,
+};
 use ndshape::Shape;
 
 use crate::voxel::CHUNK_LENGTH;
@@ -20,6 +27,17 @@ where
     shape: S,
 }
 
+/// The voxel hit by a [`ChunkMap::raycast`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelRaycastHit {
+    /// The position of the hit voxel.
+    pub position: IVec3,
+    /// The normal of the hit voxel face, zero if the ray started inside the voxel.
+    pub normal: IVec3,
+    /// The distance travelled by the ray up to the hit face.
+    pub distance: f32,
+}
+
 #[allow(dead_code)]
 impl<V, S> ChunkMap<V, S>
 where
@@ -100,14 +118,504 @@ where
         self.chunks.extend(iter);
     }
 
+    /// Returns the number of buffers in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Calls a closure on every buffer in the map along with its minimum.
+    pub fn for_each_loaded(&self, mut f: impl FnMut(IVec3, &VoxelBuffer<V, S>)) {
+        self.chunks
+            .iter()
+            .for_each(|(key, buffer)| f(IVec3::from_array((*key).into()), buffer));
+    }
+
+    /// Calls a closure on every buffer in the map along with its minimum, allowing it to modify the buffers.
+    /// Modified chunks aren't remeshed automatically, the caller is responsible for marking them dirty.
+    pub fn for_each_loaded_mut(&mut self, mut f: impl FnMut(IVec3, &mut VoxelBuffer<V, S>)) {
+        self.chunks
+            .iter_mut()
+            .for_each(|(key, buffer)| f(IVec3::from_array((*key).into()), buffer));
+    }
+
+    /// Returns the approximate memory used by the voxel data of all the buffers in the map, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|buffer| std::mem::size_of::<Morton3i32>() + buffer.size_in_bytes())
+            .sum()
+    }
+
     /// Removes the buffer at the specified minimum and returns it if it exists.
     pub fn remove(&mut self, pos: IVec3) -> Option<VoxelBuffer<V, S>> {
         let pos = ilattice::glam::IVec3::from(pos.to_array());
         self.chunks.remove(&pos.into())
     }
 
+    /// Returns the positions of the voxels equal to the one at `start` and connected to it by their faces.
+    /// The fill stops at unloaded chunks and after `max_count` voxels.
+    pub fn flood_select(&self, start: IVec3, max_count: usize) -> Vec<IVec3> {
+        self.flood_select_by(start, max_count, |a, b| a == b)
+    }
+
+    /// Same as [`ChunkMap::flood_select`] but with a custom predicate telling whether a voxel has the same type as the starting one.
+    pub fn flood_select_by(
+        &self,
+        start: IVec3,
+        max_count: usize,
+        same_type: impl Fn(&V, &V) -> bool,
+    ) -> Vec<IVec3> {
+        const NEIGHBOURS: [IVec3; 6] = [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ];
+
+        let mut selection = Vec::new();
+
+        let Some(start_voxel) = self.voxel_at(start) else {
+            return selection;
+        };
+
+        let mut visited = HashSet::from_iter([start]);
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(pos) = queue.pop_front() {
+            if selection.len() >= max_count {
+                break;
+            }
+
+            selection.push(pos);
+
+            for neighbour in NEIGHBOURS.map(|offset| pos + offset) {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+
+                // voxel_at returns None for unloaded chunks, which stops the fill at their border.
+                if self
+                    .voxel_at(neighbour)
+                    .is_some_and(|voxel| same_type(&start_voxel, &voxel))
+                {
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        selection
+    }
+
+    /// Calls `replace` on every loaded voxel within `min..=max` and stores the returned value if there's one.
+    /// Returns the minimums of the chunks where at least one voxel changed.
+    pub fn replace_in_region(
+        &mut self,
+        min: IVec3,
+        max: IVec3,
+        mut replace: impl FnMut(V) -> Option<V>,
+    ) -> Vec<IVec3> {
+        let (min, max) = (min.min(max), min.max(max));
+        let mut modified_chunks = Vec::new();
+
+        let (chunk_min, chunk_max) = (min & self.shape_mask, max & self.shape_mask);
+        let chunk_len = CHUNK_LENGTH as i32;
+
+        for x in (chunk_min.x..=chunk_max.x).step_by(CHUNK_LENGTH as usize) {
+            for y in (chunk_min.y..=chunk_max.y).step_by(CHUNK_LENGTH as usize) {
+                for z in (chunk_min.z..=chunk_max.z).step_by(CHUNK_LENGTH as usize) {
+                    let chunk_key = IVec3::new(x, y, z);
+
+                    let Some(buffer) = self.buffer_at_mut(chunk_key) else {
+                        continue;
+                    };
+
+                    let local_min = (min - chunk_key).max(IVec3::ZERO);
+                    let local_max = (max - chunk_key).min(IVec3::splat(chunk_len - 1));
+                    let mut modified = false;
+
+                    for lx in local_min.x..=local_max.x {
+                        for ly in local_min.y..=local_max.y {
+                            for lz in local_min.z..=local_max.z {
+                                let voxel = buffer.voxel_at_mut(
+                                    ilattice::glam::IVec3::new(lx, ly, lz).as_uvec3(),
+                                );
+
+                                if let Some(new_voxel) = replace(*voxel) {
+                                    modified |= new_voxel != *voxel;
+                                    *voxel = new_voxel;
+                                }
+                            }
+                        }
+                    }
+
+                    if modified {
+                        modified_chunks.push(chunk_key);
+                    }
+                }
+            }
+        }
+
+        modified_chunks
+    }
+
+    /// Returns the topmost voxel for which `is_solid` returns true in each column of the chunk at `minimum`, with its
+    /// world position, or `None` for the columns without any. Columns are yielded row by row along the X axis.
+    /// Only the chunk itself is scanned, the columns continuing in the loaded chunks above aren't looked at.
+    /// Returns `None` if the chunk isn't loaded.
+    pub fn surface_iter<'a>(
+        &'a self,
+        minimum: IVec3,
+        is_solid: impl Fn(V) -> bool + 'a,
+    ) -> Option<impl Iterator<Item = Option<(IVec3, V)>> + 'a> {
+        let buffer = self.buffer_at(minimum)?;
+        let [size_x, size_y, size_z] = self.shape.as_array();
+
+        Some(
+            (0..size_z)
+                .flat_map(move |z| (0..size_x).map(move |x| (x, z)))
+                .map(move |(x, z)| {
+                    (0..size_y).rev().find_map(|y| {
+                        let voxel = buffer.voxel_at([x, y, z].into());
+                        is_solid(voxel)
+                            .then(|| (minimum + IVec3::new(x as i32, y as i32, z as i32), voxel))
+                    })
+                }),
+        )
+    }
+
+    /// Casts a ray in voxel space and returns the first loaded voxel for which `is_hit` returns true, walking the
+    /// crossed voxels one by one up to `max_distance`.
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut is_hit: impl FnMut(V) -> bool,
+    ) -> Option<VoxelRaycastHit> {
+        let direction = direction.try_normalize()?;
+
+        let mut position = origin.floor().as_ivec3();
+        let step = IVec3::new(
+            direction.x.signum() as i32,
+            direction.y.signum() as i32,
+            direction.z.signum() as i32,
+        );
+
+        // the ray distance needed to cross a whole voxel, and to reach the next voxel border along each axis.
+        let t_delta = direction.recip().abs();
+        let mut t_max = Vec3::ZERO;
+        for axis in 0..3 {
+            t_max[axis] = if direction[axis] == 0.0 {
+                f32::INFINITY
+            } else if direction[axis] > 0.0 {
+                (position[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis]
+            } else {
+                (origin[axis] - position[axis] as f32) * t_delta[axis]
+            };
+        }
+
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+
+        loop {
+            if self.voxel_at(position).is_some_and(&mut is_hit) {
+                return Some(VoxelRaycastHit {
+                    position,
+                    normal,
+                    distance,
+                });
+            }
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+
+            distance = t_max[axis];
+            if distance > max_distance {
+                return None;
+            }
+
+            position[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+    }
+
     #[inline]
     pub const fn shape_mask(&self) -> IVec3 {
         self.shape_mask
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{ChunkShape, Voxel};
+
+    const STONE: Voxel = Voxel::new(1);
+    const WATER: Voxel = Voxel::new(2);
+
+    // a map with empty chunks loaded at the specified keys.
+    fn chunk_map(keys: &[IVec3]) -> ChunkMap<Voxel, ChunkShape> {
+        let mut map = ChunkMap::new(ChunkShape {});
+        for key in keys {
+            map.insert_empty(*key);
+        }
+        map
+    }
+
+    fn set(map: &mut ChunkMap<Voxel, ChunkShape>, pos: IVec3, voxel: Voxel) {
+        *map.voxel_at_mut(pos).unwrap() = voxel;
+    }
+
+    fn sorted(mut positions: Vec<IVec3>) -> Vec<IVec3> {
+        positions.sort_unstable_by_key(|pos| pos.to_array());
+        positions
+    }
+
+    #[test]
+    fn flood_select_spans_connected_voxels_across_chunks() {
+        let mut map = chunk_map(&[IVec3::ZERO, IVec3::X * 32]);
+
+        // a 3x2x2 blob of water across the chunk border, walled by stone on one side.
+        let mut blob = Vec::new();
+        for x in 30..33 {
+            for y in 4..6 {
+                for z in 4..6 {
+                    blob.push(IVec3::new(x, y, z));
+                    set(&mut map, IVec3::new(x, y, z), WATER);
+                }
+            }
+        }
+        set(&mut map, IVec3::new(33, 4, 4), STONE);
+        // only touching the blob by an edge.
+        set(&mut map, IVec3::new(29, 3, 4), WATER);
+
+        let selection = map.flood_select(IVec3::new(31, 4, 5), usize::MAX);
+        assert_eq!(sorted(selection), sorted(blob));
+    }
+
+    #[test]
+    fn flood_select_stops_at_the_cap_and_unloaded_chunks() {
+        let map = chunk_map(&[IVec3::ZERO]);
+
+        let capped = map.flood_select(IVec3::splat(16), 100);
+        assert_eq!(capped.len(), 100);
+        assert_eq!(capped.iter().collect::<HashSet<_>>().len(), 100);
+
+        // the air of the loaded chunk doesn't leak into its unloaded neighbours.
+        let whole_chunk = map.flood_select(IVec3::splat(16), usize::MAX);
+        assert_eq!(whole_chunk.len(), (CHUNK_LENGTH as usize).pow(3));
+        assert!(whole_chunk
+            .iter()
+            .all(|pos| pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(32)).all()));
+
+        assert!(map.flood_select(IVec3::splat(-1), usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn replace_in_region_only_changes_matching_voxels() {
+        let mut map = chunk_map(&[IVec3::ZERO, IVec3::X * 32, IVec3::X * 64]);
+        for x in 0..96 {
+            set(&mut map, IVec3::new(x, 0, 0), STONE);
+            set(&mut map, IVec3::new(x, 1, 0), WATER);
+        }
+
+        // the region spans the first two chunks, the third one only has stone outside of it.
+        let modified = map.replace_in_region(IVec3::new(40, 1, 1), IVec3::new(10, 0, 0), |voxel| {
+            (voxel == STONE).then_some(WATER)
+        });
+        assert_eq!(modified, [IVec3::ZERO, IVec3::X * 32]);
+
+        for x in 0..96 {
+            let expected = if (10..=40).contains(&x) { WATER } else { STONE };
+            assert_eq!(map.voxel_at(IVec3::new(x, 0, 0)), Some(expected));
+            assert_eq!(map.voxel_at(IVec3::new(x, 1, 0)), Some(WATER));
+            assert_eq!(map.voxel_at(IVec3::new(x, 0, 1)), Some(Voxel::default()));
+        }
+
+        // replacing voxels by themselves doesn't count as a change.
+        let unchanged = map.replace_in_region(IVec3::ZERO, IVec3::splat(95), Some);
+        assert!(unchanged.is_empty());
+    }
+
+    #[test]
+    fn raycast_hits_the_first_voxel_across_chunks() {
+        let mut map = chunk_map(&[IVec3::ZERO, IVec3::NEG_X * 32]);
+        set(&mut map, IVec3::new(-3, 4, 4), STONE);
+        set(&mut map, IVec3::new(-6, 4, 4), STONE);
+        let is_solid = |voxel: Voxel| voxel.id != 0;
+
+        // walking towards -X from within the first chunk, into the second one.
+        let hit = map.raycast(Vec3::new(1.5, 4.5, 4.5), Vec3::NEG_X, 16.0, is_solid);
+        assert_eq!(
+            hit,
+            Some(VoxelRaycastHit {
+                position: IVec3::new(-3, 4, 4),
+                normal: IVec3::X,
+                distance: 3.5,
+            })
+        );
+
+        // a ray starting inside a voxel hits it right away.
+        let inside = map.raycast(Vec3::new(-2.5, 4.5, 4.5), Vec3::Y, 16.0, is_solid);
+        assert_eq!(
+            inside,
+            Some(VoxelRaycastHit {
+                position: IVec3::new(-3, 4, 4),
+                normal: IVec3::ZERO,
+                distance: 0.0,
+            })
+        );
+
+        // out of reach, into unloaded chunks, or without a direction.
+        assert_eq!(
+            map.raycast(Vec3::new(1.5, 4.5, 4.5), Vec3::NEG_X, 3.0, is_solid),
+            None
+        );
+        assert_eq!(
+            map.raycast(Vec3::new(1.5, 4.5, 4.5), Vec3::X, 64.0, is_solid),
+            None
+        );
+        assert_eq!(
+            map.raycast(Vec3::new(1.5, 4.5, 4.5), Vec3::ZERO, 16.0, is_solid),
+            None
+        );
+    }
+
+    #[test]
+    fn size_counts_the_voxel_data_of_every_buffer() {
+        let mut map = chunk_map(&[]);
+        assert!(map.is_empty());
+        assert_eq!(map.size_in_bytes(), 0);
+
+        map.insert_empty(IVec3::ZERO);
+        map.insert_empty(IVec3::NEG_Y * 32);
+        let buffer_size = 32 * 32 * 32 * std::mem::size_of::<Voxel>();
+        assert_eq!(
+            map.buffer_at(IVec3::ZERO).unwrap().size_in_bytes(),
+            buffer_size
+        );
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map.size_in_bytes(),
+            2 * (std::mem::size_of::<Morton3i32>() + buffer_size)
+        );
+
+        map.remove(IVec3::ZERO);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn for_each_loaded_mut_reaches_every_buffer() {
+        let keys = [IVec3::ZERO, IVec3::new(-32, 64, 0)];
+        let mut map = chunk_map(&keys);
+        map.for_each_loaded_mut(|key, buffer| {
+            *buffer.voxel_at_mut([1, 2, 3].into()) = if key == keys[0] { STONE } else { WATER };
+        });
+
+        assert_eq!(map.voxel_at(IVec3::new(1, 2, 3)), Some(STONE));
+        assert_eq!(map.voxel_at(IVec3::new(-31, 66, 3)), Some(WATER));
+    }
+
+    #[test]
+    fn raycast_passes_through_voxels_the_predicate_skips() {
+        let mut map = chunk_map(&[IVec3::ZERO]);
+        set(&mut map, IVec3::new(2, 4, 4), WATER);
+        set(&mut map, IVec3::new(5, 4, 4), STONE);
+        let origin = Vec3::new(0.5, 4.5, 4.5);
+
+        let solid = map.raycast(origin, Vec3::X, 16.0, |voxel| {
+            !voxel.is_empty() && voxel != WATER
+        });
+        assert_eq!(
+            solid,
+            Some(VoxelRaycastHit {
+                position: IVec3::new(5, 4, 4),
+                normal: IVec3::NEG_X,
+                distance: 4.5,
+            })
+        );
+
+        let non_empty = map.raycast(origin, Vec3::X, 16.0, |voxel| !voxel.is_empty());
+        assert_eq!(
+            non_empty,
+            Some(VoxelRaycastHit {
+                position: IVec3::new(2, 4, 4),
+                normal: IVec3::NEG_X,
+                distance: 1.5,
+            })
+        );
+
+        // the stone is past the maximum distance once the water is skipped.
+        assert_eq!(
+            map.raycast(origin, Vec3::X, 3.0, |voxel| !voxel.is_empty()
+                && voxel != WATER),
+            None
+        );
+    }
+
+    #[test]
+    fn surface_iter_yields_the_topmost_solid_voxel_of_each_column() {
+        let key = IVec3::new(0, -32, 0);
+        let mut map = chunk_map(&[key]);
+        // stone stacked under water in the first column, stone alone in the second.
+        set(&mut map, IVec3::new(0, -30, 0), STONE);
+        set(&mut map, IVec3::new(0, -29, 0), STONE);
+        set(&mut map, IVec3::new(0, -20, 0), WATER);
+        set(&mut map, IVec3::new(1, -10, 0), STONE);
+
+        let surface: Vec<_> = map
+            .surface_iter(key, |voxel| !voxel.is_empty() && voxel != WATER)
+            .unwrap()
+            .collect();
+        assert_eq!(surface.len(), 32 * 32);
+        assert_eq!(surface[0], Some((IVec3::new(0, -29, 0), STONE)));
+        assert_eq!(surface[1], Some((IVec3::new(1, -10, 0), STONE)));
+        // all-air columns have no surface.
+        assert_eq!(surface[2], None);
+        assert_eq!(surface[32], None);
+        assert_eq!(surface.iter().flatten().count(), 2);
+
+        let with_liquids: Vec<_> = map
+            .surface_iter(key, |voxel| !voxel.is_empty())
+            .unwrap()
+            .collect();
+        assert_eq!(with_liquids[0], Some((IVec3::new(0, -20, 0), WATER)));
+
+        assert!(map.surface_iter(IVec3::ZERO, |_| true).is_none());
+    }
+
+    #[test]
+    fn exists_agrees_with_buffer_at() {
+        let loaded = [IVec3::ZERO, IVec3::new(-32, 64, 32)];
+        let mut map = chunk_map(&loaded);
+        let keys = [
+            IVec3::ZERO,
+            IVec3::new(-32, 64, 32),
+            IVec3::X * 32,
+            IVec3::new(32, -64, -32),
+        ];
+        for key in keys {
+            assert_eq!(map.exists(key), map.buffer_at(key).is_some());
+        }
+        assert!(loaded.iter().all(|key| map.exists(*key)));
+
+        map.remove(IVec3::ZERO);
+        assert!(!map.exists(IVec3::ZERO));
+        assert!(map.buffer_at(IVec3::ZERO).is_none());
+    }
+}
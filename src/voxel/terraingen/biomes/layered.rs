@@ -1,18 +1,43 @@
 use std::ops::Div;
 
-use bevy::math::{IVec3, UVec3};
+use bevy::math::{IVec3, UVec3, Vec2, Vec3Swizzles};
 use ilattice::{glam::UVec2, prelude::Extent};
 
 use crate::voxel::{
     material::VoxelMaterial,
     materials::{Dirt, Grass},
     storage::VoxelBuffer,
-    terraingen::noise::Heightmap,
+    terraingen::noise::{self, Heightmap},
     ChunkShape, Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
 };
 
 use super::BiomeTerrainGenerator;
 
+/// A kind of decorative voxel scattered over the surface columns of a biome, e.g. grass blades.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Foliage {
+    pub voxel: Voxel,
+    /// The fraction of the surface columns getting this foliage, in `0.0..=1.0`.
+    pub density: f32,
+    /// The height range of the foliage stacks, in voxels.
+    pub min_height: u32,
+    pub max_height: u32,
+    /// Seeds the random placement, foliage kinds of a same biome should use different seeds.
+    pub seed: Vec2,
+}
+
+impl Foliage {
+    /// Returns the height of the foliage stack on the column at the specified world coordinates, if it gets one.
+    fn height_at(&self, column: Vec2) -> Option<u32> {
+        let chance = noise::rand2to1(column * 0.1, self.seed).abs();
+
+        (chance < self.density).then(|| {
+            let span = self.max_height.saturating_sub(self.min_height) + 1;
+            self.min_height + ((chance / self.density * span as f32) as u32).min(span - 1)
+        })
+    }
+}
+
 /// A biome terrain generator that applies a set of layers on top of the terrain.
 pub trait LayeredBiomeTerrainGenerator: BiomeTerrainGenerator {
     /// The height function to use for applying the biome material layers on top of the terrain.
@@ -28,6 +53,11 @@ pub trait LayeredBiomeTerrainGenerator: BiomeTerrainGenerator {
         8
     }
 
+    /// The foliage placed on the surface columns, the first kind spawning on a column wins it.
+    fn foliage(&self) -> &[Foliage] {
+        &[]
+    }
+
     fn place_decoration(
         &self,
         _key: IVec3,
@@ -80,8 +110,89 @@ impl<T: LayeredBiomeTerrainGenerator> BiomeTerrainGenerator for T {
 
                 if height.div(CHUNK_LENGTH) == (chunk_key.y as u32).div(CHUNK_LENGTH) {
                     let local_height = height.rem_euclid(CHUNK_LENGTH);
+                    place_foliage(
+                        self.foliage(),
+                        chunk_key,
+                        [pos.x, local_height, pos.y].into(),
+                        buffer,
+                    );
                     self.place_decoration(chunk_key, [pos.x, local_height, pos.y].into(), buffer);
                 }
             });
     }
 }
+
+// stacks the first foliage kind spawning on the column right above its surface voxel, if it fits in the chunk.
+fn place_foliage(
+    foliage: &[Foliage],
+    key: IVec3,
+    surface: UVec3,
+    buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+) {
+    let column = surface.xz().as_vec2() + key.xz().as_vec2();
+
+    let Some((foliage, height)) = foliage
+        .iter()
+        .find_map(|foliage| foliage.height_at(column).map(|height| (foliage, height)))
+    else {
+        return;
+    };
+
+    if surface.y + height < CHUNK_LENGTH {
+        for y in 1..=height {
+            *buffer.voxel_at_mut([surface.x, surface.y + y, surface.z].into()) = foliage.voxel;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grass(density: f32, min_height: u32, max_height: u32) -> Foliage {
+        Foliage {
+            voxel: Grass::into_voxel(),
+            density,
+            min_height,
+            max_height,
+            seed: Vec2::new(42.478_2, 8_472.243),
+        }
+    }
+
+    fn columns() -> impl Iterator<Item = Vec2> {
+        (0..64).flat_map(|x| (0..64).map(move |z| Vec2::new(x as f32, z as f32)))
+    }
+
+    #[test]
+    fn foliage_density_bounds_the_covered_columns() {
+        assert!(columns().all(|column| grass(0.0, 1, 3).height_at(column).is_none()));
+
+        let dense = grass(1.0, 1, 3);
+        assert!(columns().all(|column| dense
+            .height_at(column)
+            .is_some_and(|height| (1..=3).contains(&height))));
+
+        let sparse = grass(0.5, 1, 3);
+        let covered = columns()
+            .filter(|column| sparse.height_at(*column).is_some())
+            .count();
+        assert!(covered > 0 && covered < 64 * 64);
+        assert!(columns().all(|column| sparse.height_at(column) == sparse.height_at(column)));
+    }
+
+    #[test]
+    fn foliage_is_stacked_above_the_surface() {
+        let foliage = [grass(1.0, 2, 2)];
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+
+        place_foliage(&foliage, IVec3::ZERO, [3, 10, 5].into(), &mut buffer);
+        assert_eq!(buffer.voxel_at([3, 10, 5].into()), Voxel::EMPTY_VOXEL);
+        assert_eq!(buffer.voxel_at([3, 11, 5].into()), Grass::into_voxel());
+        assert_eq!(buffer.voxel_at([3, 12, 5].into()), Grass::into_voxel());
+        assert_eq!(buffer.voxel_at([3, 13, 5].into()), Voxel::EMPTY_VOXEL);
+
+        // stacks leaving the chunk are skipped rather than cut.
+        place_foliage(&foliage, IVec3::ZERO, [3, 30, 5].into(), &mut buffer);
+        assert_eq!(buffer.voxel_at([3, 31, 5].into()), Voxel::EMPTY_VOXEL);
+    }
+}
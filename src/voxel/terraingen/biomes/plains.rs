@@ -11,10 +11,18 @@ use crate::voxel::{
 use bevy::math::{IVec3, UVec3, Vec2, Vec3Swizzles};
 use ilattice::prelude::UVec3 as ILUVec3;
 
-use super::LayeredBiomeTerrainGenerator;
+use super::{Foliage, LayeredBiomeTerrainGenerator};
 
 pub struct BasicPlainsBiomeTerrainGenerator;
 
+const PLAINS_FOLIAGE: [Foliage; 1] = [Foliage {
+    voxel: Voxel::new(Grass::ID),
+    density: 0.5,
+    min_height: 1,
+    max_height: 2,
+    seed: Vec2::new(42.478_2, 8_472.243),
+}];
+
 impl LayeredBiomeTerrainGenerator for BasicPlainsBiomeTerrainGenerator {
     fn fill_strata(&self, layer: u32) -> Voxel {
         match layer {
@@ -23,6 +31,10 @@ impl LayeredBiomeTerrainGenerator for BasicPlainsBiomeTerrainGenerator {
         }
     }
 
+    fn foliage(&self) -> &[Foliage] {
+        &PLAINS_FOLIAGE
+    }
+
     fn place_decoration(
         &self,
         key: IVec3,
@@ -34,19 +46,6 @@ impl LayeredBiomeTerrainGenerator for BasicPlainsBiomeTerrainGenerator {
             Vec2::new(12.989, 78.233),
         );
 
-        let grass_blade_height = ((noise::rand2to1(
-            (pos.xz().as_vec2() + key.xz().as_vec2()) * 0.1,
-            Vec2::new(42.478_2, 8_472.243),
-        ) * 100.) as u32)
-            .rem_euclid(4);
-
-        if grass_blade_height > 1 && pos.y <= 29 {
-            for y in 0..grass_blade_height {
-                let position = ILUVec3::from_array(pos.to_array()) + ILUVec3::new(0, y, 0);
-                *buffer.voxel_at_mut(position) = Grass::into_voxel();
-            }
-        }
-
         // Let's put some rock boulders in the plains to populate a lil bit
         let rock_spawn_chance = noise::rand2to1(
             (pos.xz().as_vec2() + key.xz().as_vec2()) * 0.1,
@@ -6,17 +6,37 @@ use crate::voxel::{
     materials::{Bedrock, Rock, Water},
     sdf,
     storage::VoxelBuffer,
+    world::WorldHeightLimits,
     ChunkShape, Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
 };
 
 use super::noise::Heightmap;
 
-/// Generate the world bottom border for a chunk.
-pub fn terrain_generate_world_bottom_border(buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
-    buffer.fill_extent(
-        Extent::from_min_and_shape(UVec3::ZERO, UVec3::new(CHUNK_LENGTH, 2, CHUNK_LENGTH)),
-        Bedrock::into_voxel(),
-    )
+/// Fill the chunk with bedrock below the world floor and clear it above the world ceiling.
+pub fn terrain_apply_height_limits(
+    buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    key: IVec3,
+    limits: &WorldHeightLimits,
+) {
+    let floor = (limits.floor - key.y).clamp(0, CHUNK_LENGTH as i32) as u32;
+    let ceiling = (limits.ceiling - key.y).clamp(0, CHUNK_LENGTH as i32) as u32;
+
+    if floor > 0 {
+        buffer.fill_extent(
+            Extent::from_min_and_shape(UVec3::ZERO, UVec3::new(CHUNK_LENGTH, floor, CHUNK_LENGTH)),
+            Bedrock::into_voxel(),
+        );
+    }
+
+    if ceiling < CHUNK_LENGTH {
+        buffer.fill_extent(
+            Extent::from_min_and_shape(
+                UVec3::new(0, ceiling, 0),
+                UVec3::new(CHUNK_LENGTH, CHUNK_LENGTH - ceiling, CHUNK_LENGTH),
+            ),
+            Voxel::EMPTY_VOXEL,
+        );
+    }
 }
 
 /// Carve the general terrain shape for a chunk.
@@ -39,8 +59,7 @@ pub fn terrain_carve_heightmap(
         .for_each(|pos| {
             let local_height = heighmap
                 .get(pos.into())
-                .checked_sub(key.y as u32)
-                .unwrap_or_default()
+                .saturating_sub(key.y as u32)
                 .min(CHUNK_LENGTH);
 
             for h in 0..local_height {
@@ -0,0 +1,226 @@
+use bevy::math::IVec3;
+use noise::{MultiFractal, NoiseFn};
+
+use crate::voxel::{
+    material::VoxelMaterial,
+    materials::{Dirt, Grass, Rock},
+    storage::VoxelBuffer,
+    ChunkShape, Voxel, CHUNK_LENGTH,
+};
+
+/// A terrain filled from a 3D density field, voxels being solid where the density is positive.
+///
+/// The density decreases with the height above a 2D surface height field and is displaced by a 3D noise, which carves
+/// overhangs, arches and caves into the surface and lifts floating islands above it, all in a single pass.
+/// The field is sampled in world space, so the terrain lines up across chunks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensityTerrainGenerator {
+    /// The height around which the terrain surface lies.
+    pub base_height: f32,
+    /// The height difference between the lowest and the highest points of the surface height field, halved.
+    pub height_amplitude: f32,
+    /// The horizontal frequency of the surface height field.
+    pub height_frequency: f64,
+    /// The distance in voxels the 3D noise displaces the surface by at most.
+    /// Overhangs and floating islands appear once it exceeds the local slopes of the surface height field.
+    pub density_amplitude: f32,
+    /// The horizontal frequency of the 3D noise.
+    pub density_frequency: f64,
+    /// The vertical frequency of the 3D noise relative to the horizontal one.
+    /// Values above 1 flatten the features into layered ledges.
+    pub vertical_squash: f64,
+}
+
+impl Default for DensityTerrainGenerator {
+    fn default() -> Self {
+        Self {
+            base_height: 132.0,
+            height_amplitude: 24.0,
+            height_frequency: 0.004,
+            density_amplitude: 18.0,
+            density_frequency: 0.015,
+            vertical_squash: 1.5,
+        }
+    }
+}
+
+/// The noises of a [`DensityTerrainGenerator`], seeded from the world seed.
+struct DensityField<'a> {
+    generator: &'a DensityTerrainGenerator,
+    surface: noise::Fbm<noise::SuperSimplex>,
+    detail: noise::Fbm<noise::Perlin>,
+}
+
+impl<'a> DensityField<'a> {
+    fn new(generator: &'a DensityTerrainGenerator, surface_seed: u32, density_seed: u32) -> Self {
+        Self {
+            generator,
+            surface: noise::Fbm::<noise::SuperSimplex>::new(surface_seed)
+                .set_octaves(4)
+                .set_frequency(generator.height_frequency),
+            detail: noise::Fbm::<noise::Perlin>::new(density_seed)
+                .set_octaves(3)
+                .set_frequency(generator.density_frequency),
+        }
+    }
+
+    fn surface_height(&self, x: i32, z: i32) -> f32 {
+        (self.surface.get([x as f64, z as f64]) as f32)
+            .mul_add(self.generator.height_amplitude, self.generator.base_height)
+    }
+
+    fn density(&self, surface_height: f32, pos: IVec3) -> f32 {
+        let detail = self.detail.get([
+            pos.x as f64,
+            pos.y as f64 * self.generator.vertical_squash,
+            pos.z as f64,
+        ]) as f32;
+        detail.mul_add(
+            self.generator.density_amplitude,
+            surface_height - pos.y as f32,
+        )
+    }
+}
+
+impl DensityTerrainGenerator {
+    /// The spacing in voxels of the density samples, the density of the voxels in between is interpolated.
+    const CELL: u32 = 4;
+    /// The number of voxels under the surface covered with dirt.
+    const DIRT_DEPTH: u32 = 3;
+
+    /// Fills the chunk with the solid voxels of the density field, covering its surfaces with grass and dirt.
+    pub fn generate(
+        &self,
+        chunk_key: IVec3,
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        surface_seed: u32,
+        density_seed: u32,
+    ) {
+        let field = DensityField::new(self, surface_seed, density_seed);
+
+        // the samples lie on a grid aligned in world space, with a row of cells above the chunk telling how deep under
+        // the surface its topmost voxels are.
+        let cells = CHUNK_LENGTH / Self::CELL;
+        let [size_x, size_y, size_z] = [cells + 1, cells + 2, cells + 1].map(|len| len as usize);
+        let mut samples = vec![0f32; size_x * size_y * size_z];
+        for z in 0..size_z {
+            for x in 0..size_x {
+                let column = chunk_key + IVec3::new(x as i32, 0, z as i32) * Self::CELL as i32;
+                let surface_height = field.surface_height(column.x, column.z);
+                for y in 0..size_y {
+                    let pos = column + IVec3::Y * (y * Self::CELL as usize) as i32;
+                    samples[(z * size_y + y) * size_x + x] = field.density(surface_height, pos);
+                }
+            }
+        }
+
+        let sample = |x: usize, y: usize, z: usize| samples[(z * size_y + y) * size_x + x];
+        let density = |x: u32, y: u32, z: u32| {
+            let (cx, cy, cz) = (
+                (x / Self::CELL) as usize,
+                (y / Self::CELL) as usize,
+                (z / Self::CELL) as usize,
+            );
+            let [tx, ty, tz] = [x, y, z].map(|v| (v % Self::CELL) as f32 / Self::CELL as f32);
+            let lerp = |a: f32, b: f32, t: f32| (b - a).mul_add(t, a);
+            let plane = |y: usize| {
+                lerp(
+                    lerp(sample(cx, y, cz), sample(cx + 1, y, cz), tx),
+                    lerp(sample(cx, y, cz + 1), sample(cx + 1, y, cz + 1), tx),
+                    tz,
+                )
+            };
+            lerp(plane(cy), plane(cy + 1), ty)
+        };
+
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                // the number of solid voxels down from the last air voxel, from above the chunk to its bottom.
+                let mut depth = 0;
+                for y in (0..CHUNK_LENGTH + Self::CELL).rev() {
+                    if density(x, y, z) <= 0.0 {
+                        depth = 0;
+                        continue;
+                    }
+
+                    depth += 1;
+                    if y < CHUNK_LENGTH {
+                        *buffer.voxel_at_mut([x, y, z].into()) = match depth {
+                            1 => Grass::into_voxel(),
+                            d if d <= Self::DIRT_DEPTH + 1 => Dirt::into_voxel(),
+                            _ => Rock::into_voxel(),
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the voxel at the specified world position, generating its chunk, which may be offset from the chunk grid.
+    fn voxel_in_chunk(generator: &DensityTerrainGenerator, chunk_key: IVec3, pos: IVec3) -> Voxel {
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        generator.generate(chunk_key, &mut buffer, 1, 2);
+        buffer.voxel_at((pos - chunk_key).as_uvec3().to_array().into())
+    }
+
+    #[test]
+    fn offset_chunks_match_their_neighbors() {
+        let generator = DensityTerrainGenerator::default();
+        let half = CHUNK_LENGTH as i32 / 2;
+        let y = 128;
+        let offset_key = IVec3::new(half, y, half);
+        let mut offset = VoxelBuffer::new_empty(ChunkShape {});
+        generator.generate(offset_key, &mut offset, 1, 2);
+
+        // the offset chunk overlaps a quarter of each of its four neighbors on the chunk grid.
+        let mut neighbors = Vec::new();
+        for (x, z) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let key = IVec3::new(x, 0, z) * CHUNK_LENGTH as i32 + IVec3::Y * y;
+            let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+            generator.generate(key, &mut buffer, 1, 2);
+            neighbors.push((key, buffer));
+        }
+
+        let mut solid = 0;
+        for z in 0..CHUNK_LENGTH {
+            for y in 0..CHUNK_LENGTH {
+                for x in 0..CHUNK_LENGTH {
+                    let pos = offset_key + IVec3::new(x as i32, y as i32, z as i32);
+                    let (key, buffer) = neighbors
+                        .iter()
+                        .find(|(key, _)| {
+                            (pos - *key).cmpge(IVec3::ZERO).all()
+                                && (pos - *key).cmplt(IVec3::splat(CHUNK_LENGTH as i32)).all()
+                        })
+                        .unwrap();
+                    let voxel = offset.voxel_at([x, y, z].into());
+                    assert_eq!(
+                        voxel,
+                        buffer.voxel_at((pos - *key).as_uvec3().to_array().into()),
+                        "voxel {pos} differs"
+                    );
+                    solid += usize::from(!voxel.is_empty());
+                }
+            }
+        }
+        // the chunk straddles the surface.
+        assert!(solid > 0 && solid < CHUNK_LENGTH.pow(3) as usize);
+    }
+
+    #[test]
+    fn the_terrain_is_solid_deep_down_and_empty_high_up() {
+        let generator = DensityTerrainGenerator::default();
+        let deep = IVec3::new(0, 32, 0);
+        let high = IVec3::new(0, 256, 0);
+        assert_eq!(
+            voxel_in_chunk(&generator, deep, deep + IVec3::splat(5)),
+            Rock::into_voxel()
+        );
+        assert!(voxel_in_chunk(&generator, high, high + IVec3::splat(5)).is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use bevy::math::IVec3;
+use ilattice::{glam::UVec3, prelude::Extent};
+
+use crate::voxel::{
+    material::VoxelMaterial,
+    materials::{Bedrock, Dirt, Grass, Rock},
+    storage::VoxelBuffer,
+    ChunkShape, Voxel, CHUNK_LENGTH,
+};
+
+/// A horizontal layer of a flat world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlatWorldLayer {
+    pub voxel: Voxel,
+    /// The thickness of the layer, in voxels.
+    pub thickness: u32,
+}
+
+impl FlatWorldLayer {
+    pub const fn new(voxel: Voxel, thickness: u32) -> Self {
+        Self { voxel, thickness }
+    }
+}
+
+/// A terrain made of flat layers stacked on top of each other, with air above them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatWorldGenerator {
+    /// The height of the bottom of the lowest layer, voxels below it are left empty.
+    pub base_height: i32,
+    /// The layers, from the bottom up.
+    pub layers: Vec<FlatWorldLayer>,
+}
+
+impl Default for FlatWorldGenerator {
+    fn default() -> Self {
+        Self {
+            base_height: 0,
+            layers: vec![
+                FlatWorldLayer::new(Bedrock::into_voxel(), 2),
+                FlatWorldLayer::new(Rock::into_voxel(), 56),
+                FlatWorldLayer::new(Dirt::into_voxel(), 5),
+                FlatWorldLayer::new(Grass::into_voxel(), 1),
+            ],
+        }
+    }
+}
+
+impl FlatWorldGenerator {
+    /// Returns the height right above the topmost layer.
+    pub fn surface_height(&self) -> i32 {
+        self.base_height
+            + self
+                .layers
+                .iter()
+                .map(|layer| layer.thickness as i32)
+                .sum::<i32>()
+    }
+
+    /// Fills the chunk with the part of the layers it intersects.
+    pub fn generate(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
+        let mut layer_min = self.base_height;
+
+        for layer in &self.layers {
+            let layer_max = layer_min + layer.thickness as i32;
+            let min = (layer_min - chunk_key.y).clamp(0, CHUNK_LENGTH as i32) as u32;
+            let max = (layer_max - chunk_key.y).clamp(0, CHUNK_LENGTH as i32) as u32;
+
+            if max > min {
+                buffer.fill_extent(
+                    Extent::from_min_and_shape(
+                        UVec3::new(0, min, 0),
+                        UVec3::new(CHUNK_LENGTH, max - min, CHUNK_LENGTH),
+                    ),
+                    layer.voxel,
+                );
+            }
+
+            layer_min = layer_max;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the voxels of the column at the origin of the chunks stacked from y = 0, in world heights.
+    fn column(generator: &FlatWorldGenerator, num_chunks: i32) -> Vec<Voxel> {
+        (0..num_chunks)
+            .flat_map(|chunk| {
+                let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+                generator.generate(IVec3::Y * chunk * CHUNK_LENGTH as i32, &mut buffer);
+                (0..CHUNK_LENGTH).map(move |y| buffer.voxel_at([7, y, 9].into()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layers_are_stacked_across_chunks() {
+        let generator = FlatWorldGenerator::default();
+        assert_eq!(generator.surface_height(), 64);
+
+        let column = column(&generator, 3);
+        assert!(column[..2]
+            .iter()
+            .all(|voxel| *voxel == Bedrock::into_voxel()));
+        assert!(column[2..58]
+            .iter()
+            .all(|voxel| *voxel == Rock::into_voxel()));
+        assert!(column[58..63]
+            .iter()
+            .all(|voxel| *voxel == Dirt::into_voxel()));
+        assert_eq!(column[63], Grass::into_voxel());
+        assert!(column[64..]
+            .iter()
+            .all(|voxel| *voxel == Voxel::EMPTY_VOXEL));
+    }
+
+    #[test]
+    fn layers_start_at_the_base_height() {
+        let generator = FlatWorldGenerator {
+            base_height: 30,
+            layers: vec![FlatWorldLayer::new(Rock::into_voxel(), 4)],
+        };
+        assert_eq!(generator.surface_height(), 34);
+
+        let column = column(&generator, 2);
+        assert!(column[..30]
+            .iter()
+            .all(|voxel| *voxel == Voxel::EMPTY_VOXEL));
+        assert!(column[30..34]
+            .iter()
+            .all(|voxel| *voxel == Rock::into_voxel()));
+        assert!(column[34..]
+            .iter()
+            .all(|voxel| *voxel == Voxel::EMPTY_VOXEL));
+    }
+}
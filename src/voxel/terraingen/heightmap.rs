@@ -0,0 +1,203 @@
+use bevy::{math::IVec3, prelude::Image, render::render_resource::TextureFormat};
+use ilattice::{glam::UVec2, prelude::Extent};
+
+use crate::voxel::{
+    material::VoxelMaterial,
+    materials::{Dirt, Grass, Rock},
+    storage::VoxelBuffer,
+    ChunkShape, Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
+};
+
+/// How the heightmap is sampled beyond the image bounds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeightmapEdge {
+    /// Repeat the image.
+    #[default]
+    Tile,
+    /// Extend the image border pixels.
+    Clamp,
+}
+
+/// A terrain heightmap read from images, with one pixel per voxel column.
+pub struct ImageHeightmap {
+    width: u32,
+    depth: u32,
+    heights: Vec<i32>,
+    surface: Option<(u32, u32, Vec<u8>)>,
+    edge: HeightmapEdge,
+}
+
+/// Returns the value of the first channel of each pixel of the image normalized to `0.0..=1.0`, along the image size.
+fn image_channel_values(image: &Image) -> Option<(u32, u32, Vec<f32>)> {
+    let size = image.texture_descriptor.size;
+    let format = image.texture_descriptor.format;
+    let pixel_size = format.block_size(None)? as usize;
+
+    let values = image
+        .data
+        .chunks_exact(pixel_size)
+        .map(|pixel| match format {
+            TextureFormat::R16Uint | TextureFormat::R16Unorm => {
+                u16::from_le_bytes([pixel[0], pixel[1]]) as f32 / u16::MAX as f32
+            }
+            _ => pixel[0] as f32 / u8::MAX as f32,
+        })
+        .collect();
+
+    Some((size.width, size.height, values))
+}
+
+impl ImageHeightmap {
+    /// Creates a heightmap from a grayscale image, mapping black to `base_height` and white to `base_height + max_height`.
+    /// The red channel of the optional surface image holds the material id of the topmost voxel of each column.
+    pub fn from_images(
+        heightmap: &Image,
+        surface: Option<&Image>,
+        base_height: i32,
+        max_height: u32,
+        edge: HeightmapEdge,
+    ) -> Option<Self> {
+        let (width, depth, values) = image_channel_values(heightmap)?;
+        let heights = values
+            .into_iter()
+            .map(|value| base_height + (value * max_height as f32).round() as i32)
+            .collect();
+
+        let surface = match surface {
+            Some(surface) => {
+                let (width, depth, values) = image_channel_values(surface)?;
+                let ids = values
+                    .into_iter()
+                    .map(|value| (value * u8::MAX as f32).round() as u8)
+                    .collect();
+                Some((width, depth, ids))
+            }
+            None => None,
+        };
+
+        (width > 0 && depth > 0).then_some(Self {
+            width,
+            depth,
+            heights,
+            surface,
+            edge,
+        })
+    }
+
+    fn pixel_index(&self, x: i32, z: i32, width: u32, depth: u32) -> usize {
+        let (x, z) = match self.edge {
+            HeightmapEdge::Tile => (x.rem_euclid(width as i32), z.rem_euclid(depth as i32)),
+            HeightmapEdge::Clamp => (x.clamp(0, width as i32 - 1), z.clamp(0, depth as i32 - 1)),
+        };
+
+        (z as u32 * width + x as u32) as usize
+    }
+
+    /// Returns the height of the terrain surface of the column at the specified world voxel coordinates.
+    /// The topmost solid voxel of the column is right below it.
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        self.heights[self.pixel_index(x, z, self.width, self.depth)]
+    }
+
+    /// Returns the voxel forming the surface of the column at the specified world voxel coordinates.
+    pub fn surface_at(&self, x: i32, z: i32) -> Voxel {
+        self.surface
+            .as_ref()
+            .map_or(Grass::into_voxel(), |(width, depth, ids)| {
+                Voxel::new(ids[self.pixel_index(x, z, *width, *depth)])
+            })
+    }
+
+    /// Fills the chunk with the terrain described by the heightmap.
+    pub fn generate(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
+        const DIRT_DEPTH: i32 = 3;
+
+        Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
+            .iter2()
+            .for_each(|pos| {
+                let (x, z) = (chunk_key.x + pos.x as i32, chunk_key.z + pos.y as i32);
+                let height = self.height_at(x, z);
+                let local_height = (height - chunk_key.y).clamp(0, CHUNK_LENGTH_U as i32) as u32;
+
+                for h in 0..local_height {
+                    let depth = height - (chunk_key.y + h as i32);
+                    *buffer.voxel_at_mut([pos.x, h, pos.y].into()) = match depth {
+                        1 => self.surface_at(x, z),
+                        d if d <= DIRT_DEPTH + 1 => Dirt::into_voxel(),
+                        _ => Rock::into_voxel(),
+                    };
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+    // a 2x2 heightmap going from black to white.
+    fn gradient_image() -> Image {
+        Image::new(
+            Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![0, 85, 170, 255],
+            TextureFormat::R8Unorm,
+        )
+    }
+
+    fn heights(heightmap: &ImageHeightmap, columns: &[(i32, i32)]) -> Vec<i32> {
+        columns
+            .iter()
+            .map(|(x, z)| heightmap.height_at(*x, *z))
+            .collect()
+    }
+
+    #[test]
+    fn column_heights_follow_the_image() {
+        let columns = [(0, 0), (1, 0), (0, 1), (1, 1), (2, 0), (-1, 0), (3, 5)];
+        let inside = [10, 20, 30, 40];
+
+        let tiled =
+            ImageHeightmap::from_images(&gradient_image(), None, 10, 30, HeightmapEdge::Tile)
+                .unwrap();
+        assert_eq!(
+            heights(&tiled, &columns),
+            [&inside[..], &[10, 20, 40]].concat()
+        );
+
+        let clamped =
+            ImageHeightmap::from_images(&gradient_image(), None, 10, 30, HeightmapEdge::Clamp)
+                .unwrap();
+        assert_eq!(
+            heights(&clamped, &columns),
+            [&inside[..], &[20, 10, 40]].concat()
+        );
+    }
+
+    #[test]
+    fn chunks_are_filled_up_to_the_column_heights() {
+        let heightmap =
+            ImageHeightmap::from_images(&gradient_image(), None, 8, 20, HeightmapEdge::Tile)
+                .unwrap();
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        heightmap.generate(IVec3::ZERO, &mut buffer);
+
+        for (x, z, height) in [(0, 0, 8), (1, 0, 15), (0, 1, 21), (1, 1, 28)] {
+            assert_eq!(
+                buffer.voxel_at([x, height - 1, z].into()),
+                Grass::into_voxel()
+            );
+            assert_eq!(
+                buffer.voxel_at([x, height - 2, z].into()),
+                Dirt::into_voxel()
+            );
+            assert_eq!(buffer.voxel_at([x, 0, z].into()), Rock::into_voxel());
+            assert_eq!(buffer.voxel_at([x, height, z].into()), Voxel::default());
+        }
+    }
+}
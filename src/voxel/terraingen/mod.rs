@@ -2,33 +2,85 @@ use float_ord::FloatOrd;
 use std::{collections::BTreeMap, sync::RwLock};
 
 use bevy::{
-    math::{IVec3, Vec3Swizzles},
-    prelude::Plugin,
+    asset::LoadState,
+    log::warn,
+    math::{IVec3, UVec2, Vec3Swizzles},
+    prelude::{
+        resource_changed, AssetServer, Assets, Commands, Handle, Image, IntoSystemConfigs, Plugin,
+        Res, ResMut, Resource, Update,
+    },
 };
 use once_cell::sync::Lazy;
 
 use self::{
     biomes::{BiomeTerrainGenerator, IntoBoxedTerrainGenerator},
-    common::terrain_generate_world_bottom_border,
-    noise::{generate_heightmap_data, Heightmap},
+    pipeline::{default_passes, ChunkGenContext, TerrainGenPass},
 };
 
-use super::{storage::VoxelBuffer, ChunkShape, Voxel, CHUNK_LENGTH_U};
+use super::{
+    storage::VoxelBuffer, world::WorldHeightLimits, ChunkCommandQueue, ChunkEntities, ChunkShape,
+    ModifiedChunks, Voxel,
+};
 
 mod biomes;
 
+/// terrain filled from a 3D density field, with overhangs and floating islands.
+pub mod density;
+pub use density::DensityTerrainGenerator;
+
+/// superflat terrain made of configurable layers.
+pub mod flat;
+pub use flat::{FlatWorldGenerator, FlatWorldLayer};
+
+/// terrain generation from heightmap images.
+pub mod heightmap;
+pub use heightmap::{HeightmapEdge, ImageHeightmap};
+
 /// noise functions ported over from C / GLSL code
 pub mod noise;
 
 /// common functions used by all terrain generators
 pub mod common;
 
+/// the ordered passes generating the terrain of a chunk.
+pub mod pipeline;
+
+/// river channels carved into the noise terrain.
+pub mod rivers;
+
 // Terrain generator singleton.
 pub static TERRAIN_GENERATOR: Lazy<RwLock<TerrainGenerator>> = Lazy::new(Default::default);
 
+/// Where the overall shape of the terrain comes from.
 #[derive(Default)]
+pub(crate) enum TerrainShape {
+    #[default]
+    Noise,
+    Heightmap(ImageHeightmap),
+    Flat(FlatWorldGenerator),
+    Density(DensityTerrainGenerator),
+}
+
 pub struct TerrainGenerator {
     biomes_map: BTreeMap<FloatOrd<f32>, Box<dyn BiomeTerrainGenerator>>,
+    // the world seed, the noise of each sub-generator is seeded from it with its own salt.
+    seed: u32,
+    shape: TerrainShape,
+    passes: Vec<Box<dyn TerrainGenPass>>,
+    // bumped by every change to the generated terrain, so the loaded chunks are generated again.
+    revision: u64,
+}
+
+impl Default for TerrainGenerator {
+    fn default() -> Self {
+        Self {
+            biomes_map: Default::default(),
+            seed: 0,
+            shape: TerrainShape::default(),
+            passes: default_passes(),
+            revision: 0,
+        }
+    }
 }
 
 impl TerrainGenerator {
@@ -38,58 +90,516 @@ impl TerrainGenerator {
         biome: Box<dyn BiomeTerrainGenerator>,
     ) -> &mut Self {
         self.biomes_map.insert(FloatOrd(chance), biome);
+        self.revision += 1;
         self
     }
 
-    //returns the biome with the closest temp / humidity
-    #[allow(clippy::borrowed_box)]
-    fn biome_at(&self, chunk_key: IVec3) -> &Box<dyn BiomeTerrainGenerator> {
+    /// Registers the plains, desert and snowy plains biomes the game world is generated with.
+    pub fn register_default_biomes(&mut self) -> &mut Self {
+        self.register_biome_generator(
+            0.0f32,
+            biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        )
+        .register_biome_generator(
+            0.8f32,
+            biomes::BasicDesertBiomeTerrainGenerator.into_boxed_generator(),
+        )
+        .register_biome_generator(
+            3.21,
+            biomes::BasicSnowyPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        )
+    }
+
+    /// Sets the heightmap the terrain is generated from, or `None` to generate it from noise.
+    pub fn set_heightmap(&mut self, heightmap: Option<ImageHeightmap>) -> &mut Self {
+        self.shape = heightmap.map_or(TerrainShape::Noise, TerrainShape::Heightmap);
+        self.revision += 1;
+        self
+    }
+
+    /// Sets the flat layers the terrain is made of, or `None` to generate it from noise.
+    pub fn set_flat_world(&mut self, flat: Option<FlatWorldGenerator>) -> &mut Self {
+        self.shape = flat.map_or(TerrainShape::Noise, TerrainShape::Flat);
+        self.revision += 1;
+        self
+    }
+
+    /// Sets the density field the terrain is filled from, or `None` to generate it from noise.
+    pub fn set_density_terrain(&mut self, density: Option<DensityTerrainGenerator>) -> &mut Self {
+        self.shape = density.map_or(TerrainShape::Noise, TerrainShape::Density);
+        self.revision += 1;
+        self
+    }
+
+    /// Returns whether the terrain is shaped from the noise heightmap, rather than from a heightmap image, flat layers
+    /// or a density field.
+    pub fn uses_noise(&self) -> bool {
+        matches!(self.shape, TerrainShape::Noise)
+    }
+
+    /// Sets the world seed, the chunks generated from then on use the new terrain.
+    pub fn set_seed(&mut self, seed: u32) -> &mut Self {
+        if self.seed != seed {
+            self.seed = seed;
+            self.revision += 1;
+        }
+        self
+    }
+
+    /// Returns the world seed.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Appends a pass to the terrain generation pipeline, running after all the current ones.
+    pub fn add_pass(&mut self, pass: impl TerrainGenPass) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self.revision += 1;
+        self
+    }
+
+    /// Gives access to the passes of the terrain generation pipeline, to insert, remove or reorder them.
+    /// The loaded chunks are generated again, as the passes may have changed.
+    pub fn passes_mut(&mut self) -> &mut Vec<Box<dyn TerrainGenPass>> {
+        self.revision += 1;
+        &mut self.passes
+    }
+
+    /// Returns a number which changes whenever the generated terrain does, e.g. when the seed or the terrain shape is
+    /// set or a pass is added.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // returns the point of the biome distribution the chunk falls on.
+    fn biome_point_at(&self, chunk_key: IVec3) -> FloatOrd<f32> {
         const BIOME_INVSCALE: f32 = 0.001;
 
-        let coords = noise::voronoi(chunk_key.xzy().truncate().as_vec2() * BIOME_INVSCALE);
-        let p = FloatOrd(noise::rand2to1i(coords));
+        // moves the biome cells around by a whole number of cells depending on the seed.
+        let seed = noise::derive_seed(self.seed, noise::BIOMES_SALT);
+        let offset = UVec2::new(seed & 0x3ff, (seed >> 10) & 0x3ff).as_vec2();
+        let coords = noise::voronoi(chunk_key.xzy().truncate().as_vec2() * BIOME_INVSCALE + offset);
+        FloatOrd(noise::rand2to1i(coords))
+    }
 
+    //returns the biome with the closest temp / humidity
+    #[allow(clippy::borrowed_box)]
+    fn biome_at(&self, chunk_key: IVec3) -> &Box<dyn BiomeTerrainGenerator> {
         self.biomes_map
-            .range(..=p)
+            .range(..=self.biome_point_at(chunk_key))
             .last()
             .map_or(self.biomes_map.first_key_value().unwrap().1, |x| x.1)
     }
 
-    pub fn generate(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
-        let biome = self.biome_at(chunk_key);
-        let noise = generate_heightmap_data(chunk_key, CHUNK_LENGTH_U);
+    /// Returns the index of the biome of the chunk, in the order of the biomes distribution.
+    pub fn biome_index_at(&self, chunk_key: IVec3) -> usize {
+        self.biomes_map
+            .range(..=self.biome_point_at(chunk_key))
+            .count()
+            .saturating_sub(1)
+    }
+
+    pub fn generate(
+        &self,
+        chunk_key: IVec3,
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        height_limits: &WorldHeightLimits,
+    ) {
+        let mut ctx = ChunkGenContext {
+            chunk_key,
+            buffer,
+            height_limits,
+            generator: self,
+            heights: None,
+        };
+
+        for pass in &self.passes {
+            pass.apply(&mut ctx);
+        }
+    }
+}
+
+/// Settings for generating the terrain from heightmap images.
+#[derive(Clone, Debug)]
+pub struct HeightmapTerrainSettings {
+    /// Asset path of the grayscale heightmap image, one pixel per voxel column.
+    pub heightmap: String,
+    /// Asset path of an optional image whose red channel holds the material id of the surface voxels.
+    pub surface: Option<String>,
+    /// The terrain height of black heightmap pixels.
+    pub base_height: i32,
+    /// The terrain height difference between black and white heightmap pixels.
+    pub max_height: u32,
+    pub edge: HeightmapEdge,
+}
+
+/// Selects how the terrain is generated.
+#[derive(Resource, Clone, Debug, Default)]
+pub enum TerrainSource {
+    /// Noise based terrain with biomes.
+    #[default]
+    Noise,
+    Heightmap(HeightmapTerrainSettings),
+    /// Layers of voxels stacked up to a flat surface.
+    Flat(FlatWorldGenerator),
+    /// Terrain filled from a 3D density field, with overhangs and floating islands.
+    Density(DensityTerrainGenerator),
+}
+
+/// The heightmap images being loaded before they replace the terrain generator source.
+#[derive(Resource)]
+struct PendingHeightmap {
+    heightmap: Handle<Image>,
+    surface: Option<Handle<Image>>,
+    settings: HeightmapTerrainSettings,
+}
+
+/// What happens to the chunks edited by the player when the terrain generator changes, e.g. its seed.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EditedChunksOnTerrainChange {
+    /// Edited chunks are generated again like the others, their edits are lost.
+    #[default]
+    Regenerate,
+    /// Edited chunks stay loaded with their edits, leaving seams with the new terrain around them until unloaded.
+    Keep,
+}
+
+/// The terrain generator revision the loaded chunks were generated with, see [`TerrainGenerator::revision`].
+#[derive(Resource)]
+struct LoadedTerrainRevision(u64);
+
+/// Unloads the chunks generated before the terrain generator last changed, so they're generated again from the new
+/// terrain. The in flight generation and meshing tasks of the chunks are dropped along with their entities.
+fn reload_chunks_on_terrain_change(
+    mut loaded_revision: ResMut<LoadedTerrainRevision>,
+    policy: Res<EditedChunksOnTerrainChange>,
+    chunk_entities: Res<ChunkEntities>,
+    modified_chunks: Res<ModifiedChunks>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+) {
+    let revision = TERRAIN_GENERATOR.read().unwrap().revision();
+    if loaded_revision.0 == revision {
+        return;
+    }
+    loaded_revision.0 = revision;
+
+    chunk_command_queue.queue_unload(chunk_entities.iter_keys().filter(|key| {
+        *policy == EditedChunksOnTerrainChange::Regenerate || !modified_chunks.is_modified(**key)
+    }));
+}
 
-        let noise_map = Heightmap::<CHUNK_LENGTH_U, CHUNK_LENGTH_U>::from_slice(&noise);
+fn load_terrain_source(
+    source: Res<TerrainSource>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    commands.remove_resource::<PendingHeightmap>();
 
-        common::terrain_carve_heightmap(buffer, chunk_key, &noise_map);
+    match &*source {
+        TerrainSource::Noise => {
+            let mut generator = TERRAIN_GENERATOR.write().unwrap();
+            if !generator.uses_noise() {
+                generator.set_heightmap(None);
+            }
+        }
+        TerrainSource::Flat(flat) => {
+            TERRAIN_GENERATOR
+                .write()
+                .unwrap()
+                .set_flat_world(Some(flat.clone()));
+        }
+        TerrainSource::Density(density) => {
+            TERRAIN_GENERATOR
+                .write()
+                .unwrap()
+                .set_density_terrain(Some(density.clone()));
+        }
+        TerrainSource::Heightmap(settings) => commands.insert_resource(PendingHeightmap {
+            heightmap: asset_server.load(&settings.heightmap),
+            surface: settings
+                .surface
+                .as_ref()
+                .map(|path| asset_server.load(path)),
+            settings: settings.clone(),
+        }),
+    }
+}
 
-        biome.carve_terrain(chunk_key, noise_map, buffer);
-        biome.decorate_terrain(chunk_key, noise_map, buffer);
+/// Switches the terrain generator to the pending heightmap once its images are loaded.
+fn apply_pending_heightmap(
+    pending: Option<Res<PendingHeightmap>>,
+    asset_server: Res<AssetServer>,
+    images: Res<Assets<Image>>,
+    mut commands: Commands,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+
+    let mut handles = std::iter::once(&pending.heightmap).chain(pending.surface.as_ref());
+    if handles.any(|handle| asset_server.get_load_state(handle) == LoadState::Failed) {
+        warn!(
+            "Failed to load the terrain heightmap {:?}, keeping the current terrain.",
+            pending.settings.heightmap
+        );
+        commands.remove_resource::<PendingHeightmap>();
+        return;
+    }
 
-        if chunk_key.y == 0 {
-            terrain_generate_world_bottom_border(buffer);
+    let Some(heightmap) = images.get(&pending.heightmap) else {
+        return;
+    };
+    let surface = match &pending.surface {
+        Some(handle) => match images.get(handle) {
+            Some(surface) => Some(surface),
+            None => return,
+        },
+        None => None,
+    };
+
+    let settings = &pending.settings;
+    match ImageHeightmap::from_images(
+        heightmap,
+        surface,
+        settings.base_height,
+        settings.max_height,
+        settings.edge,
+    ) {
+        Some(heightmap) => {
+            TERRAIN_GENERATOR
+                .write()
+                .unwrap()
+                .set_heightmap(Some(heightmap));
         }
+        None => warn!(
+            "Unsupported terrain heightmap image format {:?}, keeping the current terrain.",
+            settings.heightmap
+        ),
     }
+
+    commands.remove_resource::<PendingHeightmap>();
 }
 
 pub struct TerrainGeneratorPlugin;
 
 impl Plugin for TerrainGeneratorPlugin {
-    fn build(&self, _: &mut bevy::prelude::App) {
-        TERRAIN_GENERATOR
-            .write()
-            .unwrap()
-            .register_biome_generator(
-                0.0f32,
-                biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
-            )
-            .register_biome_generator(
-                0.8f32,
-                biomes::BasicDesertBiomeTerrainGenerator.into_boxed_generator(),
-            )
-            .register_biome_generator(
-                3.21,
-                biomes::BasicSnowyPlainsBiomeTerrainGenerator.into_boxed_generator(),
+    fn build(&self, app: &mut bevy::prelude::App) {
+        // the generator is shared with the other apps, registering the biomes again would regenerate their chunks.
+        let revision = {
+            let mut generator = TERRAIN_GENERATOR.write().unwrap();
+            if generator.biomes_map.is_empty() {
+                generator.register_default_biomes();
+            }
+            generator.revision()
+        };
+
+        app.init_resource::<TerrainSource>()
+            .init_resource::<EditedChunksOnTerrainChange>()
+            .insert_resource(LoadedTerrainRevision(revision))
+            .add_systems(
+                Update,
+                (
+                    load_terrain_source.run_if(resource_changed::<TerrainSource>()),
+                    apply_pending_heightmap,
+                    reload_chunks_on_terrain_change,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{material::VoxelMaterial, world::materials::Bedrock};
+    use bevy::prelude::{App, Entity};
+
+    #[test]
+    fn generation_respects_the_height_limits() {
+        let mut generator = TerrainGenerator::default();
+        generator.register_biome_generator(
+            0.0,
+            biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        );
+        let limits = WorldHeightLimits {
+            floor: 40,
+            ceiling: 100,
+        };
+
+        // the layers of each chunk which are bedrock and air, in local coordinates.
+        for (chunk_y, bedrock, air) in [(0, 0..32, 0..0), (32, 0..8, 0..0), (96, 0..0, 4..32)] {
+            let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+            generator.generate(IVec3::new(64, chunk_y, -32), &mut buffer, &limits);
+
+            for y in 0..32 {
+                let layer = (0..32).flat_map(|x| (0..32).map(move |z| [x, y, z]));
+                let mut voxels = layer.map(|pos| buffer.voxel_at(pos.into()));
+                if bedrock.contains(&y) {
+                    assert!(voxels.all(|voxel| voxel == Bedrock::into_voxel()));
+                } else if air.contains(&y) {
+                    assert!(voxels.all(|voxel| voxel == Voxel::EMPTY_VOXEL));
+                } else {
+                    assert!(voxels.all(|voxel| voxel != Bedrock::into_voxel()));
+                }
+            }
+        }
+    }
+
+    fn plains_generator() -> TerrainGenerator {
+        let mut generator = TerrainGenerator::default();
+        generator.register_biome_generator(
+            0.0,
+            biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        );
+        generator
+    }
+
+    // marks the top corner of the chunk, with a different voxel whether the passes before it shaped the terrain from noise.
+    struct ProbePass;
+
+    const NOISE_PROBE: Voxel = Voxel::new(42);
+
+    impl TerrainGenPass for ProbePass {
+        fn apply(&self, ctx: &mut ChunkGenContext) {
+            *ctx.buffer.voxel_at_mut([0, 31, 0].into()) = if ctx.heightmap().is_some() {
+                NOISE_PROBE
+            } else {
+                Voxel::new(43)
+            };
+        }
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let mut generator = plains_generator();
+        let names: Vec<_> = generator
+            .passes_mut()
+            .iter()
+            .map(|pass| pass.name())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                std::any::type_name::<pipeline::TerrainShapePass>(),
+                std::any::type_name::<pipeline::BiomeCarvePass>(),
+                std::any::type_name::<pipeline::RiverCarvePass>(),
+                std::any::type_name::<pipeline::BiomeDecorationPass>(),
+                std::any::type_name::<pipeline::HeightLimitsPass>(),
+            ]
+        );
+
+        // a pass run before the height limits has its changes cleared above the ceiling.
+        let limits = WorldHeightLimits {
+            floor: -64,
+            ceiling: 32,
+        };
+        generator.passes_mut().insert(4, Box::new(ProbePass));
+        let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        generator.generate(IVec3::Y * 32, &mut buffer, &limits);
+        assert_eq!(buffer.voxel_at([0, 31, 0].into()), Voxel::EMPTY_VOXEL);
+
+        // the same pass appended after them is kept, and sees the noise heights of the shaping pass.
+        let probe = generator.passes_mut().remove(4);
+        generator.passes_mut().push(probe);
+        generator.generate(IVec3::Y * 32, &mut buffer, &limits);
+        assert_eq!(buffer.voxel_at([0, 31, 0].into()), NOISE_PROBE);
+    }
+
+    #[test]
+    fn added_passes_see_the_generated_chunk() {
+        let mut generator = plains_generator();
+        generator.add_pass(ProbePass);
+
+        let key = IVec3::new(-32, 0, 64);
+        let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        generator.generate(key, &mut buffer, &WorldHeightLimits::default());
+
+        let probe = generator.passes_mut().pop().unwrap();
+        assert_eq!(probe.name(), std::any::type_name::<ProbePass>());
+        assert_eq!(buffer.voxel_at([0, 31, 0].into()), NOISE_PROBE);
+        assert_eq!(generator.passes_mut().len(), 5);
+    }
+
+    #[test]
+    fn default_biomes_generate_the_same_terrain_for_the_same_seed() {
+        let generate = |seed| {
+            let mut generator = TerrainGenerator::default();
+            generator.register_default_biomes().set_seed(seed);
+            assert_eq!(generator.biomes_map.len(), 3);
+            // a column of chunks reaching above the surface.
+            let column: Vec<_> = (0..256)
+                .step_by(32)
+                .flat_map(|y| {
+                    let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+                    generator.generate(IVec3::new(-32, y, 64), &mut buffer, &Default::default());
+                    buffer.encode_rle()
+                })
+                .collect();
+            column
+        };
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+    }
+
+    #[test]
+    fn the_revision_changes_with_the_generated_terrain() {
+        let mut generator = TerrainGenerator::default();
+        let mut revision = generator.revision();
+        let mut changed = |generator: &TerrainGenerator| {
+            let changed = generator.revision() != revision;
+            revision = generator.revision();
+            changed
+        };
+
+        generator.set_seed(0);
+        assert!(!changed(&generator));
+        generator.set_seed(5);
+        assert!(changed(&generator));
+        generator.set_flat_world(Some(FlatWorldGenerator::default()));
+        assert!(changed(&generator));
+        generator.passes_mut().pop();
+        assert!(changed(&generator));
+        generator.register_default_biomes();
+        assert!(changed(&generator));
+    }
+
+    #[test]
+    fn terrain_changes_unload_the_chunks_by_policy() {
+        let (edited, generated) = (IVec3::ZERO, IVec3::X * 32);
+        for (policy, unloads_edited) in [
+            (EditedChunksOnTerrainChange::Regenerate, true),
+            (EditedChunksOnTerrainChange::Keep, false),
+        ] {
+            let mut app = App::new();
+            let mut chunk_entities = ChunkEntities::default();
+            chunk_entities.attach_entity(edited, Entity::from_raw(1));
+            chunk_entities.attach_entity(generated, Entity::from_raw(2));
+            let mut modified_chunks = ModifiedChunks::default();
+            modified_chunks.mark_modified(edited);
+            let revision = TERRAIN_GENERATOR.read().unwrap().revision();
+            app.insert_resource(chunk_entities)
+                .insert_resource(modified_chunks)
+                .insert_resource(policy)
+                .insert_resource(LoadedTerrainRevision(revision))
+                .init_resource::<ChunkCommandQueue>()
+                .add_systems(Update, reload_chunks_on_terrain_change);
+
+            // nothing changed since the chunks were generated.
+            app.update();
+            let queue = app.world.resource::<ChunkCommandQueue>();
+            assert!(!queue.is_queued_for_unload(edited) && !queue.is_queued_for_unload(generated));
+
+            // the chunks were generated by an older revision of the generator, left untouched so the terrain of
+            // the other tests doesn't change.
+            app.insert_resource(LoadedTerrainRevision(revision.wrapping_sub(1)));
+            app.update();
+            let queue = app.world.resource::<ChunkCommandQueue>();
+            assert!(queue.is_queued_for_unload(generated));
+            assert_eq!(
+                queue.is_queued_for_unload(edited),
+                unloads_edited,
+                "{policy:?}"
             );
+        }
     }
 }
@@ -72,20 +72,49 @@ pub fn voronoi(p: Vec2) -> Vec2 {
     closest_point
 }
 
-pub fn generate_heightmap_data(key: IVec3, chunk_len: usize) -> Vec<f32> {
-    let noise = noise::Fbm::<noise::SuperSimplex>::new(0)
+/// The salt deriving the seed of the terrain height noise from the world seed.
+pub const TERRAIN_HEIGHT_SALT: u32 = 0x6865_6967;
+/// The salt deriving the seed of the biome distribution from the world seed.
+pub const BIOMES_SALT: u32 = 0x6269_6f6d;
+/// The salt deriving the seed of the river paths from the world seed.
+pub const RIVERS_SALT: u32 = 0x7269_7665;
+/// The salt deriving the seed of the 3D noise of the density terrain from the world seed.
+pub const DENSITY_SALT: u32 = 0x6465_6e73;
+
+/// Derives the seed of a sub-generator from the world seed and a salt unique to the sub-generator.
+/// Sub-generators sharing the world seed get unrelated seeds, so their patterns don't line up, while staying
+/// reproducible for a given world seed.
+pub const fn derive_seed(seed: u32, salt: u32) -> u32 {
+    // splitmix64 finalizer.
+    let mut z = (((seed as u64) << 32) | salt as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) as u32
+}
+
+/// The noise sampled in world space for the terrain height, see [`terrain_height`].
+pub fn terrain_height_noise(seed: u32) -> noise::Fbm<noise::SuperSimplex> {
+    noise::Fbm::<noise::SuperSimplex>::new(seed)
         .set_octaves(4)
         .set_frequency(0.005)
         .set_persistence(0.5)
-        .set_lacunarity(2.0);
+        .set_lacunarity(2.0)
+}
+
+/// Maps a value of the terrain height noise to the terrain height.
+#[inline]
+pub fn terrain_height(noise_value: f64) -> f32 {
+    noise_value.mul_add(20f64, 132f64) as f32
+}
 
-    noise::utils::PlaneMapBuilder::<_, 2>::new(noise)
+pub fn generate_heightmap_data(key: IVec3, chunk_len: usize, seed: u32) -> Vec<f32> {
+    noise::utils::PlaneMapBuilder::<_, 2>::new(terrain_height_noise(seed))
         .set_size(chunk_len, chunk_len)
         .set_x_bounds(key.x as f64, (key.x + chunk_len as i32) as f64)
         .set_y_bounds(key.z as f64, (key.z + chunk_len as i32) as f64)
         .build()
         .into_iter()
-        .map(|x| x.mul_add(20f64, 132f64) as f32)
+        .map(terrain_height)
         .collect()
 }
 
@@ -109,3 +138,26 @@ impl<'a, const W: usize, const H: usize> Heightmap<'a, W, H> {
         Self { slice }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_seeds_are_reproducible_and_salted() {
+        for seed in [0, 1, 42, u32::MAX] {
+            assert_eq!(
+                derive_seed(seed, TERRAIN_HEIGHT_SALT),
+                derive_seed(seed, TERRAIN_HEIGHT_SALT)
+            );
+            assert_ne!(
+                derive_seed(seed, TERRAIN_HEIGHT_SALT),
+                derive_seed(seed, BIOMES_SALT)
+            );
+        }
+        assert_ne!(
+            derive_seed(0, TERRAIN_HEIGHT_SALT),
+            derive_seed(1, TERRAIN_HEIGHT_SALT)
+        );
+    }
+}
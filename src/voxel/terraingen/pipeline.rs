@@ -0,0 +1,145 @@
+use bevy::math::IVec3;
+
+use crate::voxel::{
+    storage::VoxelBuffer, world::WorldHeightLimits, ChunkShape, Voxel, CHUNK_LENGTH_U,
+};
+
+use super::{
+    common::{terrain_apply_height_limits, terrain_carve_heightmap},
+    noise::{
+        derive_seed, generate_heightmap_data, Heightmap, DENSITY_SALT, RIVERS_SALT,
+        TERRAIN_HEIGHT_SALT,
+    },
+    rivers::RiverCarver,
+    TerrainGenerator, TerrainShape,
+};
+
+/// The chunk being generated, handed over to each pass of the terrain generation pipeline in turn.
+pub struct ChunkGenContext<'a> {
+    pub chunk_key: IVec3,
+    pub buffer: &'a mut VoxelBuffer<Voxel, ChunkShape>,
+    pub height_limits: &'a WorldHeightLimits,
+    pub generator: &'a TerrainGenerator,
+    /// The terrain height of each voxel column, set by the [`TerrainShapePass`] when generating terrain from noise.
+    pub heights: Option<Vec<f32>>,
+}
+
+impl ChunkGenContext<'_> {
+    /// Returns a view into the noise terrain heights of the chunk, if the terrain was shaped from noise.
+    pub fn heightmap(&self) -> Option<Heightmap<'_, CHUNK_LENGTH_U, CHUNK_LENGTH_U>> {
+        self.heights.as_deref().map(Heightmap::from_slice)
+    }
+}
+
+/// A step of the terrain generation, modifying the chunk after the passes preceding it in the pipeline.
+pub trait TerrainGenPass: 'static + Sync + Send {
+    /// A name identifying the pass in the pipeline.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn apply(&self, ctx: &mut ChunkGenContext);
+}
+
+/// Shapes the terrain from the heightmap images, flat layers or density field if set, from noise otherwise.
+pub struct TerrainShapePass;
+
+impl TerrainGenPass for TerrainShapePass {
+    fn apply(&self, ctx: &mut ChunkGenContext) {
+        match &ctx.generator.shape {
+            TerrainShape::Heightmap(heightmap) => heightmap.generate(ctx.chunk_key, ctx.buffer),
+            TerrainShape::Flat(flat) => flat.generate(ctx.chunk_key, ctx.buffer),
+            TerrainShape::Density(density) => density.generate(
+                ctx.chunk_key,
+                ctx.buffer,
+                derive_seed(ctx.generator.seed, TERRAIN_HEIGHT_SALT),
+                derive_seed(ctx.generator.seed, DENSITY_SALT),
+            ),
+            TerrainShape::Noise => {
+                let heights = generate_heightmap_data(
+                    ctx.chunk_key,
+                    CHUNK_LENGTH_U,
+                    derive_seed(ctx.generator.seed, TERRAIN_HEIGHT_SALT),
+                );
+                terrain_carve_heightmap(
+                    ctx.buffer,
+                    ctx.chunk_key,
+                    &Heightmap::from_slice(&heights),
+                );
+                ctx.heights = Some(heights);
+            }
+        }
+    }
+}
+
+/// Covers the noise terrain with the materials of the biome of the chunk.
+pub struct BiomeCarvePass;
+
+impl TerrainGenPass for BiomeCarvePass {
+    fn apply(&self, ctx: &mut ChunkGenContext) {
+        let Some(heights) = ctx.heights.as_deref() else {
+            return;
+        };
+
+        ctx.generator.biome_at(ctx.chunk_key).carve_terrain(
+            ctx.chunk_key,
+            Heightmap::from_slice(heights),
+            ctx.buffer,
+        );
+    }
+}
+
+/// Carves river channels into the noise terrain, see [`RiverCarver`].
+/// The decorations of the following passes grow from the river beds.
+pub struct RiverCarvePass;
+
+impl TerrainGenPass for RiverCarvePass {
+    fn apply(&self, ctx: &mut ChunkGenContext) {
+        let Some(heights) = ctx.heights.as_mut() else {
+            return;
+        };
+
+        RiverCarver::new(
+            derive_seed(ctx.generator.seed, RIVERS_SALT),
+            derive_seed(ctx.generator.seed, TERRAIN_HEIGHT_SALT),
+        )
+        .carve(ctx.chunk_key, heights, ctx.buffer);
+    }
+}
+
+/// Places the features of the biome of the chunk (e.g. trees, rocks) on the noise terrain.
+pub struct BiomeDecorationPass;
+
+impl TerrainGenPass for BiomeDecorationPass {
+    fn apply(&self, ctx: &mut ChunkGenContext) {
+        let Some(heights) = ctx.heights.as_deref() else {
+            return;
+        };
+
+        ctx.generator.biome_at(ctx.chunk_key).decorate_terrain(
+            ctx.chunk_key,
+            Heightmap::from_slice(heights),
+            ctx.buffer,
+        );
+    }
+}
+
+/// Fills the chunk with bedrock below the world floor and clears it above the world ceiling.
+pub struct HeightLimitsPass;
+
+impl TerrainGenPass for HeightLimitsPass {
+    fn apply(&self, ctx: &mut ChunkGenContext) {
+        terrain_apply_height_limits(ctx.buffer, ctx.chunk_key, ctx.height_limits);
+    }
+}
+
+/// Returns the passes of the default terrain generation pipeline, in order.
+pub fn default_passes() -> Vec<Box<dyn TerrainGenPass>> {
+    vec![
+        Box::new(TerrainShapePass),
+        Box::new(BiomeCarvePass),
+        Box::new(RiverCarvePass),
+        Box::new(BiomeDecorationPass),
+        Box::new(HeightLimitsPass),
+    ]
+}
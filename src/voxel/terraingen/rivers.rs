@@ -0,0 +1,165 @@
+use bevy::math::{IVec3, Vec2};
+use ilattice::{glam::UVec2, prelude::Extent};
+use noise::{MultiFractal, NoiseFn};
+
+use crate::voxel::{
+    material::VoxelMaterial, materials::Water, storage::VoxelBuffer, ChunkShape, Voxel,
+    CHUNK_LENGTH, CHUNK_LENGTH_U,
+};
+
+use super::noise::{terrain_height, terrain_height_noise};
+
+/// Carves river channels filled with water into the noise terrain.
+///
+/// Rivers follow the zero crossings of a low frequency noise, which form long meandering paths, and fade out where the
+/// terrain is too steep for water to settle. Everything is sampled in world space, so channels line up across chunks.
+pub struct RiverCarver {
+    paths: noise::Fbm<noise::Perlin>,
+    terrain: noise::Fbm<noise::SuperSimplex>,
+}
+
+impl RiverCarver {
+    /// Half the width of the river paths, in path noise units.
+    const HALF_WIDTH: f64 = 0.04;
+    /// The depth of the channels at the middle of the rivers, in voxels.
+    const MAX_DEPTH: f32 = 5.0;
+    /// The terrain slope from which no channel is carved.
+    const MAX_SLOPE: f32 = 0.6;
+    /// The first height above the sea drowning the low terrain, see [`super::common::terrain_carve_heightmap`].
+    const SEA_LEVEL: i32 = 128;
+
+    /// Creates a carver with the seeds of the river paths and of the terrain height noise.
+    pub fn new(paths_seed: u32, terrain_seed: u32) -> Self {
+        Self {
+            paths: noise::Fbm::<noise::Perlin>::new(paths_seed)
+                .set_octaves(3)
+                .set_frequency(0.002),
+            terrain: terrain_height_noise(terrain_seed),
+        }
+    }
+
+    /// Returns the depth of the river channel at the specified world voxel column, zero outside of rivers.
+    pub fn channel_depth(&self, x: i32, z: i32) -> u32 {
+        let (x, z) = (x as f64, z as f64);
+        let distance = self.paths.get([x, z]).abs();
+        if distance >= Self::HALF_WIDTH {
+            return 0;
+        }
+
+        let height = |x: f64, z: f64| terrain_height(self.terrain.get([x, z]));
+        let slope = Vec2::new(
+            height(x + 1.0, z) - height(x - 1.0, z),
+            height(x, z + 1.0) - height(x, z - 1.0),
+        )
+        .length()
+            / 2.0;
+
+        let flatness = (1.0 - slope / Self::MAX_SLOPE).clamp(0.0, 1.0);
+        let profile = 1.0 - (distance / Self::HALF_WIDTH).powi(2) as f32;
+        (Self::MAX_DEPTH * profile * flatness).round() as u32
+    }
+
+    /// Carves the channels crossing the chunk, lowering the heights of the carved columns to their river bed.
+    pub fn carve(
+        &self,
+        key: IVec3,
+        heights: &mut [f32],
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    ) {
+        Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
+            .iter2()
+            .for_each(|pos| {
+                let depth = self.channel_depth(key.x + pos.x as i32, key.z + pos.y as i32) as i32;
+                if depth == 0 {
+                    return;
+                }
+
+                let height = &mut heights[pos.y as usize * CHUNK_LENGTH_U + pos.x as usize];
+                let surface = height.round() as i32;
+
+                for y in (surface - depth + 1)..=surface {
+                    let local_y = y - key.y;
+                    if !(0..CHUNK_LENGTH as i32).contains(&local_y) {
+                        continue;
+                    }
+
+                    // the water surface sits a voxel below the banks, unless the river flows under the sea.
+                    *buffer.voxel_at_mut([pos.x, local_y as u32, pos.y].into()) =
+                        if y == surface && y + 1 >= Self::SEA_LEVEL {
+                            Voxel::EMPTY_VOXEL
+                        } else {
+                            Water::into_voxel()
+                        };
+                }
+
+                *height = (surface - depth) as f32;
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the minimum of the first chunk along the X axis crossed by a river.
+    fn river_chunk(carver: &RiverCarver) -> IVec3 {
+        let x = (0..8192)
+            .find(|x| carver.channel_depth(*x, 0) > 0)
+            .expect("no river along the X axis");
+        IVec3::new(
+            x.div_euclid(CHUNK_LENGTH as i32) * CHUNK_LENGTH as i32,
+            128,
+            0,
+        )
+    }
+
+    #[test]
+    fn channels_are_seeded() {
+        let carver = RiverCarver::new(1, 2);
+        let key = river_chunk(&carver);
+        let depths = |carver: &RiverCarver| -> Vec<u32> {
+            (0..CHUNK_LENGTH as i32)
+                .map(|x| carver.channel_depth(key.x + x, 0))
+                .collect()
+        };
+
+        assert_eq!(depths(&RiverCarver::new(1, 2)), depths(&carver));
+        assert_ne!(depths(&RiverCarver::new(3, 2)), depths(&carver));
+        assert!(depths(&carver)
+            .iter()
+            .all(|depth| *depth as f32 <= RiverCarver::MAX_DEPTH));
+    }
+
+    #[test]
+    fn channels_are_filled_with_water_and_lower_the_heights() {
+        let carver = RiverCarver::new(1, 2);
+        let key = river_chunk(&carver);
+        let mut heights = vec![140.0; CHUNK_LENGTH_U * CHUNK_LENGTH_U];
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        carver.carve(key, &mut heights, &mut buffer);
+
+        let mut carved = 0;
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                let depth = carver.channel_depth(key.x + x as i32, key.z + z as i32);
+                let height = heights[z as usize * CHUNK_LENGTH_U + x as usize];
+                assert_eq!(height, 140.0 - depth as f32);
+                if depth == 0 {
+                    continue;
+                }
+
+                carved += 1;
+                let local_surface = 140 - key.y as u32;
+                for y in local_surface + 1 - depth..local_surface {
+                    assert_eq!(buffer.voxel_at([x, y, z].into()), Water::into_voxel());
+                }
+                // the water stays a voxel below the banks above the sea.
+                assert_eq!(
+                    buffer.voxel_at([x, local_surface, z].into()),
+                    Voxel::EMPTY_VOXEL
+                );
+            }
+        }
+        assert!(carved > 0);
+    }
+}
@@ -1,10 +1,32 @@
 use block_mesh::{MergeVoxel, Voxel as MeshableVoxel};
 
 #[derive(Copy, Clone, Hash, Debug, PartialEq, Eq)]
-pub struct Voxel(pub u8);
+pub struct Voxel {
+    /// The material id of the voxel.
+    pub id: u8,
+    /// Block specific state (e.g. orientation or fluid level), `0` by default.
+    pub metadata: u8,
+}
 
 impl Voxel {
-    pub const EMPTY_VOXEL: Self = Self(0);
+    /// The air voxel, filling the chunks where nothing was generated.
+    pub const EMPTY_VOXEL: Self = Self::new(0);
+
+    /// Creates a voxel of the specified material with no metadata.
+    pub const fn new(id: u8) -> Self {
+        Self { id, metadata: 0 }
+    }
+
+    /// Returns a copy of this voxel with the specified metadata.
+    pub const fn with_metadata(self, metadata: u8) -> Self {
+        Self { metadata, ..self }
+    }
+
+    /// Returns whether this voxel is air, whatever its metadata.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.id == Self::EMPTY_VOXEL.id
+    }
 }
 
 impl Default for Voxel {
@@ -16,9 +38,10 @@ impl Default for Voxel {
 impl MeshableVoxel for Voxel {
     #[inline]
     fn get_visibility(&self) -> block_mesh::VoxelVisibility {
-        match *self {
-            Self::EMPTY_VOXEL => block_mesh::VoxelVisibility::Empty,
-            _ => block_mesh::VoxelVisibility::Opaque,
+        if self.is_empty() {
+            block_mesh::VoxelVisibility::Empty
+        } else {
+            block_mesh::VoxelVisibility::Opaque
         }
     }
 }
@@ -28,16 +51,63 @@ impl MergeVoxel for Voxel {
 
     #[inline]
     fn merge_value(&self) -> Self::MergeValue {
-        self.0
+        self.id
     }
 }
 
 pub trait MaterialVoxel: MergeVoxel + MeshableVoxel {
     fn as_mat_id(&self) -> u8;
+
+    /// The block specific state of the voxel, faces with a different one may not be merged when meshing.
+    fn metadata(&self) -> u8 {
+        0
+    }
 }
 
 impl MaterialVoxel for Voxel {
     fn as_mat_id(&self) -> u8 {
-        self.0
+        self.id
+    }
+
+    fn metadata(&self) -> u8 {
+        self.metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{storage::VoxelBuffer, ChunkShape};
+
+    #[test]
+    fn metadata_round_trips_without_changing_the_id() {
+        let voxel = Voxel::new(3).with_metadata(7);
+        assert_eq!(voxel.metadata, 7);
+        assert_eq!(voxel.id, 3);
+        assert_eq!(voxel.as_mat_id(), 3);
+        assert_eq!(voxel.with_metadata(0), Voxel::new(3));
+
+        // only the id is used for merging and visibility.
+        assert_eq!(voxel.merge_value(), Voxel::new(3).merge_value());
+        assert!(matches!(
+            Voxel::EMPTY_VOXEL.with_metadata(1).get_visibility(),
+            block_mesh::VoxelVisibility::Empty
+        ));
+        assert_ne!(voxel, Voxel::new(3));
+    }
+
+    #[test]
+    fn air_is_empty_whatever_its_metadata() {
+        assert!(Voxel::EMPTY_VOXEL.is_empty());
+        assert!(Voxel::EMPTY_VOXEL.with_metadata(3).is_empty());
+        assert!(Voxel::default().is_empty());
+        assert!(!Voxel::new(1).is_empty());
+
+        let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+        assert!(buffer.is_empty());
+        *buffer.voxel_at_mut([4, 5, 6].into()) = Voxel::EMPTY_VOXEL.with_metadata(1);
+        assert!(buffer.is_empty());
+        *buffer.voxel_at_mut([4, 5, 6].into()) = Voxel::new(1);
+        assert!(!buffer.is_empty());
     }
 }
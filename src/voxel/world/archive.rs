@@ -0,0 +1,213 @@
+use std::{collections::HashMap, io, ops::Range, path::Path, sync::Arc};
+
+use bevy::math::IVec3;
+
+use super::{terrain::ChunkSeeder, ChunkShape, Voxel, CHUNK_LENGTH};
+use crate::voxel::storage::VoxelBuffer;
+
+/// A read-only bundle of prebuilt chunks, e.g. to ship a fixed map with the game.
+///
+/// The archive starts with a header (the `VXWA` magic, a format version, the chunk length and the number of chunks),
+/// followed by the chunks one after the other, each stored as its key (three little endian `i32`), the length of its
+/// data (a little endian `u32`) and its voxels encoded with [`VoxelBuffer::encode_rle`]. Opening an archive reads it
+/// in a single sequential pass, the chunks being decoded as they're loaded.
+pub struct WorldArchive {
+    // the range of the encoded voxels of each chunk in the data.
+    chunks: HashMap<IVec3, Range<usize>>,
+    data: Vec<u8>,
+}
+
+impl WorldArchive {
+    const MAGIC: &'static [u8; 4] = b"VXWA";
+    const VERSION: u8 = 1;
+    const HEADER_LEN: usize = 10;
+    const ENTRY_HEADER_LEN: usize = 16;
+
+    /// Encodes the chunks into an archive, sorted by key so the same chunks always give the same bytes.
+    pub fn encode<'a>(
+        chunks: impl IntoIterator<Item = (IVec3, &'a VoxelBuffer<Voxel, ChunkShape>)>,
+    ) -> Vec<u8> {
+        let mut chunks: Vec<_> = chunks.into_iter().collect();
+        chunks.sort_unstable_by_key(|(key, _)| [key.z, key.y, key.x]);
+
+        let mut data = Vec::with_capacity(Self::HEADER_LEN);
+        data.extend_from_slice(Self::MAGIC);
+        data.push(Self::VERSION);
+        data.push(CHUNK_LENGTH as u8);
+        data.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+
+        for (key, buffer) in chunks {
+            let encoded = buffer.encode_rle();
+            for coord in key.to_array() {
+                data.extend_from_slice(&coord.to_le_bytes());
+            }
+            data.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            data.extend_from_slice(&encoded);
+        }
+
+        data
+    }
+
+    /// Reads an archive built with [`Self::encode`], `None` if the data isn't a valid archive for the current chunk
+    /// length.
+    pub fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        let header = data.get(..Self::HEADER_LEN)?;
+        if &header[..4] != Self::MAGIC
+            || header[4] != Self::VERSION
+            || header[5] as u32 != CHUNK_LENGTH
+        {
+            return None;
+        }
+        let count = u32::from_le_bytes(header[6..10].try_into().ok()?) as usize;
+
+        // the count comes from the file, so don't reserve more entries than the data could hold.
+        let max_count = (data.len() - Self::HEADER_LEN) / Self::ENTRY_HEADER_LEN;
+        let mut chunks = HashMap::with_capacity(count.min(max_count));
+        let mut offset = Self::HEADER_LEN;
+        for _ in 0..count {
+            let entry = data.get(offset..offset + Self::ENTRY_HEADER_LEN)?;
+            let field =
+                |index: usize| -> [u8; 4] { entry[index * 4..index * 4 + 4].try_into().unwrap() };
+            let key = IVec3::from_array([0, 1, 2].map(|index| i32::from_le_bytes(field(index))));
+            let len = u32::from_le_bytes(field(3)) as usize;
+
+            let start = offset + Self::ENTRY_HEADER_LEN;
+            let range = start..start.checked_add(len).filter(|end| *end <= data.len())?;
+            offset = range.end;
+            chunks.insert(key, range);
+        }
+
+        (offset == data.len()).then_some(Self { chunks, data })
+    }
+
+    /// Reads the archive file at the specified path.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid world archive"))
+    }
+
+    /// Returns whether the archive holds the chunk.
+    pub fn contains(&self, chunk_key: IVec3) -> bool {
+        self.chunks.contains_key(&chunk_key)
+    }
+
+    /// Returns the number of chunks in the archive.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns whether the archive holds no chunk.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Decodes the voxels of the chunk, `None` if the archive doesn't hold it or its data is corrupted.
+    pub fn chunk(&self, chunk_key: IVec3) -> Option<VoxelBuffer<Voxel, ChunkShape>> {
+        let range = self.chunks.get(&chunk_key)?;
+        VoxelBuffer::decode_rle(ChunkShape {}, &self.data[range.clone()])
+    }
+
+    /// Returns a seeder loading the chunks from the archive, the chunks missing from it being generated as usual.
+    /// It replaces any other seeder once inserted as a resource.
+    pub fn into_seeder(self) -> ChunkSeeder {
+        let archive = Arc::new(self);
+        ChunkSeeder::new(move |chunk_key| archive.chunk(chunk_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        storage::ChunkMap,
+        terraingen::TERRAIN_GENERATOR,
+        world::{
+            chunks::{ChunkEntities, DirtyChunks},
+            terrain::force_load_chunk,
+            WorldHeightLimits,
+        },
+    };
+    use bevy::prelude::World;
+
+    fn marked_chunk(marker: u8) -> VoxelBuffer<Voxel, ChunkShape> {
+        let mut buffer = VoxelBuffer::new(ChunkShape {}, Voxel::new(1));
+        *buffer.voxel_at_mut([3, 5, 7].into()) = Voxel::new(marker);
+        buffer
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let first = marked_chunk(2);
+        let second = marked_chunk(3);
+        let keys = [IVec3::new(0, 32, 0), IVec3::new(-32, 0, 64)];
+        let data = WorldArchive::encode([(keys[0], &first), (keys[1], &second)]);
+
+        let archive = WorldArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert!(!archive.contains(IVec3::ZERO));
+        assert!(archive.chunk(IVec3::ZERO).is_none());
+        assert_eq!(
+            archive.chunk(keys[0]).unwrap().encode_rle(),
+            first.encode_rle()
+        );
+        assert_eq!(
+            archive.chunk(keys[1]).unwrap().encode_rle(),
+            second.encode_rle()
+        );
+    }
+
+    #[test]
+    fn corrupt_archives_are_rejected() {
+        let buffer = marked_chunk(2);
+        let data = WorldArchive::encode([(IVec3::ZERO, &buffer)]);
+
+        let mut truncated = data.clone();
+        truncated.pop();
+        assert!(WorldArchive::from_bytes(truncated).is_none());
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(WorldArchive::from_bytes(trailing).is_none());
+
+        // a chunk count far beyond what the data holds must not be trusted for allocations.
+        let mut huge_count = data;
+        huge_count[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(WorldArchive::from_bytes(huge_count.clone()).is_none());
+
+        let path =
+            std::env::temp_dir().join(format!("vx_bevy_corrupt_{}.vxwa", std::process::id()));
+        std::fs::write(&path, huge_count).unwrap();
+        let error = WorldArchive::open(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn seeder_falls_back_to_the_terrain_generator() {
+        let buffer = marked_chunk(2);
+        let data = WorldArchive::encode([(IVec3::ZERO, &buffer)]);
+
+        // registering the biomes again only replaces them, should another test have done it already.
+        TERRAIN_GENERATOR.write().unwrap().register_default_biomes();
+
+        let mut world = World::new();
+        world.insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}));
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<ChunkEntities>();
+        world.init_resource::<WorldHeightLimits>();
+        world.insert_resource(WorldArchive::from_bytes(data).unwrap().into_seeder());
+
+        let archived = force_load_chunk(&mut world, IVec3::ZERO).encode_rle();
+        assert_eq!(archived, buffer.encode_rle());
+
+        let missing_key = IVec3::new(CHUNK_LENGTH as i32, 0, 0);
+        let mut generated = VoxelBuffer::new_empty(ChunkShape {});
+        TERRAIN_GENERATOR.read().unwrap().generate(
+            missing_key,
+            &mut generated,
+            world.resource::<WorldHeightLimits>(),
+        );
+        let loaded = force_load_chunk(&mut world, missing_key).encode_rle();
+        assert_eq!(loaded, generated.encode_rle());
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::{
+    math::IVec3,
+    prelude::{
+        Assets, Changed, Color, DetectChanges, Entity, Handle, IntoSystemConfigs, Local, Plugin,
+        Query, Res, ResMut, Resource, Update,
+    },
+};
+
+use super::{meshing::ChunkMeshingSet, Chunk, CHUNK_LENGTH};
+use crate::voxel::{
+    render::{ChunkMaterialSet, ChunkMaterialSingleton, GpuTerrainUniforms},
+    terraingen::TERRAIN_GENERATOR,
+};
+
+/// The colors given to tinted chunks, picked to be easy to tell apart.
+const TINT_PALETTE: [Color; 8] = [
+    Color::rgb(1.0, 0.55, 0.55),
+    Color::rgb(0.55, 1.0, 0.55),
+    Color::rgb(0.55, 0.55, 1.0),
+    Color::rgb(1.0, 1.0, 0.5),
+    Color::rgb(1.0, 0.5, 1.0),
+    Color::rgb(0.5, 1.0, 1.0),
+    Color::rgb(1.0, 0.75, 0.4),
+    Color::rgb(0.7, 0.55, 1.0),
+];
+
+/// How chunks are tinted to visualize the structure of the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkTintMode {
+    /// Chunks are rendered with their voxel colors only.
+    #[default]
+    None,
+    /// Each chunk gets a color different from all of its face neighbors.
+    ByChunk,
+    /// Each chunk gets the color of the biome it was generated with.
+    ByBiome,
+}
+
+impl ChunkTintMode {
+    /// Returns the index in the tint palette of the chunk at the specified key, if it is tinted.
+    fn palette_index(self, chunk_key: IVec3) -> Option<usize> {
+        match self {
+            Self::None => None,
+            Self::ByChunk => {
+                let pos = chunk_key / CHUNK_LENGTH as i32;
+                Some((pos.x + 2 * pos.y + 4 * pos.z).rem_euclid(TINT_PALETTE.len() as i32) as usize)
+            }
+            Self::ByBiome => Some(
+                TERRAIN_GENERATOR.read().unwrap().biome_index_at(chunk_key) % TINT_PALETTE.len(),
+            ),
+        }
+    }
+}
+
+/// Resource selecting the [`ChunkTintMode`] of the terrain.
+/// Tints are applied by swapping the chunk materials, so changing the mode doesn't remesh any chunk.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkTintSettings {
+    pub mode: ChunkTintMode,
+}
+
+/// Keeps the tinted copies of the chunk material in sync with it and assigns them to the chunks.
+fn apply_chunk_tints(
+    settings: Res<ChunkTintSettings>,
+    chunk_material: Res<ChunkMaterialSingleton>,
+    mut materials: ResMut<Assets<GpuTerrainUniforms>>,
+    mut palette: Local<Vec<Handle<GpuTerrainUniforms>>>,
+    mut chunks: Query<(Entity, &Chunk, &mut Handle<GpuTerrainUniforms>)>,
+    changed_chunks: Query<(), Changed<Handle<GpuTerrainUniforms>>>,
+) {
+    let Some(base) = materials.get(&chunk_material).cloned() else {
+        return;
+    };
+
+    let rebuild = chunk_material.is_changed() || palette.is_empty();
+    if rebuild {
+        *palette = TINT_PALETTE
+            .iter()
+            .map(|&tint| {
+                materials.add(GpuTerrainUniforms {
+                    tint,
+                    ..base.clone()
+                })
+            })
+            .collect();
+    } else {
+        // follow the fog distance and voxel scale updates of the chunk material.
+        for handle in palette.iter() {
+            let outdated = materials.get(handle).is_some_and(|uniforms| {
+                uniforms.fog_distance != base.fog_distance
+                    || uniforms.voxel_scale != base.voxel_scale
+            });
+            if outdated {
+                if let Some(uniforms) = materials.get_mut(handle) {
+                    uniforms.fog_distance = base.fog_distance;
+                    uniforms.voxel_scale = base.voxel_scale;
+                }
+            }
+        }
+    }
+
+    let reassign_all = rebuild || settings.is_changed();
+    for (entity, chunk, mut handle) in &mut chunks {
+        if !reassign_all && !changed_chunks.contains(entity) {
+            continue;
+        }
+
+        let target = settings
+            .mode
+            .palette_index(chunk.0)
+            .map_or(&**chunk_material, |index| &palette[index]);
+        if *handle != *target {
+            *handle = target.clone();
+        }
+    }
+}
+
+/// Tints the chunks according to the [`ChunkTintSettings`].
+pub struct ChunkTintPlugin;
+
+impl Plugin for ChunkTintPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkTintSettings>().add_systems(
+            Update,
+            apply_chunk_tints
+                .after(ChunkMaterialSet)
+                .after(ChunkMeshingSet),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::world::ChunkEntities;
+
+    #[test]
+    fn chunk_tints_differ_from_their_neighbors() {
+        assert_eq!(ChunkTintMode::None.palette_index(IVec3::ZERO), None);
+
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    let key = IVec3::new(x, y, z) * CHUNK_LENGTH as i32;
+                    let tint = ChunkTintMode::ByChunk.palette_index(key).unwrap();
+                    assert!(tint < TINT_PALETTE.len());
+                    for neighbor in ChunkEntities::neighbor_keys(key).into_iter().flatten() {
+                        assert_ne!(ChunkTintMode::ByChunk.palette_index(neighbor), Some(tint));
+                    }
+                }
+            }
+        }
+    }
+}
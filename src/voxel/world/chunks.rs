@@ -1,14 +1,22 @@
+use std::cmp::Reverse;
+
 use bevy::{
-    math::IVec3,
+    log::{error, warn},
+    math::{IVec3, Vec3},
     prelude::{
-        Changed, Commands, Entity, GlobalTransform, IntoSystemConfigs, Last, Plugin, PostUpdate,
-        Query, Res, ResMut, Resource, SystemSet, Update, With,
+        Changed, Commands, DetectChanges, Entity, GlobalTransform, IntoSystemConfigs, Last, Local,
+        Plugin, PostUpdate, Query, Res, ResMut, Resource, SystemSet, Update, With,
     },
+    tasks::AsyncComputeTaskPool,
     utils::{HashMap, HashSet},
 };
 use float_ord::FloatOrd;
+use ndshape::ConstShape;
 
-use super::{player::PlayerController, Chunk, ChunkShape, CHUNK_LENGTH};
+use super::{
+    player::PlayerController, Chunk, ChunkShape, ChunkState, VoxelScale, WorldHeightLimits,
+    CHUNK_LENGTH,
+};
 use crate::voxel::storage::ChunkMap;
 use crate::voxel::Voxel;
 
@@ -16,11 +24,14 @@ use crate::voxel::Voxel;
 fn update_player_pos(
     player: Query<&GlobalTransform, (With<PlayerController>, Changed<GlobalTransform>)>,
     mut chunk_pos: ResMut<CurrentLocalPlayerChunk>,
+    scale: Res<VoxelScale>,
 ) {
     if let Ok(ply) = player.get_single() {
-        let player_coords = ply.translation().as_ivec3();
+        // flooring keeps negative coordinates in the voxel (and chunk) they are in.
+        let player_coords = (ply.translation() / scale.0).floor().as_ivec3();
         let nearest_chunk_origin = !IVec3::splat((CHUNK_LENGTH - 1) as i32) & player_coords;
 
+        chunk_pos.translation = ply.translation();
         chunk_pos.world_pos = player_coords;
 
         if chunk_pos.chunk_min != nearest_chunk_origin {
@@ -29,89 +40,295 @@ fn update_player_pos(
     }
 }
 
+/// Returns the key of the chunk `offset` chunks away from the specified chunk, `None` if it lies past the limits of the
+/// world coordinates.
+pub fn offset_chunk_key(key: IVec3, offset: IVec3) -> Option<IVec3> {
+    let axis = |axis: usize| {
+        offset[axis]
+            .checked_mul(CHUNK_LENGTH as i32)
+            .and_then(|offset| key[axis].checked_add(offset))
+    };
+
+    Some(IVec3::new(axis(0)?, axis(1)?, axis(2)?))
+}
+
+/// Returns the offset, in chunks, from the `center` chunk to the chunk with the specified key, `None` if the chunks
+/// are too far apart for the offset to be computed.
+pub fn chunk_offset(key: IVec3, center: IVec3) -> Option<IVec3> {
+    let axis = |axis: usize| key[axis].checked_sub(center[axis]);
+
+    Some(IVec3::new(axis(0)?, axis(1)?, axis(2)?) / CHUNK_LENGTH as i32)
+}
+
+/// Returns whether the region loaded around the specified chunk with the specified radii, in chunks, lies within
+/// the limits of the world coordinates.
+fn region_within_limits(center: IVec3, horizontal: i32, vertical: i32) -> bool {
+    let radius = IVec3::new(horizontal, vertical, horizontal);
+
+    offset_chunk_key(center, -radius).is_some()
+        && offset_chunk_key(center, radius - IVec3::ONE).is_some()
+}
+
+/// Returns the keys of the chunks loaded around the specified chunk with the specified shape and radii, in chunks.
+/// Chunks are clamped to the lowest height of the world, so the same key may be returned several times.
+/// Chunks past the limits of the world coordinates are left out.
+fn chunks_in_radius(
+    center: IVec3,
+    shape: ChunkLoadShape,
+    horizontal: i32,
+    vertical: i32,
+    height_limits: &WorldHeightLimits,
+) -> impl Iterator<Item = IVec3> + '_ {
+    //perf: optimize this.
+    (-horizontal..horizontal)
+        .flat_map(move |x| (-horizontal..horizontal).map(move |z| (x, z)))
+        .flat_map(move |(x, z)| (-vertical..vertical).map(move |y| IVec3::new(x, y, z)))
+        .filter(move |offset| shape.loads(*offset, horizontal, vertical))
+        .filter_map(move |offset| {
+            let mut pos = offset_chunk_key(center, offset)?;
+            pos.y = pos.y.max(height_limits.lowest_chunk());
+            Some(pos)
+        })
+        // chunks above the ceiling only hold air.
+        .filter(|pos| pos.y < height_limits.ceiling)
+}
+
+/// Clamps the chunk loading radii to at least one chunk when they change, an empty region loading nothing at all.
+fn validate_chunk_load_radius(mut view_radius: ResMut<ChunkLoadRadius>) {
+    if !view_radius.is_changed() || (view_radius.horizontal >= 1 && view_radius.vertical >= 1) {
+        return;
+    }
+
+    warn!(
+        "Invalid chunk loading radii {}x{}, clamping them to at least one chunk.",
+        view_radius.horizontal, view_radius.vertical
+    );
+    view_radius.horizontal = view_radius.horizontal.max(1);
+    view_radius.vertical = view_radius.vertical.max(1);
+}
+
+/// Collects the chunks kept loaded by the anchors when they change.
+fn update_anchored_chunks(
+    mut anchors: ResMut<ChunkAnchors>,
+    view_radius: Res<ChunkLoadRadius>,
+    height_limits: Res<WorldHeightLimits>,
+    scale: Res<VoxelScale>,
+) {
+    if !anchors.is_changed()
+        && !view_radius.is_changed()
+        && !height_limits.is_changed()
+        && !scale.is_changed()
+    {
+        return;
+    }
+
+    let anchors = anchors.as_mut();
+    anchors.chunks = anchors
+        .anchors
+        .iter()
+        .flat_map(|anchor| {
+            chunks_in_radius(
+                anchor.chunk_min(scale.0),
+                view_radius.shape,
+                anchor.horizontal,
+                anchor.vertical,
+                &height_limits,
+            )
+        })
+        .collect();
+}
+
 /// Checks for the loaded chunks around the player and schedules loading of new chunks in sight
 fn update_view_chunks(
     player_pos: Res<CurrentLocalPlayerChunk>,
     chunk_entities: Res<ChunkEntities>,
     view_radius: Res<ChunkLoadRadius>,
+    height_limits: Res<WorldHeightLimits>,
+    anchors: Res<ChunkAnchors>,
     mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut out_of_range: Local<HashSet<IVec3>>,
+    mut past_limits: Local<bool>,
 ) {
-    // quick n dirty circular chunk loading.
-    //perf: optimize this.
-    for x in -view_radius.horizontal..view_radius.horizontal {
-        for z in -view_radius.horizontal..view_radius.horizontal {
-            for y in -view_radius.vertical..view_radius.vertical {
-                if x.pow(2) + z.pow(2) >= view_radius.horizontal.pow(2) {
-                    continue;
-                }
+    // the missing chunks are collected again every frame, dropping the requests left over by the spawn budget.
+    chunk_command_queue.create.clear();
 
-                let chunk_key = {
-                    let mut pos: IVec3 = player_pos.chunk_min
-                        + IVec3::new(
-                            x * CHUNK_LENGTH as i32,
-                            y * CHUNK_LENGTH as i32,
-                            z * CHUNK_LENGTH as i32,
-                        );
+    let within_limits = region_within_limits(
+        player_pos.chunk_min,
+        view_radius.horizontal,
+        view_radius.vertical,
+    );
+    if !within_limits && !*past_limits {
+        error!(
+            "The chunks around {} extend past the limits of the world coordinates, they won't be loaded.",
+            player_pos.chunk_min
+        );
+    }
+    *past_limits = !within_limits;
 
-                    pos.y = pos.y.max(0);
+    let missing: Vec<_> = chunks_in_radius(
+        player_pos.chunk_min,
+        view_radius.shape,
+        view_radius.horizontal,
+        view_radius.vertical,
+        &height_limits,
+    )
+    .chain(anchors.chunks.iter().copied())
+    .filter(|key| chunk_entities.entity(*key).is_none())
+    .collect();
+    chunk_command_queue.create.extend(missing);
 
-                    pos
-                };
+    // chunks are only unloaded past the unload radius so they don't churn while the player moves around the load radius.
+    let (unload_horizontal, unload_vertical) = view_radius.unload_radius();
+    for loaded_chunk in chunk_entities.0.keys() {
+        // chunks too far away for their offset to be computed are way past the unload radius.
+        let kept = chunk_offset(*loaded_chunk, player_pos.chunk_min).is_some_and(|offset| {
+            view_radius
+                .shape
+                .keeps(offset, unload_horizontal, unload_vertical)
+        });
 
-                if chunk_entities.entity(chunk_key).is_none() {
-                    chunk_command_queue.create.push(chunk_key);
-                }
+        if !kept && !anchors.is_anchored(*loaded_chunk) {
+            if chunk_command_queue.destroy.insert(*loaded_chunk) {
+                out_of_range.insert(*loaded_chunk);
             }
+        } else if out_of_range.remove(loaded_chunk) {
+            // back in range while its destruction was deferred by the unload budget.
+            chunk_command_queue.destroy.remove(loaded_chunk);
         }
     }
 
-    // quick n dirty circular chunk !loading.
-    for loaded_chunk in chunk_entities.0.keys() {
-        let delta: IVec3 = *loaded_chunk - player_pos.chunk_min;
+    // forget about the chunks destroyed since they were queued.
+    out_of_range.retain(|key| chunk_command_queue.destroy.contains(key));
 
-        // Compiler complains that this is a bug
-        #[allow(clippy::suspicious_operation_groupings)]
-        if delta.x.pow(2) + delta.z.pow(2)
-            > view_radius.horizontal.pow(2) * (CHUNK_LENGTH as i32).pow(2)
-            || delta.y.pow(2) > view_radius.vertical.pow(2) * (CHUNK_LENGTH as i32).pow(2)
-        {
-            chunk_command_queue.destroy.push(*loaded_chunk);
-        }
+    // load chunks starting from the player position.
+    // chunks clamped to the lowest height or shared by several anchors are requested several times, the duplicates end
+    // up next to each other.
+    chunk_command_queue.create.sort_unstable_by_key(|key| {
+        (
+            FloatOrd(key.as_vec3().distance(player_pos.chunk_min.as_vec3())),
+            key.to_array(),
+        )
+    });
+    chunk_command_queue.create.dedup();
+}
+
+/// Unloads chunks until the loaded ones fit in the memory budget.
+/// The farthest chunks from the player go first, then the anchored ones and the edited chunks only once all the others
+/// are gone.
+fn evict_chunks_over_memory_budget(
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    chunk_entities: Res<ChunkEntities>,
+    modified_chunks: Res<ModifiedChunks>,
+    anchors: Res<ChunkAnchors>,
+    memory_budget: Res<ChunkMemoryBudget>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+) {
+    let Some(max_chunks) = memory_budget.max_chunks() else {
+        return;
+    };
+
+    let mut kept: Vec<_> = chunk_entities
+        .iter_keys()
+        .filter(|key| !chunk_command_queue.destroy.contains(*key))
+        .copied()
+        .collect();
+
+    if kept.len() <= max_chunks {
+        return;
     }
 
-    // load chunks starting from the player position
-    chunk_command_queue.create.sort_unstable_by_key(|key| {
-        FloatOrd(key.as_vec3().distance(player_pos.chunk_min.as_vec3()))
+    // the chunk keys break distance ties so the eviction order doesn't depend on the map iteration order.
+    kept.sort_unstable_by_key(|key| {
+        (
+            modified_chunks.is_modified(*key),
+            anchors.is_anchored(*key),
+            Reverse(FloatOrd(
+                key.as_vec3().distance(player_pos.chunk_min.as_vec3()),
+            )),
+            key.to_array(),
+        )
     });
+
+    let excess = kept.len() - max_chunks;
+    for key in kept.into_iter().take(excess) {
+        if modified_chunks.is_modified(key) {
+            warn!("Unloading the edited chunk {key} to stay within the chunk memory budget, its edits are lost.");
+        }
+        chunk_command_queue.destroy.insert(key);
+    }
 }
 
-/// Creates the requested chunks and attach them an ECS entity.
+/// Creates the requested chunks and attach them an ECS entity, up to the spawn and memory budgets.
 fn create_chunks(
     mut chunks_command_queue: ResMut<ChunkCommandQueue>,
     mut chunk_entities: ResMut<ChunkEntities>,
+    budget: Res<ChunkSpawnBudget>,
+    memory_budget: Res<ChunkMemoryBudget>,
     mut cmds: Commands,
 ) {
+    let kept = chunk_entities
+        .len()
+        .saturating_sub(chunks_command_queue.destroy.len());
+    let room = memory_budget
+        .max_chunks()
+        .map_or(usize::MAX, |max_chunks| max_chunks.saturating_sub(kept));
+    let count = budget
+        .chunks_per_frame
+        .min(room)
+        .min(chunks_command_queue.create.len());
+
     chunks_command_queue
         .create
-        .drain(..)
-        .for_each(|request| chunk_entities.attach_entity(request, cmds.spawn(Chunk(request)).id()));
+        .drain(..count)
+        .for_each(|request| {
+            chunk_entities.attach_entity(
+                request,
+                cmds.spawn((Chunk(request), ChunkState::Spawned)).id(),
+            )
+        });
 }
 
+/// Destroys the chunks requested for unloading, up to the unload budget.
+/// The remaining requests are kept for the next frames and the voxel buffers are dropped off the main thread.
 fn destroy_chunks(
     mut chunks_command_queue: ResMut<ChunkCommandQueue>,
     mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
     mut chunk_entities: ResMut<ChunkEntities>,
+    mut modified_chunks: ResMut<ModifiedChunks>,
+    budget: Res<ChunkUnloadBudget>,
     mut cmds: Commands,
 ) {
-    for command in chunks_command_queue.destroy.drain(..) {
-        cmds.entity(chunk_entities.detach_entity(command).unwrap())
-            .despawn();
-        chunks.remove(command);
+    let batch: Vec<_> = chunks_command_queue
+        .destroy
+        .iter()
+        .take(budget.chunks_per_frame)
+        .copied()
+        .collect();
+
+    let buffers: Vec<_> = batch
+        .into_iter()
+        .filter_map(|command| {
+            chunks_command_queue.destroy.remove(&command);
+            modified_chunks.0.remove(&command);
+            if let Some(entity) = chunk_entities.detach_entity(command) {
+                cmds.entity(entity).despawn();
+            }
+            chunks.remove(command)
+        })
+        .collect();
+
+    // meshing and generation tasks own their voxel data, nothing references the buffers anymore.
+    if !buffers.is_empty() {
+        AsyncComputeTaskPool::get()
+            .spawn(async move { drop(buffers) })
+            .detach();
     }
 }
 
 fn clear_dirty_chunks(mut dirty_chunks: ResMut<DirtyChunks>) {
-    dirty_chunks.0.clear();
+    dirty_chunks.chunks.clear();
+    dirty_chunks.edited.clear();
+    dirty_chunks.edited_voxels.clear();
 }
 
 /// Label for the stage housing the chunk loading systems.
@@ -131,6 +348,31 @@ impl ChunkEntities {
         self.0.get(&pos).copied()
     }
 
+    /// Returns the entity of the chunk holding the voxel at the specified world voxel coordinates.
+    pub fn entity_at_world(&self, pos: IVec3) -> Option<Entity> {
+        self.entity(pos & !(CHUNK_LENGTH as i32 - 1))
+    }
+
+    /// Returns the keys of the six chunks sharing a face with the chunk, in the -X, +X, -Y, +Y, -Z, +Z order.
+    /// The neighbors past the limits of the world coordinates are `None`.
+    pub fn neighbor_keys(pos: IVec3) -> [Option<IVec3>; 6] {
+        [
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+            IVec3::NEG_Z,
+            IVec3::Z,
+        ]
+        .map(|direction| offset_chunk_key(pos, direction))
+    }
+
+    /// Returns the entities of the six chunks sharing a face with the chunk, in the order of [`Self::neighbor_keys`].
+    /// The neighbors which aren't loaded are `None`.
+    pub fn neighbors(&self, pos: IVec3) -> [Option<Entity>; 6] {
+        Self::neighbor_keys(pos).map(|key| key.and_then(|key| self.entity(key)))
+    }
+
     /// Attaches the specified entity to the chunk data.
     pub fn attach_entity(&mut self, pos: IVec3, entity: Entity) {
         self.0.insert(pos, entity);
@@ -150,71 +392,338 @@ impl ChunkEntities {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    /// Returns whether no chunk is loaded.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Tracks the loaded chunks edited since they were generated.
+#[derive(Default, Resource)]
+pub struct ModifiedChunks(HashSet<IVec3>);
+
+impl ModifiedChunks {
+    pub fn mark_modified(&mut self, chunk: IVec3) {
+        self.0.insert(chunk);
+    }
+
+    pub fn is_modified(&self, chunk: IVec3) -> bool {
+        self.0.contains(&chunk)
+    }
 }
 
 /// Holds the dirty chunk for the current frame.
 #[derive(Default, Resource)]
-pub struct DirtyChunks(HashSet<IVec3>);
+pub struct DirtyChunks {
+    chunks: HashSet<IVec3>,
+    // the dirty chunks edited by the player, remeshed ahead of the others.
+    edited: HashSet<IVec3>,
+    // the voxels changed in the dirty chunks only made dirty by single voxel edits.
+    edited_voxels: HashMap<IVec3, Vec<IVec3>>,
+}
 
 #[allow(dead_code)]
 impl DirtyChunks {
     pub fn mark_dirty(&mut self, chunk: IVec3) {
-        self.0.insert(chunk);
+        self.chunks.insert(chunk);
+        self.edited_voxels.remove(&chunk);
+    }
+
+    /// Marks a chunk as dirty after a player edit, so its remesh is queued before the ones of the other chunks.
+    pub fn mark_edited(&mut self, chunk: IVec3) {
+        self.mark_dirty(chunk);
+        self.edited.insert(chunk);
+    }
+
+    /// Marks the chunk holding the voxel at `pos` as dirty after a player edit of this single voxel.
+    /// The edited voxels are recorded, see [`Self::edited_voxels`].
+    pub fn mark_voxel_edited(&mut self, pos: IVec3) {
+        let chunk = pos & !(CHUNK_LENGTH as i32 - 1);
+        self.edited.insert(chunk);
+
+        if self.chunks.insert(chunk) {
+            self.edited_voxels.insert(chunk, vec![pos]);
+        } else if let Some(voxels) = self.edited_voxels.get_mut(&chunk) {
+            voxels.push(pos);
+        }
+    }
+
+    /// Returns the voxels changed in the chunk if it was only made dirty by single voxel edits, e.g. to patch its mesh
+    /// rather than meshing it again.
+    pub fn edited_voxels(&self, chunk: IVec3) -> Option<&[IVec3]> {
+        self.edited_voxels.get(&chunk).map(Vec::as_slice)
+    }
+
+    /// Drops the chunk from the dirty chunks, once its mesh was updated some other way than by meshing it again.
+    pub fn mark_clean(&mut self, chunk: IVec3) {
+        self.chunks.remove(&chunk);
+        self.edited.remove(&chunk);
+        self.edited_voxels.remove(&chunk);
     }
 
     pub fn iter_dirty(&self) -> impl Iterator<Item = &IVec3> {
-        self.0.iter()
+        self.chunks.iter()
+    }
+
+    pub fn is_dirty(&self, chunk: IVec3) -> bool {
+        self.chunks.contains(&chunk)
+    }
+
+    /// Returns whether the chunk was marked dirty by a player edit.
+    pub fn is_edited(&self, chunk: IVec3) -> bool {
+        self.edited.contains(&chunk)
     }
 
     pub fn num_dirty(&self) -> usize {
-        self.0.len()
+        self.chunks.len()
     }
 }
 
 /// Resource storing the current chunk the player is in as well as its current coords.
+/// Updated once per frame in [`ChunkLoadingSet`], systems needing the player position should read it from here.
 #[derive(Resource)]
 pub struct CurrentLocalPlayerChunk {
     pub chunk_min: IVec3,
+    /// The voxel the player is in.
     pub world_pos: IVec3,
+    /// The player position in world units.
+    pub translation: Vec3,
+}
+
+/// The shape of the region loaded around the player.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkLoadShape {
+    /// A circle of the horizontal radius, extruded over the vertical radius.
+    Cylinder,
+    /// An ellipsoid with the horizontal and vertical radii, loading less chunks far above and below the player.
+    #[default]
+    Sphere,
+    /// A box extending by the horizontal and vertical radii.
+    Cube,
+}
+
+impl ChunkLoadShape {
+    /// Returns whether the chunk at the specified offset from the center, in chunks, is loaded with the radii.
+    /// The loaded offsets range from `-radius` to `radius - 1` along each axis.
+    pub fn loads(self, offset: IVec3, horizontal: i32, vertical: i32) -> bool {
+        let in_box = (-horizontal..horizontal).contains(&offset.x)
+            && (-horizontal..horizontal).contains(&offset.z)
+            && (-vertical..vertical).contains(&offset.y);
+
+        in_box
+            && match self {
+                Self::Cylinder => offset.x.pow(2) + offset.z.pow(2) < horizontal.pow(2),
+                Self::Sphere => {
+                    (offset.x.pow(2) + offset.z.pow(2)) * vertical.pow(2)
+                        + offset.y.pow(2) * horizontal.pow(2)
+                        < (horizontal * vertical).pow(2)
+                }
+                Self::Cube => true,
+            }
+    }
+
+    /// Returns whether a loaded chunk at the specified offset from the center, in chunks, is kept with the radii.
+    /// This includes the chunks right on the boundary, unlike [`Self::loads`].
+    pub fn keeps(self, offset: IVec3, horizontal: i32, vertical: i32) -> bool {
+        // checked first so that the far away chunks don't overflow the squared distances.
+        let in_box = offset.x.unsigned_abs() <= horizontal.unsigned_abs()
+            && offset.z.unsigned_abs() <= horizontal.unsigned_abs()
+            && offset.y.unsigned_abs() <= vertical.unsigned_abs();
+        if !in_box {
+            return false;
+        }
+
+        let horizontal_sq = offset.x.pow(2) + offset.z.pow(2);
+        match self {
+            Self::Cylinder => horizontal_sq <= horizontal.pow(2),
+            Self::Sphere => {
+                horizontal_sq * vertical.pow(2) + offset.y.pow(2) * horizontal.pow(2)
+                    <= (horizontal * vertical).pow(2)
+            }
+            Self::Cube => true,
+        }
+    }
 }
 
 // Resource holding the view distance.
 #[derive(Resource)]
 pub struct ChunkLoadRadius {
+    pub shape: ChunkLoadShape,
+    /// Horizontal radius of the loaded region, in chunks, at least `1`.
     pub horizontal: i32,
+    /// Vertical radius of the loaded region, in chunks, at least `1`.
     pub vertical: i32,
+    /// Horizontal radius past which loaded chunks are unloaded, never smaller than `horizontal`.
+    pub unload_horizontal: i32,
+    /// Vertical radius past which loaded chunks are unloaded, never smaller than `vertical`.
+    pub unload_vertical: i32,
+}
+
+impl ChunkLoadRadius {
+    /// Returns the horizontal and vertical unload radii, clamped so they're at least as large as the load radii.
+    pub fn unload_radius(&self) -> (i32, i32) {
+        (
+            self.unload_horizontal.max(self.horizontal),
+            self.unload_vertical.max(self.vertical),
+        )
+    }
+}
+
+/// A region of the world kept loaded wherever the players are.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkAnchor {
+    /// The center of the region, in world coordinates.
+    pub position: Vec3,
+    /// The horizontal radius of the region, in chunks.
+    pub horizontal: i32,
+    /// The vertical radius of the region, in chunks.
+    pub vertical: i32,
+}
+
+impl ChunkAnchor {
+    /// Returns the key of the chunk holding the anchor position.
+    pub fn chunk_min(&self, scale: f32) -> IVec3 {
+        (self.position / scale).floor().as_ivec3() & !IVec3::splat((CHUNK_LENGTH - 1) as i32)
+    }
+}
+
+/// Resource listing the regions loaded on top of the ones around the player, e.g. the spawn area.
+#[derive(Default, Resource)]
+pub struct ChunkAnchors {
+    anchors: Vec<ChunkAnchor>,
+    // the chunks in the anchor regions, updated when the anchors change.
+    chunks: HashSet<IVec3>,
+}
+
+impl ChunkAnchors {
+    /// Keeps the chunks in the region of the specified anchor loaded.
+    pub fn add(&mut self, anchor: ChunkAnchor) {
+        self.anchors.push(anchor);
+    }
+
+    /// Removes all the anchors, their chunks get unloaded once out of the player radius.
+    pub fn clear(&mut self) {
+        self.anchors.clear();
+    }
+
+    /// Returns an iterator over the anchors.
+    pub fn iter(&self) -> impl Iterator<Item = &ChunkAnchor> {
+        self.anchors.iter()
+    }
+
+    /// Returns whether the chunk lies in the region of an anchor.
+    pub fn is_anchored(&self, chunk: IVec3) -> bool {
+        self.chunks.contains(&chunk)
+    }
 }
 
 /// A queue tracking the creation / destroy commands for chunks.
 #[derive(Default, Resource)]
 pub struct ChunkCommandQueue {
     create: Vec<IVec3>,
-    destroy: Vec<IVec3>,
+    // a set as chunks may be queued for unloading again while their destruction is deferred.
+    destroy: HashSet<IVec3>,
+}
+
+/// Resource bounding the number of chunks spawned each frame, closest to the player first.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkSpawnBudget {
+    pub chunks_per_frame: usize,
+}
+
+impl Default for ChunkSpawnBudget {
+    fn default() -> Self {
+        Self {
+            chunks_per_frame: 256,
+        }
+    }
+}
+
+/// Resource capping the memory used by the voxel data of the loaded chunks.
+/// Chunks count against the budget as soon as they're spawned, as each of them holds a voxel buffer once generated.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct ChunkMemoryBudget {
+    /// The maximum size in bytes of the loaded chunk buffers, unlimited when unset.
+    pub max_bytes: Option<usize>,
+}
+
+impl ChunkMemoryBudget {
+    /// The size in bytes of the voxel buffer of a chunk.
+    pub const CHUNK_BYTES: usize = std::mem::size_of::<Voxel>() * ChunkShape::USIZE;
+
+    /// Returns the number of chunks fitting in the budget, if any.
+    pub fn max_chunks(&self) -> Option<usize> {
+        self.max_bytes
+            .map(|max_bytes| max_bytes / Self::CHUNK_BYTES)
+    }
+}
+
+/// Resource bounding the number of chunks destroyed each frame.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkUnloadBudget {
+    pub chunks_per_frame: usize,
+}
+
+impl Default for ChunkUnloadBudget {
+    fn default() -> Self {
+        Self {
+            chunks_per_frame: 128,
+        }
+    }
 }
 
 impl ChunkCommandQueue {
     pub fn queue_unload<'a>(&mut self, region: impl Iterator<Item = &'a IVec3>) {
         self.destroy.extend(region);
     }
+
+    /// Returns whether the chunk is waiting to be unloaded.
+    #[cfg(test)]
+    pub fn is_queued_for_unload(&self, chunk: IVec3) -> bool {
+        self.destroy.contains(&chunk)
+    }
+
+    /// Drops all the pending creation / destroy commands.
+    pub fn clear(&mut self) {
+        self.create.clear();
+        self.destroy.clear();
+    }
 }
 
 impl Plugin for VoxelWorldChunkingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.insert_resource::<ChunkLoadRadius>(ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
             horizontal: 16,
             vertical: 6,
+            unload_horizontal: 18,
+            unload_vertical: 7,
         })
         .init_resource::<ChunkEntities>()
         .insert_resource(CurrentLocalPlayerChunk {
             chunk_min: IVec3::ZERO,
             world_pos: IVec3::ZERO,
+            translation: Vec3::ZERO,
         })
         .init_resource::<ChunkCommandQueue>()
+        .init_resource::<ChunkAnchors>()
+        .init_resource::<ChunkSpawnBudget>()
+        .init_resource::<ChunkUnloadBudget>()
+        .init_resource::<ChunkMemoryBudget>()
+        .init_resource::<ModifiedChunks>()
         .init_resource::<DirtyChunks>()
         .configure_set(Update, ChunkLoadingSet)
         .add_systems(
             Update,
-            (update_player_pos, update_view_chunks, create_chunks)
+            (
+                update_player_pos,
+                validate_chunk_load_radius,
+                update_anchored_chunks,
+                update_view_chunks,
+                evict_chunks_over_memory_budget,
+                create_chunks,
+            )
                 .chain()
                 .in_set(ChunkLoadingSet),
         )
@@ -222,3 +731,441 @@ impl Plugin for VoxelWorldChunkingPlugin {
         .add_systems(Last, clear_dirty_chunks);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::schedule::ExecutorKind,
+        prelude::{App, MinimalPlugins},
+        utils::tracing::{self, span, Level, Metadata, Subscriber},
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    // an app loading the cylinder of chunks within 2 chunks horizontally of the player, unloaded past 3 chunks.
+    fn chunking_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelWorldChunkingPlugin))
+            .init_resource::<WorldHeightLimits>()
+            .init_resource::<VoxelScale>()
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .insert_resource(ChunkLoadRadius {
+                shape: ChunkLoadShape::Cylinder,
+                horizontal: 2,
+                vertical: 1,
+                unload_horizontal: 3,
+                unload_vertical: 2,
+            });
+        app
+    }
+
+    // moves the player to the chunk at the specified offset from the origin, well above the world floor.
+    fn move_player(app: &mut App, offset: IVec3) {
+        app.world
+            .resource_mut::<CurrentLocalPlayerChunk>()
+            .chunk_min = IVec3::Y * 64 + offset * CHUNK_LENGTH as i32;
+        app.update();
+    }
+
+    fn loaded_chunks(app: &App) -> HashSet<IVec3> {
+        app.world
+            .resource::<ChunkEntities>()
+            .iter_keys()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn chunks_dont_churn_within_the_unload_radius() {
+        let mut app = chunking_app();
+        move_player(&mut app, IVec3::ZERO);
+        let initial = loaded_chunks(&app);
+        assert!(!initial.is_empty());
+
+        move_player(&mut app, IVec3::X);
+        let band = loaded_chunks(&app);
+        assert!(band.is_superset(&initial) && band.len() > initial.len());
+
+        // going back and forth across the chunk border neither loads nor unloads anything.
+        for offset in [IVec3::ZERO, IVec3::X].repeat(5) {
+            move_player(&mut app, offset);
+            assert_eq!(loaded_chunks(&app), band);
+        }
+
+        // the chunks past the unload radius still go once the player moves away.
+        move_player(&mut app, IVec3::X * 5);
+        let center = app.world.resource::<CurrentLocalPlayerChunk>().chunk_min;
+        let loaded = loaded_chunks(&app);
+        assert!(loaded.iter().all(|key| {
+            let delta = (*key - center) / CHUNK_LENGTH as i32;
+            delta.x.pow(2) + delta.z.pow(2) <= 9
+        }));
+        assert!(band.iter().any(|key| !loaded.contains(key)));
+    }
+
+    #[test]
+    fn unload_radius_is_never_smaller_than_the_load_radius() {
+        let radius = ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
+            horizontal: 4,
+            vertical: 2,
+            unload_horizontal: 1,
+            unload_vertical: 3,
+        };
+        assert_eq!(radius.unload_radius(), (4, 3));
+    }
+
+    #[test]
+    fn player_position_is_floored_into_its_chunk() {
+        let mut app = chunking_app();
+        app.insert_resource(VoxelScale(0.5));
+        let translation = Vec3::new(-0.25, 20.0, 16.1);
+        app.world.spawn((
+            PlayerController::default(),
+            GlobalTransform::from_translation(translation),
+        ));
+        app.update();
+
+        let player_pos = app.world.resource::<CurrentLocalPlayerChunk>();
+        assert_eq!(player_pos.translation, translation);
+        assert_eq!(player_pos.world_pos, IVec3::new(-1, 40, 32));
+        assert_eq!(player_pos.chunk_min, IVec3::new(-32, 32, 32));
+    }
+
+    // loads `len` empty chunks in a row along the X axis, starting `from` chunks away from the origin.
+    fn load_chunk_row(app: &mut App, from: i32, len: i32) -> Vec<IVec3> {
+        let keys: Vec<_> = (from..from + len)
+            .map(|x| IVec3::new(x * CHUNK_LENGTH as i32, 64, 0))
+            .collect();
+        for key in &keys {
+            let entity = app.world.spawn((Chunk(*key), ChunkState::Spawned)).id();
+            app.world
+                .resource_mut::<ChunkEntities>()
+                .attach_entity(*key, entity);
+            app.world
+                .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+                .insert_empty(*key);
+        }
+        keys
+    }
+
+    #[test]
+    fn chunks_within_the_budget_are_unloaded_in_a_frame() {
+        let mut app = chunking_app();
+        assert!(app.world.resource::<ChunkUnloadBudget>().chunks_per_frame >= 100);
+        move_player(&mut app, IVec3::ZERO);
+        let near = loaded_chunks(&app);
+
+        let far = load_chunk_row(&mut app, 10, 100);
+        move_player(&mut app, IVec3::ZERO);
+
+        assert_eq!(loaded_chunks(&app), near);
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert!(far.iter().all(|key| !chunks.exists(*key)));
+        assert!(app.world.resource::<ChunkCommandQueue>().destroy.is_empty());
+    }
+
+    #[test]
+    fn deferred_unloads_are_dropped_when_back_in_range() {
+        let mut app = chunking_app();
+        move_player(&mut app, IVec3::ZERO);
+        let near = loaded_chunks(&app);
+        let entities = |app: &App| {
+            let chunk_entities = app.world.resource::<ChunkEntities>();
+            near.iter()
+                .map(|key| chunk_entities.entity(*key))
+                .collect::<Vec<_>>()
+        };
+        let near_entities = entities(&app);
+
+        // the player moves away and back before the unload budget allows destroying anything.
+        app.world
+            .resource_mut::<ChunkUnloadBudget>()
+            .chunks_per_frame = 0;
+        move_player(&mut app, IVec3::X * 10);
+        assert!(near.iter().all(|key| app
+            .world
+            .resource::<ChunkCommandQueue>()
+            .destroy
+            .contains(key)));
+        move_player(&mut app, IVec3::ZERO);
+
+        app.world
+            .resource_mut::<ChunkUnloadBudget>()
+            .chunks_per_frame = 128;
+        move_player(&mut app, IVec3::ZERO);
+
+        // the chunks were neither destroyed nor loaded again.
+        assert_eq!(entities(&app), near_entities);
+        assert!(app.world.resource::<ChunkCommandQueue>().destroy.is_empty());
+    }
+
+    #[test]
+    fn chunk_spawning_stays_within_the_budget() {
+        let mut unbudgeted = chunking_app();
+        move_player(&mut unbudgeted, IVec3::ZERO);
+        let in_radius = loaded_chunks(&unbudgeted);
+
+        let mut app = chunking_app();
+        app.insert_resource(ChunkSpawnBudget {
+            chunks_per_frame: 5,
+        });
+        move_player(&mut app, IVec3::ZERO);
+        let mut loaded = loaded_chunks(&app).len();
+        assert_eq!(loaded, 5);
+
+        for _ in 0..in_radius.len() {
+            app.update();
+            let now_loaded = loaded_chunks(&app).len();
+            assert!(now_loaded - loaded <= 5);
+            loaded = now_loaded;
+        }
+        assert_eq!(loaded_chunks(&app), in_radius);
+    }
+
+    // the player chunk and the five chunks a chunk away from it, the ones left under a budget of six chunks.
+    fn nearest_chunks(center: IVec3) -> HashSet<IVec3> {
+        [
+            IVec3::ZERO,
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ]
+        .map(|offset| center + offset * CHUNK_LENGTH as i32)
+        .into()
+    }
+
+    fn set_memory_budget(app: &mut App, chunks: usize) {
+        app.insert_resource(ChunkMemoryBudget {
+            max_bytes: Some(chunks * ChunkMemoryBudget::CHUNK_BYTES),
+        });
+        app.update();
+    }
+
+    #[test]
+    fn farthest_chunks_are_evicted_first() {
+        let mut app = chunking_app();
+        move_player(&mut app, IVec3::ZERO);
+        assert!(loaded_chunks(&app).len() > 6);
+
+        set_memory_budget(&mut app, 6);
+        let center = app.world.resource::<CurrentLocalPlayerChunk>().chunk_min;
+        assert_eq!(loaded_chunks(&app), nearest_chunks(center));
+
+        // the evicted chunks aren't loaded again while over the budget.
+        app.update();
+        assert_eq!(loaded_chunks(&app), nearest_chunks(center));
+    }
+
+    #[test]
+    fn modified_chunks_are_evicted_last() {
+        let mut app = chunking_app();
+        move_player(&mut app, IVec3::ZERO);
+        let center = app.world.resource::<CurrentLocalPlayerChunk>().chunk_min;
+        let far = center + IVec3::new(-1, -1, -1) * CHUNK_LENGTH as i32;
+        assert!(loaded_chunks(&app).contains(&far));
+        app.world
+            .resource_mut::<ModifiedChunks>()
+            .mark_modified(far);
+
+        set_memory_budget(&mut app, 6);
+
+        // the nearest chunks tied in distance go in the order of their keys.
+        let mut expected = nearest_chunks(center);
+        expected.remove(&(center + IVec3::NEG_X * CHUNK_LENGTH as i32));
+        expected.insert(far);
+        assert_eq!(loaded_chunks(&app), expected);
+    }
+
+    #[test]
+    fn anchored_chunks_stay_loaded_away_from_the_player() {
+        let mut app = chunking_app();
+        let anchor = ChunkAnchor {
+            position: Vec3::new(-0.5, 80.0, 40.0),
+            horizontal: 1,
+            vertical: 1,
+        };
+        assert_eq!(anchor.chunk_min(1.0), IVec3::new(-32, 64, 32));
+        app.world.resource_mut::<ChunkAnchors>().add(anchor);
+
+        move_player(&mut app, IVec3::X * 10);
+        let loaded = loaded_chunks(&app);
+        let anchored = [IVec3::new(-32, 32, 32), IVec3::new(-32, 64, 32)];
+        for key in anchored {
+            assert!(loaded.contains(&key));
+            assert!(app.world.resource::<ChunkAnchors>().is_anchored(key));
+        }
+
+        // once the anchor is gone, its chunks are unloaded like any other chunk out of range.
+        app.world.resource_mut::<ChunkAnchors>().clear();
+        app.update();
+        app.update();
+        let loaded = loaded_chunks(&app);
+        assert!(anchored.iter().all(|key| !loaded.contains(key)));
+    }
+
+    #[test]
+    fn load_shapes_at_a_radius_of_two_chunks() {
+        let center = IVec3::new(0, 128, 0);
+        let chunks = |shape| -> HashSet<IVec3> {
+            chunks_in_radius(center, shape, 2, 2, &WorldHeightLimits::default())
+                .map(|key| (key - center) / CHUNK_LENGTH as i32)
+                .collect()
+        };
+
+        let cube = chunks(ChunkLoadShape::Cube);
+        assert_eq!(cube.len(), 4 * 4 * 4);
+        assert!(cube.contains(&IVec3::splat(-2)) && cube.contains(&IVec3::ONE));
+
+        // the rows and columns at -2 are out of the circle, leaving 3x3 columns.
+        let cylinder = chunks(ChunkLoadShape::Cylinder);
+        assert_eq!(cylinder.len(), 3 * 3 * 4);
+        assert!(cylinder.contains(&IVec3::new(1, -2, 1)));
+        assert!(cylinder.is_subset(&cube));
+
+        let sphere = chunks(ChunkLoadShape::Sphere);
+        assert_eq!(sphere.len(), 3 * 3 * 3);
+        assert!(!sphere.contains(&IVec3::new(0, -2, 0)));
+        assert!(sphere.is_subset(&cylinder));
+    }
+
+    #[test]
+    fn neighbors_are_the_loaded_face_adjacent_chunks() {
+        let pos = IVec3::new(0, -32, 64);
+        let keys = ChunkEntities::neighbor_keys(pos).map(Option::unwrap);
+        assert_eq!(
+            keys,
+            [
+                IVec3::new(-32, -32, 64),
+                IVec3::new(32, -32, 64),
+                IVec3::new(0, -64, 64),
+                IVec3::new(0, 0, 64),
+                IVec3::new(0, -32, 32),
+                IVec3::new(0, -32, 96),
+            ]
+        );
+
+        let mut entities = ChunkEntities::default();
+        let (up, west) = (Entity::from_raw(1), Entity::from_raw(2));
+        entities.attach_entity(keys[3], up);
+        entities.attach_entity(keys[0], west);
+        // a chunk only sharing an edge isn't a neighbor.
+        entities.attach_entity(pos + IVec3::new(32, 32, 0), Entity::from_raw(3));
+        assert_eq!(
+            entities.neighbors(pos),
+            [Some(west), None, None, Some(up), None, None]
+        );
+    }
+
+    #[test]
+    fn world_positions_resolve_to_the_chunk_holding_them() {
+        let mut entities = ChunkEntities::default();
+        let (below, origin, above) = (
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        entities.attach_entity(IVec3::splat(-32), below);
+        entities.attach_entity(IVec3::ZERO, origin);
+        entities.attach_entity(IVec3::splat(32), above);
+
+        for (pos, entity) in [(-1, below), (0, origin), (31, origin), (32, above)] {
+            assert_eq!(
+                entities.entity_at_world(IVec3::splat(pos)),
+                Some(entity),
+                "{pos}"
+            );
+        }
+        assert_eq!(entities.entity_at_world(IVec3::new(0, 0, -1)), None);
+    }
+
+    // the key of the last chunk along the X axis before the limits of the world coordinates.
+    const EDGE: i32 = i32::MAX - (CHUNK_LENGTH as i32 - 1);
+
+    #[test]
+    fn coordinates_past_the_world_limits_are_refused() {
+        let edge = IVec3::new(EDGE, 0, 0);
+        assert_eq!(offset_chunk_key(edge, IVec3::X), None);
+        assert_eq!(
+            offset_chunk_key(edge, IVec3::NEG_X),
+            Some(IVec3::new(EDGE - CHUNK_LENGTH as i32, 0, 0))
+        );
+        assert_eq!(offset_chunk_key(IVec3::ZERO, IVec3::X * i32::MAX), None);
+
+        assert_eq!(chunk_offset(edge, -edge), None);
+        assert_eq!(
+            chunk_offset(edge, edge - IVec3::X * CHUNK_LENGTH as i32),
+            Some(IVec3::X)
+        );
+
+        assert!(region_within_limits(IVec3::ZERO, 2, 1));
+        assert!(!region_within_limits(edge, 2, 1));
+        assert!(!region_within_limits(-edge - IVec3::X, 2, 1));
+
+        assert_eq!(ChunkEntities::neighbor_keys(edge)[1], None);
+        assert!(ChunkEntities::neighbor_keys(edge)[0].is_some());
+    }
+
+    // counts the errors logged on the current thread.
+    struct ErrorCounter(Arc<AtomicUsize>);
+
+    impl Subscriber for ErrorCounter {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            if *event.metadata().level() == Level::ERROR {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn chunks_past_the_world_limits_are_left_out() {
+        let mut app = chunking_app();
+        // runs the systems on the current thread, where the errors are counted.
+        app.edit_schedule(Update, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+        let errors = Arc::new(AtomicUsize::new(0));
+        let _counter = tracing::subscriber::set_default(ErrorCounter(errors.clone()));
+
+        move_player(&mut app, IVec3::ZERO);
+        let normal = loaded_chunks(&app).len();
+        assert_eq!(errors.load(Ordering::Relaxed), 0);
+
+        let edge = IVec3::new(EDGE, 64, 0);
+        for _ in 0..3 {
+            app.world
+                .resource_mut::<CurrentLocalPlayerChunk>()
+                .chunk_min = edge;
+            app.update();
+        }
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+
+        // only the chunks on the near side of the limits are loaded, none wrapped around to the far side.
+        let loaded = loaded_chunks(&app);
+        assert!(!loaded.is_empty() && loaded.len() < normal);
+        assert!(loaded
+            .iter()
+            .all(|key| key.x >= EDGE - 2 * CHUNK_LENGTH as i32));
+    }
+}
@@ -8,7 +8,7 @@ use bevy::{
 
 use super::{
     meshing::{ChunkMeshingSet, ChunkMeshingTask},
-    Chunk,
+    Chunk, VoxelScale,
 };
 
 const ANIMATION_DURATION: f32 = 0.8;
@@ -23,6 +23,7 @@ fn attach_chunk_animation(
     mut ready_chunks: Query<(&mut Transform, &mut Visibility, &Chunk)>,
     mut removed_chunk_meshes: RemovedComponents<ChunkMeshingTask>,
     time: Res<Time>,
+    scale: Res<VoxelScale>,
     mut commands: Commands,
 ) {
     removed_chunk_meshes.iter().for_each(|entity| {
@@ -32,7 +33,7 @@ fn attach_chunk_animation(
             });
             if let Ok((mut transform, mut visibility, chunk)) = ready_chunks.get_mut(entity) {
                 *visibility = Visibility::Visible;
-                transform.translation.y = chunk.0.y as f32 - ANIMATION_HEIGHT;
+                transform.translation.y = (chunk.0.y as f32 - ANIMATION_HEIGHT) * scale.0;
             };
         }
     });
@@ -42,13 +43,15 @@ fn attach_chunk_animation(
 fn step_chunk_animation(
     mut chunks: Query<(Entity, &mut Transform, &Chunk, &ChunkSpawnAnimation)>,
     time: Res<Time>,
+    scale: Res<VoxelScale>,
     mut commands: Commands,
 ) {
     chunks.for_each_mut(|(entity, mut transform, _chunk, animation)| {
         let delta = (time.elapsed_seconds() - animation.start_time).min(ANIMATION_DURATION);
 
         let ytransform = (1. - (1. - (delta / ANIMATION_DURATION)).powi(5))
-            .mul_add(ANIMATION_HEIGHT, _chunk.0.y as f32 - ANIMATION_HEIGHT);
+            .mul_add(ANIMATION_HEIGHT, _chunk.0.y as f32 - ANIMATION_HEIGHT)
+            * scale.0;
 
         transform.translation.y = ytransform;
 
@@ -0,0 +1,297 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    log::info,
+    prelude::{IntoSystemConfigs, Local, Plugin, Res, Resource, Time, Update},
+    time::common_conditions::on_timer,
+    utils::Duration,
+};
+
+use super::{
+    meshing::ChunkMeshingSet, terrain::TerrainGenSet, ChunkMeshingBacklog, ChunkMeshingMetrics,
+    ChunkShape, TerrainGenMetrics, Voxel,
+};
+use crate::voxel::storage::ChunkMap;
+
+/// Resource enabling a periodic log record of the chunk generation and meshing work, to report performance issues with.
+///
+/// Each record is logged at the info level with the `vx_bevy::chunk_pipeline` target and holds, over the last
+/// interval: `chunks_generated`, `generation_time_ms`, `chunks_meshed` and `meshing_time_ms`, the task times being
+/// summed over the chunks. It also holds the current `loaded_chunks`, `generation_backlog` and `meshing_backlog`.
+/// The numbers are read from the [`TerrainGenMetrics`] and [`ChunkMeshingMetrics`] the world already measures.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkPipelineLogSettings {
+    pub enabled: bool,
+    /// The time between two records.
+    pub interval: Duration,
+}
+
+impl Default for ChunkPipelineLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The chunk pipeline work summed since the last record.
+#[derive(Default)]
+struct ChunkPipelineLogWindow {
+    // the time the window opened at, `None` until the logging is enabled.
+    start: Option<Duration>,
+    chunks_generated: usize,
+    generation_time: Duration,
+    chunks_meshed: usize,
+    meshing_time: Duration,
+}
+
+fn log_chunk_pipeline(
+    settings: Res<ChunkPipelineLogSettings>,
+    time: Res<Time>,
+    generation: Res<TerrainGenMetrics>,
+    meshing: Res<ChunkMeshingMetrics>,
+    meshing_backlog: Res<ChunkMeshingBacklog>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut window: Local<ChunkPipelineLogWindow>,
+) {
+    if !settings.enabled {
+        *window = ChunkPipelineLogWindow::default();
+        return;
+    }
+
+    let now = time.elapsed();
+    let start = *window.start.get_or_insert(now);
+    window.chunks_generated += generation.tasks_finished;
+    window.generation_time += generation.task_time;
+    window.chunks_meshed += meshing.tasks_finished;
+    window.meshing_time += meshing.task_time;
+
+    if now.saturating_sub(start) < settings.interval {
+        return;
+    }
+
+    info!(
+        target: "vx_bevy::chunk_pipeline",
+        chunks_generated = window.chunks_generated,
+        generation_time_ms = window.generation_time.as_secs_f64() * 1000.0,
+        chunks_meshed = window.chunks_meshed,
+        meshing_time_ms = window.meshing_time.as_secs_f64() * 1000.0,
+        loaded_chunks = chunks.len(),
+        generation_backlog = generation.tasks_pending,
+        meshing_backlog = meshing_backlog.len(),
+        "chunk pipeline over the last {:.1?}",
+        now - start
+    );
+
+    *window = ChunkPipelineLogWindow {
+        start: Some(now),
+        ..Default::default()
+    };
+}
+
+/// Reports the memory used by the voxel data of the world and the number of chunks it is split into, along with the
+/// meshing work done every frame.
+pub struct VoxelWorldDiagnosticsPlugin;
+
+impl VoxelWorldDiagnosticsPlugin {
+    /// The approximate memory used by the loaded chunk buffers, in mebibytes.
+    pub const CHUNK_DATA_SIZE: DiagnosticId =
+        DiagnosticId::from_u128(271927527056839126239160876690383972665);
+    /// The number of chunk buffers held by the world.
+    pub const LOADED_CHUNK_COUNT: DiagnosticId =
+        DiagnosticId::from_u128(118404341253561506333937145183025651380);
+    /// The number of chunk meshing tasks started during the frame.
+    pub const MESHING_TASKS_SPAWNED: DiagnosticId =
+        DiagnosticId::from_u128(296060007291027883019140735991800248597);
+    /// The average wall-clock time of the chunk meshing tasks completed during the frame, in milliseconds.
+    pub const MESHING_TASK_TIME: DiagnosticId =
+        DiagnosticId::from_u128(130250452227836012528063410637304470595);
+
+    /// How often the measurements are taken, as summing all the buffer sizes isn't free for large worlds.
+    const MEASUREMENT_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn diagnostic_system(mut diagnostics: Diagnostics, chunks: Res<ChunkMap<Voxel, ChunkShape>>) {
+        diagnostics.add_measurement(Self::CHUNK_DATA_SIZE, || {
+            chunks.size_in_bytes() as f64 / (1024.0 * 1024.0)
+        });
+        diagnostics.add_measurement(Self::LOADED_CHUNK_COUNT, || chunks.len() as f64);
+    }
+
+    fn meshing_diagnostic_system(mut diagnostics: Diagnostics, metrics: Res<ChunkMeshingMetrics>) {
+        diagnostics.add_measurement(Self::MESHING_TASKS_SPAWNED, || metrics.tasks_spawned as f64);
+
+        // frames without any finished task don't say anything about how long a task takes.
+        if metrics.tasks_finished > 0 {
+            diagnostics.add_measurement(Self::MESHING_TASK_TIME, || {
+                metrics.task_time.as_secs_f64() * 1000.0 / metrics.tasks_finished as f64
+            });
+        }
+    }
+}
+
+impl Plugin for VoxelWorldDiagnosticsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.register_diagnostic(
+            Diagnostic::new(Self::CHUNK_DATA_SIZE, "chunk_data_size", 20).with_suffix("MiB"),
+        )
+        .register_diagnostic(Diagnostic::new(
+            Self::LOADED_CHUNK_COUNT,
+            "loaded_chunk_count",
+            20,
+        ))
+        .register_diagnostic(Diagnostic::new(
+            Self::MESHING_TASKS_SPAWNED,
+            "meshing_tasks_spawned",
+            20,
+        ))
+        .register_diagnostic(
+            Diagnostic::new(Self::MESHING_TASK_TIME, "meshing_task_time", 20).with_suffix("ms"),
+        )
+        .add_systems(
+            Update,
+            Self::diagnostic_system.run_if(on_timer(Self::MEASUREMENT_INTERVAL)),
+        )
+        .add_systems(
+            Update,
+            Self::meshing_diagnostic_system.after(ChunkMeshingSet),
+        )
+        .init_resource::<ChunkPipelineLogSettings>()
+        .add_systems(
+            Update,
+            log_chunk_pipeline
+                .after(ChunkMeshingSet)
+                .after(TerrainGenSet),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::schedule::ExecutorKind,
+        prelude::App,
+        utils::{
+            tracing::{
+                self,
+                field::{Field, Visit},
+                span, Event, Metadata, Subscriber,
+            },
+            Instant,
+        },
+    };
+    use std::{
+        fmt,
+        sync::{Arc, Mutex},
+    };
+
+    // the fields of a log record, with their values formatted.
+    #[derive(Default)]
+    struct Fields(Vec<(&'static str, String)>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push((field.name(), format!("{value:?}")));
+        }
+    }
+
+    // the chunk pipeline records logged on the current thread.
+    type Records = Arc<Mutex<Vec<Fields>>>;
+
+    struct RecordCollector(Records);
+
+    impl Subscriber for RecordCollector {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            if event.metadata().target() == "vx_bevy::chunk_pipeline" {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields);
+            }
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn records_sum_the_work_of_each_interval() {
+        let mut app = App::new();
+        app.insert_resource(ChunkPipelineLogSettings {
+            enabled: true,
+            interval: Duration::from_secs(10),
+        })
+        .init_resource::<Time>()
+        .init_resource::<ChunkMeshingBacklog>()
+        .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+        .insert_resource(TerrainGenMetrics {
+            tasks_finished: 2,
+            task_time: Duration::from_millis(3),
+            tasks_pending: 5,
+        })
+        .insert_resource(ChunkMeshingMetrics {
+            tasks_spawned: 1,
+            tasks_finished: 1,
+            task_time: Duration::from_millis(4),
+        })
+        .add_systems(Update, log_chunk_pipeline);
+        // runs the system on the current thread, where the records are collected.
+        app.edit_schedule(Update, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+        let records = Records::default();
+        let _collector = tracing::subscriber::set_default(RecordCollector(records.clone()));
+
+        // frames at 0, 5 and 10 seconds, with the same work done every frame.
+        let start = Instant::now();
+        let advance_to = |app: &mut App, secs| {
+            app.world
+                .resource_mut::<Time>()
+                .update_with_instant(start + Duration::from_secs(secs));
+            app.update();
+        };
+        for secs in [0, 5, 10] {
+            advance_to(&mut app, secs);
+        }
+        {
+            let records = records.lock().unwrap();
+            assert_eq!(records.len(), 1);
+            let field = |name| {
+                records[0]
+                    .0
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            assert_eq!(field("chunks_generated"), Some("6"));
+            assert_eq!(field("generation_time_ms"), Some("9.0"));
+            assert_eq!(field("chunks_meshed"), Some("3"));
+            assert_eq!(field("meshing_time_ms"), Some("12.0"));
+            assert_eq!(field("loaded_chunks"), Some("0"));
+            assert_eq!(field("generation_backlog"), Some("5"));
+            assert_eq!(field("meshing_backlog"), Some("0"));
+        }
+
+        // disabling the logging discards the open window.
+        advance_to(&mut app, 15);
+        app.world.resource_mut::<ChunkPipelineLogSettings>().enabled = false;
+        advance_to(&mut app, 20);
+        app.world.resource_mut::<ChunkPipelineLogSettings>().enabled = true;
+        advance_to(&mut app, 25);
+        assert_eq!(records.lock().unwrap().len(), 1);
+        advance_to(&mut app, 35);
+        assert_eq!(records.lock().unwrap().len(), 2);
+    }
+}
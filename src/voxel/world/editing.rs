@@ -0,0 +1,291 @@
+use bevy::{
+    ecs::system::SystemParam,
+    math::IVec3,
+    prelude::{Event, EventWriter, Plugin, Res, ResMut, SystemSet},
+};
+
+use super::{
+    chunks::{DirtyChunks, ModifiedChunks},
+    ChunkShape, Voxel, CHUNK_LENGTH,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::ChunkMap,
+};
+
+/// How the metadata of replaced voxels is handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataPolicy {
+    /// Keep the metadata of the voxel being replaced.
+    Preserve,
+    /// Use the metadata of the replacement voxel.
+    Reset,
+}
+
+/// Sent when a voxel is broken through the [`VoxelEditor`], e.g. to play a sound at its position.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockBroken {
+    /// The position of the voxel, in voxel coordinates.
+    pub pos: IVec3,
+    /// The voxel as it was before being broken.
+    pub voxel: Voxel,
+}
+
+/// Sent when a voxel is placed through the [`VoxelEditor`].
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockPlaced {
+    /// The position of the voxel, in voxel coordinates.
+    pub pos: IVec3,
+    /// The placed voxel.
+    pub voxel: Voxel,
+}
+
+/// A system param for editing the voxel world, scheduling the edited chunks for a remesh.
+///
+/// Systems using it in [`bevy::prelude::Update`] should run in the [`VoxelEditSet`]: however many times a chunk is
+/// edited by them in a frame, it is then meshed once, from its voxels after the last edit.
+#[derive(SystemParam)]
+pub struct VoxelEditor<'w> {
+    chunks: ResMut<'w, ChunkMap<Voxel, ChunkShape>>,
+    dirty_chunks: ResMut<'w, DirtyChunks>,
+    modified_chunks: ResMut<'w, ModifiedChunks>,
+    materials: Res<'w, VoxelMaterialRegistry>,
+    broken_events: EventWriter<'w, BlockBroken>,
+    placed_events: EventWriter<'w, BlockPlaced>,
+}
+
+impl<'w> VoxelEditor<'w> {
+    // schedules an edited chunk for a remesh and records it differs from the generated terrain.
+    fn mark_edited(&mut self, chunk: IVec3) {
+        self.dirty_chunks.mark_edited(chunk);
+        self.modified_chunks.mark_modified(chunk);
+    }
+
+    // same as `mark_edited` for the chunk holding a single edited voxel, whose faces may be patched into the mesh.
+    fn mark_voxel_edited(&mut self, pos: IVec3) {
+        self.dirty_chunks.mark_voxel_edited(pos);
+        self.modified_chunks
+            .mark_modified(pos & !(CHUNK_LENGTH as i32 - 1));
+    }
+
+    /// Checks whether the voxel at `pos` is loaded, not empty and not of an unbreakable material.
+    pub fn can_break(&self, pos: IVec3) -> bool {
+        self.chunks
+            .voxel_at(pos)
+            .filter(|voxel| !voxel.is_empty())
+            .is_some_and(|voxel| {
+                self.materials
+                    .get_by_id(voxel.id)
+                    .is_none_or(|mat| !mat.flags.contains(VoxelMaterialFlags::UNBREAKABLE))
+            })
+    }
+
+    /// Empties the voxel at `pos`, unless it is unloaded, already empty or of an unbreakable material.
+    /// Returns the broken voxel, which is also sent in a [`BlockBroken`] event.
+    pub fn break_voxel(&mut self, pos: IVec3) -> Option<Voxel> {
+        if !self.can_break(pos) {
+            return None;
+        }
+
+        let voxel = std::mem::replace(self.chunks.voxel_at_mut(pos)?, Voxel::EMPTY_VOXEL);
+        self.mark_voxel_edited(pos);
+        self.broken_events.send(BlockBroken { pos, voxel });
+
+        Some(voxel)
+    }
+
+    /// Places `voxel` at `pos` if the position is loaded and empty or filled with a liquid.
+    /// Returns whether the voxel was placed, sending a [`BlockPlaced`] event if so.
+    pub fn place_voxel(&mut self, pos: IVec3, voxel: Voxel) -> bool {
+        let materials = &self.materials;
+        let Some(target) = self.chunks.voxel_at_mut(pos).filter(|target| {
+            target.is_empty()
+                || materials
+                    .get_by_id(target.id)
+                    .is_some_and(|mat| mat.flags.contains(VoxelMaterialFlags::LIQUID))
+        }) else {
+            return false;
+        };
+
+        *target = voxel;
+        self.mark_voxel_edited(pos);
+        self.placed_events.send(BlockPlaced { pos, voxel });
+
+        true
+    }
+
+    /// Replaces every voxel with the material of `from` by `to` within `min..=max`.
+    /// Returns the number of chunks that changed, each of them is remeshed once.
+    pub fn replace_in_region(
+        &mut self,
+        min: IVec3,
+        max: IVec3,
+        from: Voxel,
+        to: Voxel,
+        metadata: MetadataPolicy,
+    ) -> usize {
+        let modified_chunks = self.chunks.replace_in_region(min, max, |voxel| {
+            (voxel.id == from.id).then_some(match metadata {
+                MetadataPolicy::Preserve => to.with_metadata(voxel.metadata),
+                MetadataPolicy::Reset => to,
+            })
+        });
+
+        modified_chunks
+            .iter()
+            .for_each(|chunk| self.mark_edited(*chunk));
+
+        modified_chunks.len()
+    }
+}
+
+/// The set of systems editing the voxels with a [`VoxelEditor`], the chunk meshing systems running after it.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemSet)]
+pub struct VoxelEditSet;
+
+/// Registers the events sent by the [`VoxelEditor`].
+pub struct VoxelEditingPlugin;
+
+impl Plugin for VoxelEditingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<BlockBroken>().add_event::<BlockPlaced>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        material::VoxelMaterial,
+        materials::{Bedrock, VoxelWorldBaseMaterialsPlugin},
+    };
+    use bevy::{
+        ecs::system::SystemState,
+        prelude::{App, Events, IntoSystemConfigs, MinimalPlugins, Update, World},
+    };
+
+    const STONE: Voxel = Voxel::new(1);
+    const WATER: Voxel = Voxel::new(3);
+
+    // a world with a row of 3 chunks with a layer of stone carrying some metadata.
+    fn stone_row() -> World {
+        let mut world = World::new();
+        let mut chunks = ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {});
+        for x in 0..3 {
+            chunks.insert_empty(IVec3::X * 32 * x);
+        }
+        for x in 0..96 {
+            *chunks.voxel_at_mut(IVec3::new(x, 0, 0)).unwrap() = STONE.with_metadata(2);
+        }
+        world.insert_resource(chunks);
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<ModifiedChunks>();
+        world.init_resource::<Events<BlockBroken>>();
+        world.init_resource::<Events<BlockPlaced>>();
+        world.init_resource::<VoxelMaterialRegistry>();
+        world
+    }
+
+    #[test]
+    fn replaced_chunks_are_marked_dirty_once() {
+        for (policy, metadata) in [(MetadataPolicy::Preserve, 2), (MetadataPolicy::Reset, 7)] {
+            let mut world = stone_row();
+            let mut editor = SystemState::<VoxelEditor>::new(&mut world);
+
+            let replaced = editor.get_mut(&mut world).replace_in_region(
+                IVec3::new(20, 0, 0),
+                IVec3::new(40, 5, 5),
+                Voxel::new(1),
+                WATER.with_metadata(7),
+                policy,
+            );
+            assert_eq!(replaced, 2);
+
+            let dirty_chunks = world.resource::<DirtyChunks>();
+            assert_eq!(dirty_chunks.num_dirty(), 2);
+            assert!(dirty_chunks.is_dirty(IVec3::ZERO) && dirty_chunks.is_dirty(IVec3::X * 32));
+
+            let chunks = world.resource::<ChunkMap<Voxel, ChunkShape>>();
+            assert_eq!(
+                chunks.voxel_at(IVec3::new(20, 0, 0)),
+                Some(WATER.with_metadata(metadata))
+            );
+            assert_eq!(
+                chunks.voxel_at(IVec3::new(41, 0, 0)),
+                Some(STONE.with_metadata(2))
+            );
+        }
+    }
+
+    #[test]
+    fn bedrock_cant_be_broken() {
+        let mut app = App::new();
+        app.init_resource::<VoxelMaterialRegistry>()
+            .add_plugins(VoxelWorldBaseMaterialsPlugin);
+        let mut world = std::mem::take(&mut app.world);
+        let mut chunks = ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {});
+        chunks.insert_empty(IVec3::ZERO);
+        *chunks.voxel_at_mut(IVec3::ZERO).unwrap() = Bedrock::into_voxel();
+        *chunks.voxel_at_mut(IVec3::Y).unwrap() = STONE;
+        world.insert_resource(chunks);
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<ModifiedChunks>();
+        world.init_resource::<Events<BlockBroken>>();
+        world.init_resource::<Events<BlockPlaced>>();
+
+        let mut editor = SystemState::<VoxelEditor>::new(&mut world);
+        assert_eq!(editor.get_mut(&mut world).break_voxel(IVec3::ZERO), None);
+        assert_eq!(world.resource::<DirtyChunks>().num_dirty(), 0);
+
+        assert_eq!(
+            editor.get_mut(&mut world).break_voxel(IVec3::Y),
+            Some(STONE)
+        );
+        assert_eq!(editor.get_mut(&mut world).break_voxel(IVec3::Y), None);
+        assert!(world.resource::<DirtyChunks>().is_dirty(IVec3::ZERO));
+
+        let chunks = world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert_eq!(chunks.voxel_at(IVec3::ZERO), Some(Bedrock::into_voxel()));
+        assert_eq!(chunks.voxel_at(IVec3::Y), Some(Voxel::EMPTY_VOXEL));
+    }
+
+    const POS: IVec3 = IVec3::new(3, 4, 5);
+
+    fn break_at_pos(mut editor: VoxelEditor) {
+        editor.break_voxel(POS);
+    }
+
+    #[test]
+    fn breaking_a_voxel_twice_sends_a_single_event() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelEditingPlugin))
+            .init_resource::<VoxelMaterialRegistry>()
+            .init_resource::<DirtyChunks>()
+            .init_resource::<ModifiedChunks>()
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .add_systems(Update, (break_at_pos, break_at_pos).chain());
+
+        let mut chunks = app.world.resource_mut::<ChunkMap<Voxel, ChunkShape>>();
+        chunks.insert_empty(IVec3::ZERO);
+        *chunks.voxel_at_mut(POS).unwrap() = STONE;
+
+        app.update();
+        app.update();
+
+        let events = app.world.resource::<Events<BlockBroken>>();
+        let broken: Vec<_> = events.get_reader().iter(events).copied().collect();
+        assert_eq!(
+            broken,
+            [BlockBroken {
+                pos: POS,
+                voxel: STONE
+            }]
+        );
+        assert_eq!(
+            app.world
+                .resource::<ChunkMap<Voxel, ChunkShape>>()
+                .voxel_at(POS),
+            Some(Voxel::EMPTY_VOXEL)
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use bevy::{
+    prelude::{
+        default, resource_changed, BackgroundColor, BuildChildren, Color, Commands, Component,
+        DetectChanges, IntoSystemConfigs, NodeBundle, Plugin, Query, Res, Resource, Startup, Style,
+        Text, TextBundle, TextStyle, Update, Val, Visibility, With, Without,
+    },
+    ui::PositionType,
+};
+
+use super::{
+    chunks::ChunkLoadingSet, interaction::Hotbar, player::PlayerControllerSet,
+    CurrentLocalPlayerChunk,
+};
+use crate::voxel::material::VoxelMaterialRegistry;
+
+/// The corner of the screen the HUD panel is anchored to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HudCorner {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+/// Settings for the crosshair drawn at the center of the screen and the HUD panel showing the selected hotbar voxel
+/// and the player coordinates.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct HudSettings {
+    pub crosshair: bool,
+    /// The length of the crosshair arms, end to end, in logical pixels.
+    pub crosshair_size: f32,
+    /// The thickness of the crosshair arms, in logical pixels.
+    pub crosshair_thickness: f32,
+    pub crosshair_color: Color,
+    pub panel: bool,
+    pub panel_corner: HudCorner,
+    /// The distance between the panel and the edges of the screen, in logical pixels.
+    pub panel_margin: f32,
+    pub font_size: f32,
+}
+
+impl Default for HudSettings {
+    fn default() -> Self {
+        Self {
+            crosshair: true,
+            crosshair_size: 16.0,
+            crosshair_thickness: 2.0,
+            crosshair_color: Color::rgba(1.0, 1.0, 1.0, 0.8),
+            panel: true,
+            panel_corner: HudCorner::default(),
+            panel_margin: 8.0,
+            font_size: 18.0,
+        }
+    }
+}
+
+impl HudSettings {
+    /// Returns the layout of the crosshair node, centered on the screen.
+    fn crosshair_style(&self) -> Style {
+        Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(50.0),
+            top: Val::Percent(50.0),
+            width: Val::Px(self.crosshair_size),
+            height: Val::Px(self.crosshair_size),
+            margin: bevy::ui::UiRect {
+                left: Val::Px(-self.crosshair_size / 2.0),
+                top: Val::Px(-self.crosshair_size / 2.0),
+                ..default()
+            },
+            ..default()
+        }
+    }
+
+    /// Returns the layout of one of the crosshair arms, spanning the crosshair node along one axis.
+    fn crosshair_arm_style(&self, horizontal: bool) -> Style {
+        let offset = Val::Px((self.crosshair_size - self.crosshair_thickness) / 2.0);
+        let thickness = Val::Px(self.crosshair_thickness);
+        if horizontal {
+            Style {
+                position_type: PositionType::Absolute,
+                top: offset,
+                width: Val::Percent(100.0),
+                height: thickness,
+                ..default()
+            }
+        } else {
+            Style {
+                position_type: PositionType::Absolute,
+                left: offset,
+                width: thickness,
+                height: Val::Percent(100.0),
+                ..default()
+            }
+        }
+    }
+
+    /// Returns the layout of the panel, anchored to its corner.
+    fn panel_style(&self) -> Style {
+        let margin = Val::Px(self.panel_margin);
+        let (top, bottom) = match self.panel_corner {
+            HudCorner::TopLeft | HudCorner::TopRight => (margin, Val::Auto),
+            HudCorner::BottomLeft | HudCorner::BottomRight => (Val::Auto, margin),
+        };
+        let (left, right) = match self.panel_corner {
+            HudCorner::TopLeft | HudCorner::BottomLeft => (margin, Val::Auto),
+            HudCorner::TopRight | HudCorner::BottomRight => (Val::Auto, margin),
+        };
+
+        Style {
+            position_type: PositionType::Absolute,
+            top,
+            bottom,
+            left,
+            right,
+            ..default()
+        }
+    }
+
+    fn text_style(&self) -> TextStyle {
+        TextStyle {
+            font_size: self.font_size,
+            color: Color::WHITE,
+            ..default()
+        }
+    }
+}
+
+fn visibility(visible: bool) -> Visibility {
+    if visible {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+/// Marks the crosshair node, an arm along each axis being attached to it.
+#[derive(Component)]
+pub struct HudCrosshair;
+
+#[derive(Component)]
+struct HudCrosshairArm {
+    horizontal: bool,
+}
+
+/// Marks the text of the HUD panel.
+#[derive(Component)]
+pub struct HudPanel;
+
+fn spawn_hud(mut commands: Commands, settings: Res<HudSettings>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: settings.crosshair_style(),
+                visibility: visibility(settings.crosshair),
+                ..default()
+            },
+            HudCrosshair,
+        ))
+        .with_children(|crosshair| {
+            for horizontal in [true, false] {
+                crosshair.spawn((
+                    NodeBundle {
+                        style: settings.crosshair_arm_style(horizontal),
+                        background_color: settings.crosshair_color.into(),
+                        ..default()
+                    },
+                    HudCrosshairArm { horizontal },
+                ));
+            }
+        });
+
+    commands.spawn((
+        TextBundle::from_section("", settings.text_style()).with_style(settings.panel_style()),
+        HudPanel,
+    ));
+}
+
+/// Lays out the HUD elements again whenever their settings change.
+fn apply_hud_settings(
+    settings: Res<HudSettings>,
+    mut crosshair: Query<(&mut Style, &mut Visibility), With<HudCrosshair>>,
+    mut arms: Query<(&HudCrosshairArm, &mut Style, &mut BackgroundColor), Without<HudCrosshair>>,
+    mut panel: Query<
+        (&mut Style, &mut Visibility, &mut Text),
+        (
+            With<HudPanel>,
+            Without<HudCrosshair>,
+            Without<HudCrosshairArm>,
+        ),
+    >,
+) {
+    for (mut style, mut visible) in &mut crosshair {
+        *style = settings.crosshair_style();
+        *visible = visibility(settings.crosshair);
+    }
+
+    for (arm, mut style, mut color) in &mut arms {
+        *style = settings.crosshair_arm_style(arm.horizontal);
+        *color = settings.crosshair_color.into();
+    }
+
+    for (mut style, mut visible, mut text) in &mut panel {
+        *style = settings.panel_style();
+        *visible = visibility(settings.panel);
+        for section in &mut text.sections {
+            section.style = settings.text_style();
+        }
+    }
+}
+
+/// Shows the name of the selected hotbar voxel and the voxel coordinates of the player in the HUD panel.
+fn update_hud_panel(
+    hotbar: Res<Hotbar>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut panel: Query<&mut Text, With<HudPanel>>,
+) {
+    if !hotbar.is_changed() && !player_pos.is_changed() {
+        return;
+    }
+
+    let selected = hotbar
+        .selected_voxel()
+        .and_then(|voxel| materials.get_by_id(voxel.id))
+        .map_or("nothing", |material| material.name);
+    let pos = player_pos.world_pos;
+    let value = format!(
+        "Selected: {selected}\nX: {} Y: {} Z: {}",
+        pos.x, pos.y, pos.z
+    );
+
+    for mut text in &mut panel {
+        if let Some(section) = text.sections.first_mut() {
+            if section.value != value {
+                section.value = value.clone();
+            }
+        }
+    }
+}
+
+/// Draws the crosshair and the HUD panel, see [`HudSettings`].
+pub struct VoxelHudPlugin;
+
+impl Plugin for VoxelHudPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<HudSettings>()
+            .add_systems(Startup, spawn_hud)
+            .add_systems(
+                Update,
+                (
+                    apply_hud_settings.run_if(resource_changed::<HudSettings>()),
+                    update_hud_panel
+                        .after(PlayerControllerSet)
+                        .after(ChunkLoadingSet),
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        material::VoxelMaterialRegistry, world::materials::VoxelWorldBaseMaterialsPlugin,
+    };
+    use bevy::prelude::{App, IVec3, Vec3};
+
+    // an app drawing the HUD of a player standing at `x: 1, y: 2, z: 3`.
+    fn hud_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<VoxelMaterialRegistry>()
+            .add_plugins((VoxelWorldBaseMaterialsPlugin, VoxelHudPlugin))
+            .init_resource::<Hotbar>()
+            .insert_resource(CurrentLocalPlayerChunk {
+                chunk_min: IVec3::ZERO,
+                world_pos: IVec3::new(1, 2, 3),
+                translation: Vec3::new(1.5, 2.5, 3.5),
+            });
+        app.update();
+        app
+    }
+
+    fn panel_text(app: &mut App) -> String {
+        let mut panel = app.world.query_filtered::<&Text, With<HudPanel>>();
+        panel.single(&app.world).sections[0].value.clone()
+    }
+
+    fn selected_name(app: &App) -> &'static str {
+        let voxel = app.world.resource::<Hotbar>().selected_voxel().unwrap();
+        let materials = app.world.resource::<VoxelMaterialRegistry>();
+        materials.get_by_id(voxel.id).unwrap().name
+    }
+
+    #[test]
+    fn the_crosshair_is_centered_on_the_screen() {
+        let mut app = hud_app();
+        let mut crosshair = app
+            .world
+            .query_filtered::<(&Style, &Visibility), With<HudCrosshair>>();
+        let (style, visibility) = crosshair.single(&app.world);
+        assert_eq!(*visibility, Visibility::Inherited);
+        assert_eq!(
+            (style.left, style.top),
+            (Val::Percent(50.0), Val::Percent(50.0))
+        );
+        // offset by half its size, so its center rather than its corner is at the center of the screen.
+        assert_eq!(
+            (style.margin.left, style.margin.top),
+            (Val::Px(-8.0), Val::Px(-8.0))
+        );
+        assert_eq!(
+            app.world
+                .query::<&HudCrosshairArm>()
+                .iter(&app.world)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn the_panel_follows_the_hotbar_and_the_player() {
+        let mut app = hud_app();
+        assert_eq!(
+            panel_text(&mut app),
+            format!("Selected: {}\nX: 1 Y: 2 Z: 3", selected_name(&app))
+        );
+
+        app.world.resource_mut::<Hotbar>().cycle(1);
+        app.world
+            .resource_mut::<CurrentLocalPlayerChunk>()
+            .world_pos = IVec3::new(-4, 70, 9);
+        app.update();
+        assert_eq!(
+            panel_text(&mut app),
+            format!("Selected: {}\nX: -4 Y: 70 Z: 9", selected_name(&app))
+        );
+    }
+
+    #[test]
+    fn changing_the_settings_lays_the_hud_out_again() {
+        let mut app = hud_app();
+        app.insert_resource(HudSettings {
+            crosshair: false,
+            panel_corner: HudCorner::TopRight,
+            panel_margin: 12.0,
+            ..Default::default()
+        });
+        app.update();
+
+        let mut crosshair = app
+            .world
+            .query_filtered::<&Visibility, With<HudCrosshair>>();
+        assert_eq!(*crosshair.single(&app.world), Visibility::Hidden);
+        let mut panel = app
+            .world
+            .query_filtered::<(&Style, &Visibility), With<HudPanel>>();
+        let (style, visibility) = panel.single(&app.world);
+        assert_eq!(*visibility, Visibility::Inherited);
+        assert_eq!((style.top, style.right), (Val::Px(12.0), Val::Px(12.0)));
+        assert_eq!((style.bottom, style.left), (Val::Auto, Val::Auto));
+    }
+}
@@ -0,0 +1,601 @@
+use bevy::{
+    input::mouse::MouseWheel,
+    math::{IVec3, Vec3},
+    prelude::{
+        Color, EventReader, Gizmos, Input, IntoSystemConfigs, KeyCode, MouseButton, Plugin, Query,
+        Res, ResMut, Resource, Time, Transform, Update, With,
+    },
+};
+
+use super::{
+    editing::{VoxelEditSet, VoxelEditor},
+    materials::{Dirt, Grass, Leaves, Rock, Sand, Sandstone, Snow, Wood},
+    player::{PlayerController, PlayerControllerSet},
+    ChunkShape, Voxel, VoxelScale,
+};
+use crate::voxel::{
+    material::{VoxelMaterial, VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::{ChunkMap, VoxelRaycastHit},
+};
+
+/// Selects which voxels stop the ray targeting voxels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoxelPickMode {
+    /// Pass through liquids and hit the first solid voxel.
+    #[default]
+    Solid,
+    /// Hit the first non empty voxel, liquids included.
+    NonEmpty,
+}
+
+impl VoxelPickMode {
+    /// Checks whether the voxel stops the ray in this mode.
+    pub fn is_hit(self, voxel: Voxel, materials: &VoxelMaterialRegistry) -> bool {
+        !voxel.is_empty()
+            && (self == Self::NonEmpty
+                || materials
+                    .get_by_id(voxel.id)
+                    .is_none_or(|mat| !mat.flags.contains(VoxelMaterialFlags::LIQUID)))
+    }
+}
+
+/// Settings for the player interactions with the voxel world.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct VoxelInteractionSettings {
+    /// The maximum distance in voxels at which the player can target, break or place voxels.
+    /// Must be positive.
+    pub reach_distance: f32,
+    /// How long the break button must be held on a voxel to break it, in seconds.
+    /// Voxels break instantly when this isn't positive.
+    pub break_time: f32,
+    pub pick_mode: VoxelPickMode,
+}
+
+impl Default for VoxelInteractionSettings {
+    fn default() -> Self {
+        Self {
+            reach_distance: 8.0,
+            break_time: 0.6,
+            pick_mode: VoxelPickMode::default(),
+        }
+    }
+}
+
+impl VoxelInteractionSettings {
+    /// Returns the reach distance, falling back to the default one if it isn't positive.
+    pub fn reach_distance(&self) -> f32 {
+        if self.reach_distance > 0.0 {
+            self.reach_distance
+        } else {
+            Self::default().reach_distance
+        }
+    }
+}
+
+/// The voxel the player is currently looking at, in voxel space.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct TargetedVoxel(pub Option<VoxelRaycastHit>);
+
+/// Casts a ray from the player camera to find the voxel it is looking at.
+fn update_targeted_voxel(
+    player: Query<&Transform, With<PlayerController>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    scale: Res<VoxelScale>,
+    settings: Res<VoxelInteractionSettings>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut targeted: ResMut<TargetedVoxel>,
+) {
+    let hit = player.get_single().ok().and_then(|transform| {
+        chunks.raycast(
+            transform.translation / scale.0,
+            transform.forward(),
+            settings.reach_distance(),
+            |voxel| settings.pick_mode.is_hit(voxel, &materials),
+        )
+    });
+
+    if targeted.0 != hit {
+        targeted.0 = hit;
+    }
+}
+
+/// Draws an outline around the targeted voxel.
+fn draw_targeted_voxel_outline(
+    targeted: Res<TargetedVoxel>,
+    scale: Res<VoxelScale>,
+    mut gizmos: Gizmos,
+) {
+    if let Some(hit) = targeted.0 {
+        // slightly bigger than the voxel to not z-fight with its faces.
+        gizmos.cuboid(
+            Transform::from_translation((hit.position.as_vec3() + 0.5) * scale.0)
+                .with_scale(Vec3::splat(scale.0 * 1.005)),
+            Color::BLACK,
+        );
+    }
+}
+
+/// The voxels the player can place, along the currently selected one.
+#[derive(Resource, Clone, Debug)]
+pub struct Hotbar {
+    pub slots: Vec<Voxel>,
+    pub selected: usize,
+}
+
+impl Default for Hotbar {
+    fn default() -> Self {
+        Self {
+            slots: vec![
+                Dirt::into_voxel(),
+                Grass::into_voxel(),
+                Sand::into_voxel(),
+                Rock::into_voxel(),
+                Snow::into_voxel(),
+                Sandstone::into_voxel(),
+                Wood::into_voxel(),
+                Leaves::into_voxel(),
+            ],
+            selected: 0,
+        }
+    }
+}
+
+impl Hotbar {
+    /// Returns the selected voxel, if the hotbar isn't empty.
+    pub fn selected_voxel(&self) -> Option<Voxel> {
+        self.slots.get(self.selected).copied()
+    }
+
+    /// Moves the selection by `offset` slots, wrapping around the ends of the hotbar.
+    pub fn cycle(&mut self, offset: isize) {
+        if !self.slots.is_empty() {
+            self.selected =
+                (self.selected as isize + offset).rem_euclid(self.slots.len() as isize) as usize;
+        }
+    }
+}
+
+/// Changes the selected hotbar slot with the number keys or the mouse wheel.
+fn select_hotbar_slot(
+    keys: Res<Input<KeyCode>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut hotbar: ResMut<Hotbar>,
+) {
+    const SLOT_KEYS: [KeyCode; 9] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    if let Some(slot) = SLOT_KEYS
+        .iter()
+        .position(|key| keys.just_pressed(*key))
+        .filter(|slot| *slot < hotbar.slots.len())
+    {
+        hotbar.selected = slot;
+    }
+
+    let scroll: f32 = mouse_wheel.iter().map(|event| event.y).sum();
+    if scroll != 0.0 {
+        // scrolling up selects the previous slot.
+        hotbar.cycle(-scroll.signum() as isize);
+    }
+}
+
+/// Places the selected hotbar voxel against the targeted voxel face on right click.
+fn place_targeted_voxel(
+    player: Query<&PlayerController>,
+    btns: Res<Input<MouseButton>>,
+    targeted: Res<TargetedVoxel>,
+    hotbar: Res<Hotbar>,
+    mut editor: VoxelEditor,
+) {
+    if !btns.just_pressed(MouseButton::Right)
+        || !player.get_single().is_ok_and(|ply| ply.cursor_locked())
+    {
+        return;
+    }
+
+    // a zero normal means the player is inside the targeted voxel.
+    if let (Some(hit), Some(voxel)) = (
+        targeted.0.filter(|hit| hit.normal != IVec3::ZERO),
+        hotbar.selected_voxel(),
+    ) {
+        editor.place_voxel(hit.position + hit.normal, voxel);
+    }
+}
+
+/// The number of stages of the cracking overlay drawn on the voxel being broken.
+pub const CRACK_STAGES: usize = 8;
+
+/// The voxel the player is breaking and how far along it is.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub struct BreakProgress {
+    pub target: Option<IVec3>,
+    /// The fraction of the break time elapsed, from 0 to 1.
+    pub progress: f32,
+}
+
+impl BreakProgress {
+    /// Returns the cracking overlay stage matching the progress, from 0 (no cracks) to [`CRACK_STAGES`].
+    pub fn stage(&self) -> usize {
+        ((self.progress.clamp(0.0, 1.0) * CRACK_STAGES as f32) as usize).min(CRACK_STAGES)
+    }
+}
+
+/// Breaks the targeted voxel once the left mouse button has been held on it for the break time.
+/// Looking away or releasing the button resets the progress.
+fn break_targeted_voxel(
+    player: Query<&PlayerController>,
+    btns: Res<Input<MouseButton>>,
+    time: Res<Time>,
+    targeted: Res<TargetedVoxel>,
+    settings: Res<VoxelInteractionSettings>,
+    mut progress: ResMut<BreakProgress>,
+    mut editor: VoxelEditor,
+) {
+    let charging =
+        btns.pressed(MouseButton::Left) && player.get_single().is_ok_and(|ply| ply.cursor_locked());
+    let target = targeted
+        .0
+        .map(|hit| hit.position)
+        .filter(|pos| charging && editor.can_break(*pos));
+
+    if progress.target != target {
+        *progress = BreakProgress {
+            target,
+            progress: 0.0,
+        };
+    }
+
+    let Some(pos) = target else {
+        return;
+    };
+
+    progress.progress += if settings.break_time > 0.0 {
+        time.delta_seconds() / settings.break_time
+    } else {
+        1.0
+    };
+
+    if progress.progress >= 1.0 {
+        editor.break_voxel(pos);
+        *progress = BreakProgress::default();
+    }
+}
+
+/// Draws cracks spreading over the targeted face of the voxel being broken, one more per stage.
+fn draw_break_overlay(
+    targeted: Res<TargetedVoxel>,
+    progress: Res<BreakProgress>,
+    scale: Res<VoxelScale>,
+    mut gizmos: Gizmos,
+) {
+    // the direction and length of each crack, relative to the face size.
+    const CRACKS: [(f32, f32); CRACK_STAGES] = [
+        (0.3, 0.45),
+        (3.5, 0.4),
+        (1.9, 0.35),
+        (5.1, 0.45),
+        (0.9, 0.3),
+        (4.3, 0.38),
+        (2.7, 0.42),
+        (5.8, 0.32),
+    ];
+
+    let Some(hit) = targeted
+        .0
+        .filter(|hit| Some(hit.position) == progress.target && hit.normal != IVec3::ZERO)
+    else {
+        return;
+    };
+
+    let normal = hit.normal.as_vec3();
+    // any two axes orthogonal to the face normal span the face.
+    let (u, v) = if normal.x != 0.0 {
+        (Vec3::Y, Vec3::Z)
+    } else if normal.y != 0.0 {
+        (Vec3::X, Vec3::Z)
+    } else {
+        (Vec3::X, Vec3::Y)
+    };
+    // slightly off the face to not z-fight with it.
+    let center = hit.position.as_vec3() + 0.5 + normal * 0.505;
+
+    for (angle, length) in CRACKS.iter().take(progress.stage()) {
+        let end = center + (u * angle.cos() + v * angle.sin()) * *length;
+        gizmos.line(center * scale.0, end * scale.0, Color::BLACK);
+    }
+}
+
+/// Handles targeting voxels of the world from the player camera.
+pub struct VoxelWorldInteractionPlugin;
+
+impl Plugin for VoxelWorldInteractionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<VoxelInteractionSettings>()
+            .init_resource::<TargetedVoxel>()
+            .init_resource::<Hotbar>()
+            .init_resource::<BreakProgress>()
+            .add_systems(
+                Update,
+                (
+                    update_targeted_voxel,
+                    draw_targeted_voxel_outline,
+                    select_hotbar_slot,
+                    place_targeted_voxel,
+                    break_targeted_voxel,
+                    draw_break_overlay,
+                )
+                    .chain()
+                    .in_set(VoxelEditSet)
+                    .after(PlayerControllerSet),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        material::{VoxelMaterial, VoxelMaterialRegistry},
+        world::{
+            chunks::{DirtyChunks, ModifiedChunks},
+            editing::VoxelEditingPlugin,
+            materials::{Rock, VoxelWorldBaseMaterialsPlugin, Water},
+        },
+    };
+    use bevy::{
+        input::mouse::MouseScrollUnit,
+        prelude::{App, Entity, MinimalPlugins},
+    };
+
+    // an app targeting voxels for a player standing in an empty chunk, facing towards -Z.
+    fn targeting_app(reach_distance: f32) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(VoxelInteractionSettings {
+                reach_distance,
+                ..Default::default()
+            })
+            .init_resource::<TargetedVoxel>()
+            .init_resource::<VoxelScale>()
+            .init_resource::<VoxelMaterialRegistry>()
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .add_systems(Update, update_targeted_voxel);
+        app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert_empty(IVec3::ZERO);
+        app.world.spawn((
+            PlayerController::default(),
+            Transform::from_xyz(4.5, 4.5, 20.5),
+        ));
+        app
+    }
+
+    fn targeted_after_placing(app: &mut App, position: IVec3) -> Option<VoxelRaycastHit> {
+        *app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .voxel_at_mut(position)
+            .unwrap() = Voxel::new(1);
+        app.update();
+        app.world.resource::<TargetedVoxel>().0
+    }
+
+    #[test]
+    fn voxels_past_the_reach_are_never_targeted() {
+        // the voxel face is 6 voxels away from the player.
+        let within = IVec3::new(4, 4, 14);
+        assert_eq!(
+            targeted_after_placing(&mut targeting_app(8.0), within).map(|hit| hit.position),
+            Some(within)
+        );
+        assert_eq!(
+            targeted_after_placing(&mut targeting_app(5.0), within),
+            None
+        );
+    }
+
+    #[test]
+    fn non_positive_reaches_fall_back_to_the_default() {
+        let default_reach = VoxelInteractionSettings::default().reach_distance;
+        for reach_distance in [0.0, -3.0] {
+            let settings = VoxelInteractionSettings {
+                reach_distance,
+                ..Default::default()
+            };
+            assert_eq!(settings.reach_distance(), default_reach);
+        }
+
+        let within_default = IVec3::new(4, 4, 14);
+        assert_eq!(
+            targeted_after_placing(&mut targeting_app(0.0), within_default).map(|hit| hit.position),
+            Some(within_default)
+        );
+    }
+
+    // an app running the hotbar and placement systems for a player with a grabbed cursor, over an empty chunk.
+    fn interaction_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelEditingPlugin))
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .add_event::<MouseWheel>()
+            .init_resource::<Hotbar>()
+            .init_resource::<TargetedVoxel>()
+            .init_resource::<VoxelMaterialRegistry>()
+            .init_resource::<DirtyChunks>()
+            .init_resource::<ModifiedChunks>()
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .add_systems(Update, (select_hotbar_slot, place_targeted_voxel).chain());
+        app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert_empty(IVec3::ZERO);
+        app.world.spawn(PlayerController::with_cursor_locked());
+        app
+    }
+
+    fn scroll(app: &mut App, y: f32) {
+        app.world.send_event(MouseWheel {
+            unit: MouseScrollUnit::Line,
+            x: 0.0,
+            y,
+            window: Entity::PLACEHOLDER,
+        });
+        app.update();
+    }
+
+    fn press_key(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app.update();
+        app.world.resource_mut::<Input<KeyCode>>().reset_all();
+    }
+
+    fn selected(app: &App) -> usize {
+        app.world.resource::<Hotbar>().selected
+    }
+
+    #[test]
+    fn scrolling_and_number_keys_change_the_selection() {
+        let mut app = interaction_app();
+        let slots = app.world.resource::<Hotbar>().slots.len();
+
+        scroll(&mut app, -1.0);
+        assert_eq!(selected(&app), 1);
+
+        press_key(&mut app, KeyCode::Key3);
+        assert_eq!(selected(&app), 2);
+
+        // scrolling up past the first slot wraps around to the last one.
+        for _ in 0..3 {
+            scroll(&mut app, 1.0);
+        }
+        assert_eq!(selected(&app), slots - 1);
+
+        // keys past the last slot are ignored.
+        app.world.resource_mut::<Hotbar>().slots.truncate(4);
+        press_key(&mut app, KeyCode::Key6);
+        assert_eq!(selected(&app), slots - 1);
+    }
+
+    #[test]
+    fn placement_uses_the_selected_voxel() {
+        let mut app = interaction_app();
+        press_key(&mut app, KeyCode::Key4);
+        let voxel = app.world.resource::<Hotbar>().selected_voxel().unwrap();
+
+        app.world.resource_mut::<TargetedVoxel>().0 = Some(VoxelRaycastHit {
+            position: IVec3::splat(5),
+            normal: IVec3::Y,
+            distance: 2.0,
+        });
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Right);
+        app.update();
+
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert_eq!(chunks.voxel_at(IVec3::new(5, 6, 5)), Some(voxel));
+        assert_eq!(chunks.voxel_at(IVec3::splat(5)), Some(Voxel::default()));
+        assert!(app.world.resource::<DirtyChunks>().is_dirty(IVec3::ZERO));
+    }
+
+    #[test]
+    fn crack_stage_follows_the_break_progress() {
+        let stage = |progress: f32| {
+            BreakProgress {
+                target: Some(IVec3::ZERO),
+                progress,
+            }
+            .stage()
+        };
+
+        assert_eq!(stage(0.0), 0);
+        assert_eq!(stage(0.1), 0);
+        assert_eq!(stage(1.0 / CRACK_STAGES as f32), 1);
+        assert_eq!(stage(0.5), CRACK_STAGES / 2);
+        assert_eq!(stage(0.99), CRACK_STAGES - 1);
+        assert_eq!(stage(1.0), CRACK_STAGES);
+        assert_eq!(stage(-1.0), 0);
+        assert_eq!(stage(3.0), CRACK_STAGES);
+    }
+
+    // an interaction app breaking the voxel targeted at the specified position, taking `break_time` seconds.
+    fn breaking_app(break_time: f32, target: IVec3) -> App {
+        let mut app = interaction_app();
+        app.insert_resource(VoxelInteractionSettings {
+            break_time,
+            ..Default::default()
+        })
+        .init_resource::<BreakProgress>()
+        .add_systems(Update, break_targeted_voxel);
+        *app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .voxel_at_mut(target)
+            .unwrap() = Voxel::new(1);
+        app.world.resource_mut::<TargetedVoxel>().0 = Some(VoxelRaycastHit {
+            position: target,
+            normal: IVec3::Y,
+            distance: 2.0,
+        });
+        app
+    }
+
+    #[test]
+    fn holding_the_button_breaks_the_targeted_voxel() {
+        let target = IVec3::splat(5);
+        let mut app = breaking_app(3600.0, target);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Left);
+        for _ in 0..3 {
+            app.update();
+        }
+        let progress = *app.world.resource::<BreakProgress>();
+        assert_eq!(progress.target, Some(target));
+        assert!(progress.progress > 0.0 && progress.progress < 1.0);
+
+        // releasing the button resets the progress, leaving the voxel in place.
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .release(MouseButton::Left);
+        app.update();
+        assert_eq!(
+            *app.world.resource::<BreakProgress>(),
+            BreakProgress::default()
+        );
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert_eq!(chunks.voxel_at(target), Some(Voxel::new(1)));
+
+        let mut app = breaking_app(0.0, target);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Left);
+        app.update();
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert_eq!(chunks.voxel_at(target), Some(Voxel::default()));
+    }
+
+    #[test]
+    fn solid_pick_mode_passes_through_liquids() {
+        let mut app = App::new();
+        app.init_resource::<VoxelMaterialRegistry>()
+            .add_plugins(VoxelWorldBaseMaterialsPlugin);
+        let materials = app.world.resource::<VoxelMaterialRegistry>();
+        let water = Voxel::new(Water::ID);
+        let rock = Voxel::new(Rock::ID);
+
+        assert!(!VoxelPickMode::Solid.is_hit(water, materials));
+        assert!(VoxelPickMode::NonEmpty.is_hit(water, materials));
+        for mode in [VoxelPickMode::Solid, VoxelPickMode::NonEmpty] {
+            assert!(mode.is_hit(rock, materials));
+            assert!(!mode.is_hit(Voxel::EMPTY_VOXEL, materials));
+        }
+    }
+}
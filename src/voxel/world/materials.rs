@@ -125,7 +125,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
         registry.register_material::<Leaves>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(109, 177, 56),
             name: Leaves::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            flags: VoxelMaterialFlags::SOLID.union(VoxelMaterialFlags::NO_MERGE),
             emissive: Color::BLACK,
             perceptual_roughness: 0.73,
             metallic: 1.0,
@@ -135,7 +135,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
         registry.register_material::<PineLeaves>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(135, 201, 167),
             name: PineLeaves::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            flags: VoxelMaterialFlags::SOLID.union(VoxelMaterialFlags::NO_MERGE),
             emissive: Color::BLACK,
             perceptual_roughness: 0.73,
             metallic: 1.0,
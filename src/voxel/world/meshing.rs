@@ -1,20 +1,35 @@
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use super::{
-    chunks::{ChunkEntities, ChunkLoadingSet, DirtyChunks},
+    chunks::{ChunkEntities, ChunkLoadingSet, CurrentLocalPlayerChunk, DirtyChunks},
+    editing::VoxelEditSet,
     terrain::TerrainGenSet,
-    Chunk, ChunkShape, Voxel, CHUNK_LENGTH,
+    Chunk, ChunkShape, ChunkState, Voxel, VoxelScale, VoxelTaskPools, WorldHeightLimits,
+    CHUNK_LENGTH,
 };
 use crate::voxel::{
-    render::{mesh_buffer, ChunkMaterialSingleton, MeshBuffers},
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    render::{
+        mesh_buffer_cancellable, patch_raw_mesh, ChunkMaterialSingleton, FaceWinding,
+        MaterialIdSet, MeshBuffers, MeshingAlgorithm, MeshingOptions, RawMesh,
+    },
     storage::ChunkMap,
 };
 use bevy::{
+    ecs::{query::Has, system::SystemParam},
     pbr::NotShadowCaster,
     prelude::*,
     render::{primitives::Aabb, render_resource::PrimitiveTopology},
-    tasks::{AsyncComputeTaskPool, Task},
+    tasks::Task,
 };
+use float_ord::FloatOrd;
 use futures_lite::future;
 use once_cell::sync::Lazy;
 use thread_local::ThreadLocal;
@@ -24,6 +39,7 @@ pub fn prepare_chunks(
     chunks: Query<(Entity, &Chunk), Added<Chunk>>,
     mut meshes: ResMut<Assets<Mesh>>,
     material: Res<ChunkMaterialSingleton>,
+    scale: Res<VoxelScale>,
     mut cmds: Commands,
 ) {
     for (chunk, chunk_key) in chunks.iter() {
@@ -32,11 +48,11 @@ pub fn prepare_chunks(
             MaterialMeshBundle {
                 material: (**material).clone(),
                 mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
-                transform: Transform::from_translation(chunk_key.0.as_vec3()),
+                transform: Transform::from_translation(chunk_key.0.as_vec3() * scale.0),
                 visibility: Visibility::Hidden,
                 ..Default::default()
             },
-            Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_LENGTH as f32)),
+            Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_LENGTH as f32 * scale.0)),
         ));
         // There is no need to cast shadows for chunks below the surface.
         if chunk_key.0.y <= 64 {
@@ -49,57 +65,478 @@ pub fn prepare_chunks(
 static SHARED_MESH_BUFFERS: Lazy<ThreadLocal<RefCell<MeshBuffers<Voxel, ChunkShape>>>> =
     Lazy::new(ThreadLocal::default);
 
-/// Queues meshing tasks for the chunks in need of a remesh.
-fn queue_mesh_tasks(
+/// Flags the chunks invalidated this frame as in need of a remesh.
+fn mark_dirty_chunks(
     mut commands: Commands,
+    time: Res<Time>,
     dirty_chunks: Res<DirtyChunks>,
     chunk_entities: Res<ChunkEntities>,
+    mut chunk_states: Query<&mut ChunkState>,
+) {
+    dirty_chunks
+        .iter_dirty()
+        .filter_map(|key| chunk_entities.entity(*key).map(|entity| (*key, entity)))
+        .for_each(|(key, entity)| {
+            // chunks still waiting for their voxel data get meshed once generated.
+            if let Ok(mut state) = chunk_states.get_mut(entity) {
+                // chunks already waiting keep their place in the queue.
+                if *state != ChunkState::NeedsMeshing
+                    && state.can_transition_to(ChunkState::NeedsMeshing)
+                {
+                    state.transition(ChunkState::NeedsMeshing);
+                    commands
+                        .entity(entity)
+                        .insert(ChunkQueuedForMeshing(time.elapsed()));
+                }
+
+                if dirty_chunks.is_edited(key) {
+                    commands.entity(entity).insert(ChunkEditedPriority);
+                }
+            }
+        });
+}
+
+// Returns the options the chunks are meshed with.
+fn chunk_meshing_options(
+    settings: &ChunkMeshingSettings,
+    scale: &VoxelScale,
+    materials: &VoxelMaterialRegistry,
+) -> MeshingOptions {
+    let mut unmerged_materials = MaterialIdSet::default();
+    materials
+        .iter_mats()
+        .enumerate()
+        .filter(|(_, mat)| mat.flags.contains(VoxelMaterialFlags::NO_MERGE))
+        .for_each(|(id, _)| unmerged_materials.insert(id as u8));
+
+    MeshingOptions {
+        algorithm: settings.algorithm,
+        scale: scale.0,
+        tangents: settings.tangents,
+        parallel_threshold: settings.parallel_threshold,
+        unmerged_materials,
+        merge_metadata_mask: settings.merge_metadata_mask,
+        border_tint: settings.border_tint,
+        winding: settings.winding,
+        max_quad_size: settings.max_quad_size,
+        packed_positions: settings.packed_vertices,
+        ..Default::default()
+    }
+}
+
+// Returns the options the chunk at the specified key is meshed with, culling the faces looking under the world floor.
+fn chunk_key_meshing_options(
+    options: &MeshingOptions,
+    settings: &ChunkMeshingSettings,
+    height_limits: &WorldHeightLimits,
+    key: IVec3,
+) -> MeshingOptions {
+    MeshingOptions {
+        cull_bottom_layer: settings.cull_world_floor && key.y == height_limits.lowest_chunk(),
+        ..*options
+    }
+}
+
+/// Patches the faces of the few voxels edited in a chunk into its current mesh, rather than meshing it again.
+/// Only up to date meshes can be patched, the other dirty chunks are left to be remeshed.
+fn patch_edited_chunks(
+    mut chunk_query: Query<
+        (Entity, &Chunk, &Handle<Mesh>, &mut ChunkState),
+        Without<ChunkMeshingTask>,
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
+    mut commands: Commands,
     chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    chunk_entities: Res<ChunkEntities>,
+    settings: Res<ChunkMeshingSettings>,
+    scale: Res<VoxelScale>,
+    materials: Res<VoxelMaterialRegistry>,
+    height_limits: Res<WorldHeightLimits>,
+    time: Res<Time>,
 ) {
-    let task_pool = AsyncComputeTaskPool::get();
+    let Some(max_edited_voxels) = settings.incremental_edits else {
+        return;
+    };
 
-    dirty_chunks
+    if settings.algorithm != MeshingAlgorithm::PerVoxelCubes {
+        return;
+    }
+
+    let options = chunk_meshing_options(&settings, &scale, &materials);
+    let edited: Vec<_> = dirty_chunks
         .iter_dirty()
-        .filter_map(|key| chunk_entities.entity(*key).map(|entity| (key, entity)))
-        .filter_map(|(key, entity)| {
-            chunks
-                .buffer_at(*key)
-                .map(|buffer| (buffer.clone(), entity))
+        .filter(|key| {
+            dirty_chunks
+                .edited_voxels(**key)
+                .is_some_and(|voxels| voxels.len() <= max_edited_voxels)
         })
-        .map(|(buffer, entity)| {
-            (
-                entity,
-                ChunkMeshingTask(task_pool.spawn(async move {
-                    let mut mesh_buffers = SHARED_MESH_BUFFERS
-                        .get_or(|| {
-                            RefCell::new(MeshBuffers::<Voxel, ChunkShape>::new(ChunkShape {}))
-                        })
-                        .borrow_mut();
-
-                    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-                    mesh_buffer(&buffer, &mut mesh_buffers, &mut mesh, 1.0);
-
-                    mesh
-                })),
-            )
+        .copied()
+        .collect();
+
+    for key in edited {
+        let Some((entity, _, handle, mut state)) = chunk_entities
+            .entity(key)
+            .and_then(|entity| chunk_query.get_mut(entity).ok())
+        else {
+            continue;
+        };
+
+        if *state != ChunkState::Meshed {
+            continue;
+        }
+
+        let (Some(buffer), Some(mesh)) = (chunks.buffer_at(key), meshes.get_mut(handle)) else {
+            continue;
+        };
+
+        let options = chunk_key_meshing_options(&options, &settings, &height_limits, key);
+        let Some(mut raw_mesh) = RawMesh::from_mesh(mesh, &options) else {
+            continue;
+        };
+
+        let patched = dirty_chunks
+            .edited_voxels(key)
+            .unwrap_or_default()
+            .iter()
+            .all(|pos| patch_raw_mesh(buffer, &mut raw_mesh, (*pos - key).as_uvec3(), &options));
+
+        if !patched {
+            continue;
+        }
+
+        raw_mesh.insert_into(mesh, &options);
+        state.transition(ChunkState::Meshing);
+        state.transition(ChunkState::Meshed);
+        dirty_chunks.mark_clean(key);
+        meshed_events.send(ChunkMeshed(key, entity));
+        commands
+            .entity(entity)
+            .insert(ChunkLastMeshed(time.elapsed()));
+    }
+}
+
+/// Queues meshing tasks for the chunks in need of a remesh, the ones edited by the player then the closest to the
+/// player first. A fraction of the budget goes to the chunks waiting the longest, see
+/// [`ChunkMeshingBudget::oldest_first_fraction`].
+fn queue_mesh_tasks(
+    mut commands: Commands,
+    mut pending_chunks: Query<
+        (
+            Entity,
+            &Chunk,
+            &mut ChunkState,
+            Option<&ChunkLastMeshed>,
+            Has<ChunkEditedPriority>,
+            Option<&ChunkQueuedForMeshing>,
+        ),
+        Without<ChunkMeshingTask>,
+    >,
+    mut oldest_first_credit: Local<f32>,
+    running_tasks: Query<(), With<ChunkMeshingTask>>,
+    time: Res<Time>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    budget: Res<ChunkMeshingBudget>,
+    settings: Res<ChunkMeshingSettings>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    scale: Res<VoxelScale>,
+    materials: Res<VoxelMaterialRegistry>,
+    height_limits: Res<WorldHeightLimits>,
+    task_pools: Res<VoxelTaskPools>,
+    mut metrics: ResMut<ChunkMeshingMetrics>,
+) {
+    let task_pool = task_pools.meshing();
+    let options = chunk_meshing_options(&settings, &scale, &materials);
+    metrics.tasks_spawned = 0;
+
+    let running = running_tasks.iter().count();
+    let available = budget
+        .max_concurrent_tasks
+        .saturating_sub(running)
+        .min(budget.meshes_per_frame);
+
+    if available == 0 {
+        return;
+    }
+
+    // chunks without any voxel data (e.g. unloaded while pending) are skipped without consuming the budget.
+    // chunks meshed too recently stay pending, so the edits made during their cooldown are coalesced in a single remesh.
+    let now = time.elapsed();
+    let mut candidates: Vec<_> = pending_chunks
+        .iter()
+        .filter(|(_, _, state, _, _, _)| **state == ChunkState::NeedsMeshing)
+        .filter(|(_, _, _, last_meshed, _, _)| {
+            last_meshed.is_none_or(|last| now.saturating_sub(last.0) >= budget.remesh_cooldown)
+        })
+        .filter_map(|(entity, chunk, _, _, edited, queued)| {
+            chunks.buffer_at(chunk.0).map(|buffer| {
+                let queued_at = queued.map_or(now, |queued| queued.0);
+                (entity, chunk.0, buffer, edited, queued_at)
+            })
+        })
+        .collect();
+
+    candidates.sort_unstable_by_key(|(_, key, _, edited, _)| {
+        (
+            !edited,
+            FloatOrd(key.as_vec3().distance(player_pos.chunk_min.as_vec3())),
+        )
+    });
+
+    // the reserved share of the budget accumulates over frames, so it is honored even when a single task can start.
+    let mut reserved = 0;
+    if candidates.len() > available {
+        *oldest_first_credit += available as f32 * budget.oldest_first_fraction.clamp(0.0, 1.0);
+        reserved = (oldest_first_credit.floor() as usize).min(available);
+        *oldest_first_credit -= reserved as f32;
+    }
+
+    let mut batch = Vec::with_capacity(available);
+    if reserved > 0 {
+        let mut oldest: Vec<_> = (0..candidates.len()).collect();
+        oldest.sort_unstable_by_key(|index| (candidates[*index].4, *index));
+        let mut oldest = oldest[..reserved].to_vec();
+        // remove from the back so the remaining indices stay valid.
+        oldest.sort_unstable_by(|a, b| b.cmp(a));
+        batch.extend(oldest.into_iter().map(|index| candidates.remove(index)));
+    }
+    let remaining = available - batch.len();
+    batch.extend(candidates.into_iter().take(remaining));
+
+    let max_retained_buffer_bytes = settings.max_retained_buffer_bytes;
+    let mut scheduled = 0;
+
+    batch
+        .into_iter()
+        .map(|(entity, key, buffer, _, _)| {
+            let options = chunk_key_meshing_options(&options, &settings, &height_limits, key);
+            let buffer = buffer.clone();
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let task_cancelled = cancelled.clone();
+            // large chunks are split into slabs meshed on the same pool.
+            let slab_pool = task_pool.clone();
+            let task = task_pool.spawn(async move {
+                let start = Instant::now();
+                let mut mesh_buffers = SHARED_MESH_BUFFERS
+                    .get_or(|| RefCell::new(MeshBuffers::<Voxel, ChunkShape>::new(ChunkShape {})))
+                    .borrow_mut();
+
+                let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+                let meshed = mesh_buffer_cancellable(
+                    &buffer,
+                    &mut mesh_buffers,
+                    &mut mesh,
+                    &options,
+                    Some(&slab_pool),
+                    || task_cancelled.load(Ordering::Relaxed),
+                );
+
+                if let Some(max_bytes) = max_retained_buffer_bytes {
+                    mesh_buffers.shrink_to(max_bytes);
+                }
+
+                (meshed.then_some(mesh), start.elapsed())
+            });
+
+            (entity, ChunkMeshingTask { task, cancelled })
         })
         .for_each(|(entity, task)| {
-            commands.entity(entity).insert(task);
+            scheduled += 1;
+            if let Ok((_, _, mut state, _, _, _)) = pending_chunks.get_mut(entity) {
+                state.transition(ChunkState::Meshing);
+            }
+            commands
+                .entity(entity)
+                .insert(task)
+                .remove::<(ChunkEditedPriority, ChunkQueuedForMeshing)>();
         });
+
+    debug_assert!(running + scheduled <= budget.max_concurrent_tasks);
+    metrics.tasks_spawned = scheduled;
+}
+
+/// Moves the loaded chunks and schedules them for a remesh whenever the voxel scale changes.
+fn apply_voxel_scale(
+    scale: Res<VoxelScale>,
+    mut chunks: Query<(&Chunk, &mut Transform, &mut Aabb)>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+) {
+    if !scale.is_changed() || scale.is_added() {
+        return;
+    }
+
+    chunks.for_each_mut(|(chunk, mut transform, mut aabb)| {
+        transform.translation = chunk.0.as_vec3() * scale.0;
+        *aabb = Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_LENGTH as f32 * scale.0));
+        dirty_chunks.mark_dirty(chunk.0);
+    });
 }
 
-/// Polls and process the generated chunk meshes
+/// Schedules all the loaded chunks for a remesh whenever the meshing settings change.
+fn apply_meshing_settings(
+    settings: Res<ChunkMeshingSettings>,
+    chunks: Query<&Chunk>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    chunks.for_each(|chunk| dirty_chunks.mark_dirty(chunk.0));
+}
+
+/// Polls and process the generated chunk meshes, up to the per frame budget.
+/// Finished meshes are applied ordered by chunk key, so the same finished tasks always yield the same writes.
+/// Tasks finished past the budget are left unpolled and applied on the next frames.
 fn process_mesh_tasks(
     mut meshes: ResMut<Assets<Mesh>>,
-    mut chunk_query: Query<(Entity, &Handle<Mesh>, &mut ChunkMeshingTask), With<Chunk>>,
+    mut chunk_query: Query<(
+        Entity,
+        &Chunk,
+        &Handle<Mesh>,
+        &mut ChunkMeshingTask,
+        &mut ChunkState,
+    )>,
+    time: Res<Time>,
+    budget: Res<ChunkMeshingBudget>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
+    mut metrics: ResMut<ChunkMeshingMetrics>,
     mut commands: Commands,
 ) {
-    chunk_query.for_each_mut(|(entity, handle, mut mesh_task)| {
-        if let Some(mesh) = future::block_on(future::poll_once(&mut mesh_task.0)) {
-            *meshes.get_mut(handle).unwrap() = mesh;
-            commands.entity(entity).remove::<ChunkMeshingTask>();
+    let mut finished: Vec<_> = chunk_query
+        .iter_mut()
+        .filter_map(|(entity, chunk, _, mut mesh_task, _)| {
+            future::block_on(future::poll_once(&mut mesh_task.task))
+                .map(|mesh| (chunk.0, entity, mesh))
+        })
+        .take(budget.applied_meshes_per_frame)
+        .collect();
+
+    finished.sort_unstable_by_key(|(key, _, _)| key.to_array());
+
+    metrics.tasks_finished = finished.len();
+    metrics.task_time = finished.iter().map(|(_, _, (_, time))| *time).sum();
+
+    for (key, entity, (mesh, _)) in finished {
+        let Ok((_, _, handle, _, mut state)) = chunk_query.get_mut(entity) else {
+            continue;
+        };
+
+        // the mesh asset may already be gone if the chunk is being unloaded.
+        if let (Some(chunk_mesh), Some(mesh)) = (meshes.get_mut(handle), mesh) {
+            *chunk_mesh = mesh;
+            meshed_events.send(ChunkMeshed(key, entity));
         }
-    });
+        // the chunk may have been invalidated again while it was being meshed.
+        if *state == ChunkState::Meshing {
+            state.transition(ChunkState::Meshed);
+        }
+        commands
+            .entity(entity)
+            .remove::<ChunkMeshingTask>()
+            .insert(ChunkLastMeshed(time.elapsed()));
+    }
+}
+
+/// Resource measuring the meshing work of the last frame, e.g. to tell whether the meshing is bound by its budget or by
+/// the number of chunks to mesh. See [`super::diagnostics::VoxelWorldDiagnosticsPlugin`].
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct ChunkMeshingMetrics {
+    /// The number of meshing tasks started, at most the budget but less when fewer chunks need meshing.
+    pub tasks_spawned: usize,
+    /// The number of meshing tasks whose output was collected.
+    pub tasks_finished: usize,
+    /// The wall-clock time the collected tasks took to mesh their chunk, summed.
+    pub task_time: Duration,
+}
+
+/// Sent when the new mesh of a chunk is applied, with the chunk key and entity.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkMeshed(pub IVec3, pub Entity);
+
+/// Collects the chunks waiting for a (re)mesh once the meshing tasks of the frame are queued.
+fn update_meshing_backlog(
+    chunks: Query<(&Chunk, &ChunkState)>,
+    mut backlog: ResMut<ChunkMeshingBacklog>,
+) {
+    backlog.chunks.clear();
+    backlog.chunks.extend(
+        chunks
+            .iter()
+            .filter(|(_, state)| **state == ChunkState::NeedsMeshing)
+            .map(|(chunk, _)| chunk.0),
+    );
+    backlog.chunks.sort_unstable_by_key(|key| key.to_array());
+}
+
+/// Resource listing the chunks in need of meshing for which no task was started yet, updated every frame.
+#[derive(Resource, Default, Debug)]
+pub struct ChunkMeshingBacklog {
+    chunks: Vec<IVec3>,
+}
+
+impl ChunkMeshingBacklog {
+    /// Returns the number of chunks waiting for meshing.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns whether no chunk is waiting for meshing.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns the keys of the chunks waiting for meshing, sorted.
+    pub fn keys(&self) -> &[IVec3] {
+        &self.chunks
+    }
+}
+
+/// Statistics about the current mesh of a chunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Whether the chunk is waiting for a (re)mesh.
+    pub needs_meshing: bool,
+}
+
+/// A system param for querying the mesh statistics of the loaded chunks.
+#[derive(SystemParam)]
+pub struct ChunkMeshStatsQuery<'w, 's> {
+    chunk_entities: Res<'w, ChunkEntities>,
+    dirty_chunks: Res<'w, DirtyChunks>,
+    meshes: Res<'w, Assets<Mesh>>,
+    chunks: Query<
+        'w,
+        's,
+        (
+            &'static Handle<Mesh>,
+            &'static ChunkState,
+            Has<ChunkMeshingTask>,
+        ),
+        With<Chunk>,
+    >,
+}
+
+impl<'w, 's> ChunkMeshStatsQuery<'w, 's> {
+    /// Returns the mesh statistics of the chunk at the specified key, if it is loaded and prepared for rendering.
+    pub fn chunk_mesh_stats(&self, chunk_key: IVec3) -> Option<MeshStats> {
+        let (handle, state, meshing) = self
+            .chunk_entities
+            .entity(chunk_key)
+            .and_then(|entity| self.chunks.get(entity).ok())?;
+        let mesh = self.meshes.get(handle)?;
+
+        Some(MeshStats {
+            vertex_count: mesh.count_vertices(),
+            triangle_count: mesh.indices().map_or(0, |indices| indices.len() / 3),
+            needs_meshing: *state == ChunkState::NeedsMeshing
+                || meshing
+                || self.dirty_chunks.is_dirty(chunk_key),
+        })
+    }
 }
 
 /// The set of systems which asynchronusly mesh the chunks.
@@ -111,18 +548,948 @@ pub struct VoxelWorldMeshingPlugin;
 
 impl Plugin for VoxelWorldMeshingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.configure_set(
-            Update,
-            ChunkMeshingSet.after(TerrainGenSet).after(ChunkLoadingSet),
-        )
-        .add_systems(
-            Update,
-            (prepare_chunks, queue_mesh_tasks, process_mesh_tasks)
-                .chain()
-                .in_set(ChunkMeshingSet),
-        );
+        app.init_resource::<ChunkMeshingBudget>()
+            .init_resource::<ChunkMeshingSettings>()
+            .init_resource::<ChunkMeshingBacklog>()
+            .init_resource::<ChunkMeshingMetrics>()
+            .add_event::<ChunkMeshed>()
+            .configure_set(
+                Update,
+                ChunkMeshingSet
+                    .after(TerrainGenSet)
+                    .after(ChunkLoadingSet)
+                    // all the edits of the frame are in before their chunks get queued.
+                    .after(VoxelEditSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    prepare_chunks,
+                    apply_voxel_scale,
+                    apply_meshing_settings,
+                    patch_edited_chunks,
+                    mark_dirty_chunks,
+                    apply_deferred,
+                    queue_mesh_tasks,
+                    process_mesh_tasks,
+                    update_meshing_backlog,
+                )
+                    .chain()
+                    .in_set(ChunkMeshingSet),
+            );
     }
 }
 
+/// A running meshing task, cancelled when dropped along with its chunk entity.
+/// Dropping a task only stops it at its next await point, so the meshing also checks the flag between its steps.
+#[derive(Component)]
+pub struct ChunkMeshingTask {
+    // yields no mesh when cancelled, along with the time spent meshing.
+    task: Task<(Option<Mesh>, Duration)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for ChunkMeshingTask {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Marks a chunk edited by the player, whose remesh is queued before the ones of the chunks loaded in the meantime.
+#[derive(Component)]
+pub struct ChunkEditedPriority;
+
+/// The time at which a chunk started waiting for a meshing task.
 #[derive(Component)]
-pub struct ChunkMeshingTask(Task<Mesh>);
+pub struct ChunkQueuedForMeshing(Duration);
+
+/// The time at which the current mesh of a chunk was applied.
+#[derive(Component)]
+pub struct ChunkLastMeshed(Duration);
+
+/// Resource controlling the content of the generated chunk meshes.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkMeshingSettings {
+    pub algorithm: MeshingAlgorithm,
+    /// Emit texture coordinates and tangents for normal-mapped voxel materials.
+    /// This is off by default as the terrain material doesn't use them.
+    pub tangents: bool,
+    /// Chunk side length from which a single chunk is meshed in parallel slabs on the meshing task pool, see
+    /// [`MeshingOptions::parallel_threshold`]. This is only worth it for chunks much larger than the default
+    /// [`CHUNK_LENGTH`], so the default of twice that length keeps chunks of the default size in a single pass. Lower
+    /// it to `CHUNK_LENGTH` or less to split them.
+    pub parallel_threshold: Option<u32>,
+    /// Tint the faces of the outermost voxel layer of each chunk, to spot meshing seams.
+    pub border_tint: bool,
+    /// The winding of the chunk mesh triangles. Keep the default one for the terrain material, which culls back faces.
+    pub winding: FaceWinding,
+    /// The voxel metadata bits which keep faces of a same material from being merged, see [`MeshingOptions`].
+    pub merge_metadata_mask: u8,
+    /// The number of bytes of meshing buffers each worker thread keeps around between chunks, see
+    /// [`MeshBuffers::shrink_to`]. `None` keeps the buffers as large as the most complex chunk they meshed.
+    pub max_retained_buffer_bytes: Option<usize>,
+    /// The number of voxels the player may edit in a chunk during a frame for the faces around them to be patched
+    /// into the chunk mesh instead of meshing the whole chunk again, see [`patch_raw_mesh`].
+    /// Only meshes built with [`MeshingAlgorithm::PerVoxelCubes`] can be patched, `None` always remeshes.
+    pub incremental_edits: Option<usize>,
+    /// The maximum length in voxels of the sides of the merged quads, see [`MeshingOptions::max_quad_size`].
+    pub max_quad_size: Option<u32>,
+    /// Skip the downward faces of the bottom voxel layer of the lowest chunks, see [`WorldHeightLimits::lowest_chunk`].
+    /// Nothing is below the world floor, so these faces can only be seen from outside of the world.
+    pub cull_world_floor: bool,
+    /// Pack the vertex positions of the chunk meshes into the voxel data, see [`MeshingOptions::packed_positions`].
+    /// This cuts the vertex memory by seven, but the meshes can only be rendered with the terrain material.
+    pub packed_vertices: bool,
+}
+
+impl Default for ChunkMeshingSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: MeshingAlgorithm::default(),
+            tangents: false,
+            parallel_threshold: Some(2 * CHUNK_LENGTH),
+            border_tint: false,
+            winding: FaceWinding::default(),
+            merge_metadata_mask: u8::MAX,
+            max_retained_buffer_bytes: Some(1024 * 1024),
+            incremental_edits: Some(8),
+            max_quad_size: None,
+            cull_world_floor: false,
+            packed_vertices: false,
+        }
+    }
+}
+
+/// Resource controlling how much meshing work can be dispatched.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkMeshingBudget {
+    /// The maximum number of meshing tasks started each frame.
+    pub meshes_per_frame: usize,
+    /// The maximum number of finished meshes written to the mesh assets each frame.
+    /// This spreads the upload of a backlog of meshes, e.g. after the app was paused, over several frames.
+    pub applied_meshes_per_frame: usize,
+    /// The maximum number of meshing tasks running at the same time.
+    pub max_concurrent_tasks: usize,
+    /// The minimum time between two remeshes of the same chunk.
+    /// Chunks edited again within this delay are remeshed once it elapses, bounding the cost of the chunks edited every frame.
+    pub remesh_cooldown: Duration,
+    /// The fraction of the tasks started each frame given to the chunks waiting the longest, whatever their distance
+    /// to the player. This keeps far chunks from waiting forever while the player moves and near chunks keep coming.
+    pub oldest_first_fraction: f32,
+}
+
+impl Default for ChunkMeshingBudget {
+    fn default() -> Self {
+        // leave a core available for the main and render schedules.
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+
+        Self {
+            meshes_per_frame: 64,
+            applied_meshes_per_frame: 32,
+            max_concurrent_tasks: cores.saturating_sub(1).max(1),
+            remesh_cooldown: Duration::from_millis(100),
+            oldest_first_fraction: 0.1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        render::mesh_buffer_raw,
+        storage::VoxelBuffer,
+        terraingen::TerrainGeneratorPlugin,
+        world::{
+            editing::{MetadataPolicy, VoxelEditSet, VoxelEditingPlugin, VoxelEditor},
+            terrain::VoxelWorldTerrainGenPlugin,
+            ChunkCommandQueue, ModifiedChunks, WorldHeightLimits,
+        },
+        VoxelTaskPoolSettings,
+    };
+    use bevy::{
+        ecs::{schedule::common_conditions::run_once, system::SystemState},
+        render::mesh::VertexAttributeValues,
+        tasks::AsyncComputeTaskPool,
+    };
+    use ilattice::{glam::UVec3, prelude::Extent};
+    use std::time::Duration;
+
+    // an app running the meshing systems over the chunks of its chunk map, without rendering them.
+    fn meshing_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .init_resource::<DirtyChunks>()
+            .init_resource::<ChunkEntities>()
+            // not bound by the cores of the machine running the tests, nor by the remesh cooldown.
+            .insert_resource(ChunkMeshingBudget {
+                max_concurrent_tasks: 64,
+                remesh_cooldown: Duration::ZERO,
+                ..Default::default()
+            })
+            .init_resource::<VoxelScale>()
+            .init_resource::<ChunkMeshingSettings>()
+            .init_resource::<VoxelMaterialRegistry>()
+            .init_resource::<ChunkMeshingBacklog>()
+            .add_event::<ChunkMeshed>()
+            .init_resource::<ChunkMeshingMetrics>()
+            .init_resource::<WorldHeightLimits>()
+            .insert_resource(VoxelTaskPools::new(&VoxelTaskPoolSettings::default()))
+            .insert_resource(CurrentLocalPlayerChunk {
+                chunk_min: IVec3::ZERO,
+                world_pos: IVec3::ZERO,
+                translation: Vec3::ZERO,
+            })
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .add_systems(
+                Update,
+                (
+                    apply_meshing_settings,
+                    mark_dirty_chunks,
+                    apply_deferred,
+                    queue_mesh_tasks,
+                    process_mesh_tasks,
+                    update_meshing_backlog,
+                )
+                    .chain()
+                    .in_set(ChunkMeshingSet),
+            )
+            .add_systems(Last, |mut dirty_chunks: ResMut<DirtyChunks>| {
+                *dirty_chunks = DirtyChunks::default();
+            });
+        app
+    }
+
+    // spawns the entity of a chunk and marks it dirty.
+    fn spawn_dirty_chunk(app: &mut App, key: IVec3, mesh: Handle<Mesh>) -> Entity {
+        let entity = app
+            .world
+            .spawn((Chunk(key), ChunkState::Generated, mesh))
+            .id();
+        app.world
+            .resource_mut::<ChunkEntities>()
+            .attach_entity(key, entity);
+        app.world.resource_mut::<DirtyChunks>().mark_dirty(key);
+        entity
+    }
+
+    // runs the app until all the meshing tasks are done.
+    fn finish_mesh_tasks(app: &mut App) {
+        for _ in 0..1000 {
+            if app
+                .world
+                .query_filtered::<(), With<ChunkMeshingTask>>()
+                .iter(&app.world)
+                .len()
+                == 0
+            {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+            app.update();
+        }
+        panic!("the meshing tasks never finished");
+    }
+
+    #[test]
+    fn chunks_without_voxel_data_are_skipped() {
+        let mut app = meshing_app();
+
+        // the first chunk has no voxels, the mesh asset of the second one is gone like when it is being unloaded.
+        let [missing, unloading, loaded] = [0, 1, 2].map(|x| IVec3::X * x * CHUNK_LENGTH as i32);
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::PointList));
+        for key in [unloading, loaded] {
+            app.world
+                .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+                .insert_empty(key);
+        }
+        let missing = spawn_dirty_chunk(&mut app, missing, Handle::default());
+        spawn_dirty_chunk(&mut app, unloading, Handle::default());
+        spawn_dirty_chunk(&mut app, loaded, mesh.clone());
+
+        app.update();
+        assert!(app.world.get::<ChunkMeshingTask>(missing).is_none());
+
+        // the other chunks are still meshed.
+        finish_mesh_tasks(&mut app);
+        assert_eq!(
+            app.world
+                .resource::<Assets<Mesh>>()
+                .get(&mesh)
+                .unwrap()
+                .primitive_topology(),
+            PrimitiveTopology::TriangleList
+        );
+    }
+
+    #[test]
+    fn mesh_stats_match_the_chunk_mesh() {
+        let mut app = meshing_app();
+
+        // a single voxel, meshed as the six faces of a cube.
+        let mut chunks = app.world.resource_mut::<ChunkMap<Voxel, ChunkShape>>();
+        chunks.insert_empty(IVec3::ZERO);
+        *chunks
+            .buffer_at_mut(IVec3::ZERO)
+            .unwrap()
+            .voxel_at_mut([4, 4, 4].into()) = Voxel::new(1);
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::TriangleList));
+        spawn_dirty_chunk(&mut app, IVec3::ZERO, mesh);
+
+        let mut stats = SystemState::<ChunkMeshStatsQuery>::new(&mut app.world);
+        let pending = stats.get(&app.world).chunk_mesh_stats(IVec3::ZERO);
+        assert_eq!(
+            pending,
+            Some(MeshStats {
+                vertex_count: 0,
+                triangle_count: 0,
+                needs_meshing: true,
+            })
+        );
+        assert_eq!(stats.get(&app.world).chunk_mesh_stats(IVec3::X), None);
+
+        app.update();
+        finish_mesh_tasks(&mut app);
+        assert_eq!(
+            stats.get(&app.world).chunk_mesh_stats(IVec3::ZERO),
+            Some(MeshStats {
+                vertex_count: 6 * 4,
+                triangle_count: 6 * 2,
+                needs_meshing: false,
+            })
+        );
+    }
+
+    // inserts solid voxel data for a row of chunks along the X axis and spawns them, returning their entities.
+    fn spawn_chunk_row(app: &mut App, len: i32) -> Vec<Entity> {
+        (0..len)
+            .map(|x| {
+                let key = IVec3::X * x * CHUNK_LENGTH as i32;
+                app.world
+                    .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+                    .insert(key, VoxelBuffer::new(ChunkShape {}, Voxel::new(1)));
+                let mesh = app
+                    .world
+                    .resource_mut::<Assets<Mesh>>()
+                    .add(Mesh::new(PrimitiveTopology::PointList));
+                spawn_dirty_chunk(app, key, mesh)
+            })
+            .collect()
+    }
+
+    fn chunk_state(app: &App, entity: Entity) -> ChunkState {
+        *app.world.get::<ChunkState>(entity).unwrap()
+    }
+
+    #[test]
+    fn concurrent_meshing_tasks_are_capped() {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingBudget {
+            max_concurrent_tasks: 3,
+            ..Default::default()
+        });
+        let entities = spawn_chunk_row(&mut app, 10);
+
+        for _ in 0..1000 {
+            app.update();
+            let running = app
+                .world
+                .query_filtered::<(), With<ChunkMeshingTask>>()
+                .iter(&app.world)
+                .len();
+            assert!(running <= 3);
+
+            if entities
+                .iter()
+                .all(|entity| chunk_state(&app, *entity) == ChunkState::Meshed)
+            {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("the chunks were never all meshed");
+    }
+
+    // the states a chunk went through, recorded by `record_chunk_states` at several points of each frame.
+    #[derive(Resource, Default)]
+    struct RecordedStates(Vec<ChunkState>);
+
+    fn record_chunk_states(chunks: Query<&ChunkState>, mut recorded: ResMut<RecordedStates>) {
+        for state in chunks.iter() {
+            if recorded.0.last() != Some(state) {
+                recorded.0.push(*state);
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_go_through_the_whole_lifecycle() {
+        let mut app = meshing_app();
+        app.add_plugins((TerrainGeneratorPlugin, VoxelWorldTerrainGenPlugin))
+            .add_asset::<Image>()
+            .init_resource::<ChunkCommandQueue>()
+            .init_resource::<ModifiedChunks>()
+            .init_resource::<WorldHeightLimits>()
+            .init_resource::<RecordedStates>()
+            .configure_set(Update, TerrainGenSet.before(mark_dirty_chunks))
+            .add_systems(First, record_chunk_states)
+            .add_systems(
+                Update,
+                (
+                    record_chunk_states
+                        .after(TerrainGenSet)
+                        .before(mark_dirty_chunks),
+                    record_chunk_states
+                        .after(mark_dirty_chunks)
+                        .before(queue_mesh_tasks),
+                ),
+            )
+            .add_systems(Last, record_chunk_states);
+
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::TriangleList));
+        let entity = app
+            .world
+            .spawn((Chunk(IVec3::ZERO), ChunkState::Spawned, mesh))
+            .id();
+        app.world
+            .resource_mut::<ChunkEntities>()
+            .attach_entity(IVec3::ZERO, entity);
+
+        for _ in 0..1000 {
+            app.update();
+            if chunk_state(&app, entity) == ChunkState::Meshed {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        use ChunkState::*;
+        assert_eq!(
+            app.world.resource::<RecordedStates>().0,
+            [
+                Spawned,
+                Generating,
+                Generated,
+                NeedsMeshing,
+                Meshing,
+                Meshed
+            ]
+        );
+    }
+
+    #[test]
+    fn changing_the_algorithm_remeshes_the_loaded_chunks() {
+        let mut app = meshing_app();
+
+        // a 2x2x2 block of voxels, a quad per side when merged.
+        let mut chunks = app.world.resource_mut::<ChunkMap<Voxel, ChunkShape>>();
+        chunks.insert_empty(IVec3::ZERO);
+        let buffer = chunks.buffer_at_mut(IVec3::ZERO).unwrap();
+        for x in 4..6 {
+            for y in 4..6 {
+                for z in 4..6 {
+                    *buffer.voxel_at_mut([x, y, z].into()) = Voxel::new(1);
+                }
+            }
+        }
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::TriangleList));
+        let entity = spawn_dirty_chunk(&mut app, IVec3::ZERO, mesh);
+
+        app.update();
+        finish_mesh_tasks(&mut app);
+        let mut stats = SystemState::<ChunkMeshStatsQuery>::new(&mut app.world);
+        let vertex_count = |app: &App, stats: &mut SystemState<ChunkMeshStatsQuery>| {
+            stats
+                .get(&app.world)
+                .chunk_mesh_stats(IVec3::ZERO)
+                .unwrap()
+                .vertex_count
+        };
+        assert_eq!(vertex_count(&app, &mut stats), 6 * 4);
+
+        // nothing is remeshed while the settings are left alone.
+        app.update();
+        assert_eq!(chunk_state(&app, entity), ChunkState::Meshed);
+
+        app.world.resource_mut::<ChunkMeshingSettings>().algorithm =
+            MeshingAlgorithm::PerVoxelCubes;
+        app.update();
+        assert_ne!(chunk_state(&app, entity), ChunkState::Meshed);
+        finish_mesh_tasks(&mut app);
+        assert_eq!(vertex_count(&app, &mut stats), 6 * 4 * 4);
+    }
+
+    // the number of meshing tasks started so far.
+    #[derive(Resource, Default)]
+    struct StartedMeshes(usize);
+
+    fn count_started_meshes(
+        started: Query<(), Added<ChunkMeshingTask>>,
+        mut count: ResMut<StartedMeshes>,
+    ) {
+        count.0 += started.iter().count();
+    }
+
+    #[test]
+    fn edits_within_the_cooldown_are_meshed_once() {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingBudget {
+            remesh_cooldown: Duration::from_secs(3600),
+            ..Default::default()
+        })
+        .init_resource::<StartedMeshes>()
+        .add_systems(Last, count_started_meshes);
+        let entity = spawn_chunk_row(&mut app, 1)[0];
+
+        // an edit every frame.
+        for _ in 0..10 {
+            app.world
+                .resource_mut::<DirtyChunks>()
+                .mark_dirty(IVec3::ZERO);
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        finish_mesh_tasks(&mut app);
+        assert_eq!(app.world.resource::<StartedMeshes>().0, 1);
+        assert_eq!(chunk_state(&app, entity), ChunkState::NeedsMeshing);
+
+        // the pending edits are meshed together once the cooldown elapses.
+        app.world
+            .resource_mut::<ChunkMeshingBudget>()
+            .remesh_cooldown = Duration::ZERO;
+        app.update();
+        finish_mesh_tasks(&mut app);
+        assert_eq!(app.world.resource::<StartedMeshes>().0, 2);
+        assert_eq!(chunk_state(&app, entity), ChunkState::Meshed);
+    }
+
+    #[test]
+    fn despawning_a_chunk_cancels_its_meshing() {
+        let mut app = meshing_app();
+        app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert_empty(IVec3::ZERO);
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::TriangleList));
+        let entity = spawn_dirty_chunk(&mut app, IVec3::ZERO, mesh);
+
+        app.update();
+        let cancelled = app
+            .world
+            .get::<ChunkMeshingTask>(entity)
+            .expect("the chunk wasn't queued for meshing")
+            .cancelled
+            .clone();
+        assert!(!cancelled.load(Ordering::Relaxed));
+
+        app.world.despawn(entity);
+        assert!(cancelled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn mesh_backlogs_are_applied_over_several_frames() {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingBudget {
+            meshes_per_frame: 128,
+            applied_meshes_per_frame: 32,
+            max_concurrent_tasks: 128,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+        spawn_chunk_row(&mut app, 100);
+
+        let pending = |app: &mut App| {
+            app.world
+                .query_filtered::<(), With<ChunkMeshingTask>>()
+                .iter(&app.world)
+                .count()
+        };
+
+        // all the tasks are started in the first frame, and left to finish.
+        app.update();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let mut frames = 0;
+        let mut left = pending(&mut app);
+        while left > 0 {
+            assert!(frames < 1000, "the meshes were never all applied");
+            app.update();
+            let now = pending(&mut app);
+            assert!(left - now <= 32);
+            left = now;
+            frames += 1;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(frames >= 3);
+    }
+
+    #[test]
+    fn the_backlog_lists_the_chunks_waiting_for_a_task() {
+        let mut app = meshing_app();
+        // no task can be started until the budget is raised.
+        app.insert_resource(ChunkMeshingBudget {
+            meshes_per_frame: 0,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+        spawn_chunk_row(&mut app, 3);
+
+        app.update();
+        let backlog = app.world.resource::<ChunkMeshingBacklog>();
+        assert_eq!(backlog.len(), 3);
+        assert_eq!(
+            backlog.keys(),
+            [0, 1, 2].map(|x| IVec3::X * x * CHUNK_LENGTH as i32)
+        );
+
+        app.insert_resource(ChunkMeshingBudget {
+            max_concurrent_tasks: 64,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+        app.update();
+        assert!(app.world.resource::<ChunkMeshingBacklog>().is_empty());
+        finish_mesh_tasks(&mut app);
+        assert!(app.world.resource::<ChunkMeshingBacklog>().is_empty());
+    }
+
+    #[derive(Resource, Default)]
+    struct RecordedMeshedEvents(Vec<ChunkMeshed>);
+
+    #[test]
+    fn applying_a_mesh_sends_a_single_event() {
+        let mut app = meshing_app();
+        app.init_resource::<RecordedMeshedEvents>().add_systems(
+            Last,
+            |mut events: EventReader<ChunkMeshed>, mut recorded: ResMut<RecordedMeshedEvents>| {
+                recorded.0.extend(events.iter().copied());
+            },
+        );
+        let key = IVec3::X * CHUNK_LENGTH as i32;
+        app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert(key, VoxelBuffer::new(ChunkShape {}, Voxel::new(1)));
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::PointList));
+        let entity = spawn_dirty_chunk(&mut app, key, mesh);
+
+        app.update();
+        finish_mesh_tasks(&mut app);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(
+            app.world.resource::<RecordedMeshedEvents>().0,
+            [ChunkMeshed(key, entity)]
+        );
+    }
+
+    #[test]
+    fn edited_chunks_are_meshed_before_nearer_ones() {
+        let mut app = meshing_app();
+        let entities = spawn_chunk_row(&mut app, 4);
+        app.update();
+        finish_mesh_tasks(&mut app);
+        app.insert_resource(ChunkMeshingBudget {
+            meshes_per_frame: 1,
+            oldest_first_fraction: 0.0,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+
+        let [near, far] = [0, 3].map(|x| IVec3::X * x * CHUNK_LENGTH as i32);
+        let mut dirty_chunks = app.world.resource_mut::<DirtyChunks>();
+        dirty_chunks.mark_dirty(near);
+        dirty_chunks.mark_edited(far);
+        app.update();
+
+        assert!(app.world.get::<ChunkMeshingTask>(entities[3]).is_some());
+        assert!(app.world.get::<ChunkMeshingTask>(entities[0]).is_none());
+        assert!(app.world.get::<ChunkEditedPriority>(entities[3]).is_none());
+
+        // the nearer chunk is meshed next.
+        finish_mesh_tasks(&mut app);
+        app.update();
+        assert!(app.world.get::<ChunkMeshingTask>(entities[0]).is_some());
+        finish_mesh_tasks(&mut app);
+        assert!(entities
+            .iter()
+            .all(|entity| chunk_state(&app, *entity) == ChunkState::Meshed));
+    }
+
+    #[test]
+    fn metrics_count_the_tasks_of_the_frame() {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingBudget {
+            meshes_per_frame: 3,
+            max_concurrent_tasks: 64,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+        spawn_chunk_row(&mut app, 5);
+
+        let mut spawned = Vec::new();
+        let mut finished = 0;
+        let mut task_time = Duration::ZERO;
+        for _ in 0..1000 {
+            app.update();
+            let metrics = *app.world.resource::<ChunkMeshingMetrics>();
+            spawned.push(metrics.tasks_spawned);
+            finished += metrics.tasks_finished;
+            task_time += metrics.task_time;
+            if finished == 5 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // the budget bounds the first frame, the chunks left the second one.
+        assert_eq!(spawned[..2], [3, 2]);
+        assert!(spawned[2..].iter().all(|spawned| *spawned == 0));
+        assert_eq!(finished, 5);
+        assert!(task_time > Duration::ZERO);
+
+        app.update();
+        let metrics = *app.world.resource::<ChunkMeshingMetrics>();
+        assert_eq!((metrics.tasks_spawned, metrics.tasks_finished), (0, 0));
+    }
+
+    // returns the frame at which a far chunk starts meshing while a new nearer chunk needs meshing every frame.
+    fn frames_until_far_chunk_is_meshed(oldest_first_fraction: f32) -> Option<usize> {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingBudget {
+            meshes_per_frame: 1,
+            max_concurrent_tasks: 64,
+            oldest_first_fraction,
+            remesh_cooldown: Duration::ZERO,
+            ..Default::default()
+        });
+
+        let spawn_solid_chunk = |app: &mut App, key: IVec3| {
+            app.world
+                .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+                .insert(key, VoxelBuffer::new(ChunkShape {}, Voxel::new(1)));
+            let mesh = app
+                .world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Mesh::new(PrimitiveTopology::PointList));
+            spawn_dirty_chunk(app, key, mesh)
+        };
+        let far = spawn_solid_chunk(&mut app, IVec3::X * 20 * CHUNK_LENGTH as i32);
+
+        (1..=10).find(|frame| {
+            spawn_solid_chunk(&mut app, IVec3::Z * *frame as i32 * CHUNK_LENGTH as i32);
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+            chunk_state(&app, far) != ChunkState::NeedsMeshing
+        })
+    }
+
+    #[test]
+    fn the_oldest_chunks_dont_starve() {
+        assert_eq!(frames_until_far_chunk_is_meshed(0.0), None);
+        // half a task of credit per frame, and the far chunk is older than the nearer ones from the second frame.
+        assert_eq!(frames_until_far_chunk_is_meshed(0.5), Some(2));
+    }
+
+    // the number of downward facing vertices of a mesh.
+    fn downward_vertices(app: &App, mesh: &Handle<Mesh>) -> usize {
+        let mesh = app.world.resource::<Assets<Mesh>>().get(mesh).unwrap();
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("the mesh has no normals");
+        };
+        normals.iter().filter(|normal| normal[1] < 0.0).count()
+    }
+
+    #[test]
+    fn only_floor_chunks_skip_their_downward_faces() {
+        let mut app = meshing_app();
+        app.insert_resource(ChunkMeshingSettings {
+            cull_world_floor: true,
+            ..Default::default()
+        });
+        let floor = app.world.resource::<WorldHeightLimits>().lowest_chunk();
+
+        // a slab of four voxel layers at the bottom of a floor chunk and of the chunk above it.
+        let meshes = [floor, floor + CHUNK_LENGTH as i32].map(|y| {
+            let key = IVec3::Y * y;
+            let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+            buffer.fill_extent(
+                Extent::from_min_and_shape(UVec3::ZERO, UVec3::new(CHUNK_LENGTH, 4, CHUNK_LENGTH)),
+                Voxel::new(1),
+            );
+            app.world
+                .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+                .insert(key, buffer);
+            let mesh = app
+                .world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Mesh::new(PrimitiveTopology::TriangleList));
+            spawn_dirty_chunk(&mut app, key, mesh.clone());
+            mesh
+        });
+
+        app.update();
+        finish_mesh_tasks(&mut app);
+        assert_eq!(downward_vertices(&app, &meshes[0]), 0);
+        assert!(downward_vertices(&app, &meshes[1]) > 0);
+    }
+
+    #[test]
+    fn edits_from_several_systems_are_meshed_once() {
+        let mut app = meshing_app();
+        app.add_plugins(VoxelEditingPlugin)
+            .init_resource::<ModifiedChunks>()
+            .init_resource::<StartedMeshes>()
+            .configure_set(Update, ChunkMeshingSet.after(VoxelEditSet))
+            .add_systems(Last, count_started_meshes);
+        let entity = spawn_chunk_row(&mut app, 1)[0];
+        app.update();
+        finish_mesh_tasks(&mut app);
+
+        // two systems editing the same chunk in the same frame, hollowing it out and then changing what's left.
+        app.add_systems(
+            Update,
+            (
+                (|mut editor: VoxelEditor| {
+                    editor.replace_in_region(
+                        IVec3::ONE,
+                        IVec3::splat(30),
+                        Voxel::new(1),
+                        Voxel::default(),
+                        MetadataPolicy::Reset,
+                    );
+                })
+                .run_if(run_once()),
+                (|mut editor: VoxelEditor| {
+                    editor.replace_in_region(
+                        IVec3::ZERO,
+                        IVec3::splat(31),
+                        Voxel::new(1),
+                        Voxel::new(2),
+                        MetadataPolicy::Reset,
+                    );
+                })
+                .run_if(run_once()),
+            )
+                .in_set(VoxelEditSet),
+        );
+        app.world.resource_mut::<StartedMeshes>().0 = 0;
+        app.update();
+        finish_mesh_tasks(&mut app);
+        assert_eq!(app.world.resource::<StartedMeshes>().0, 1);
+
+        // the applied mesh is the one of the voxels after both edits.
+        let settings = app.world.resource::<ChunkMeshingSettings>();
+        let options = chunk_key_meshing_options(
+            &chunk_meshing_options(
+                settings,
+                app.world.resource::<VoxelScale>(),
+                app.world.resource::<VoxelMaterialRegistry>(),
+            ),
+            settings,
+            app.world.resource::<WorldHeightLimits>(),
+            IVec3::ZERO,
+        );
+        let mut expected = RawMesh::default();
+        mesh_buffer_raw(
+            app.world
+                .resource::<ChunkMap<Voxel, ChunkShape>>()
+                .buffer_at(IVec3::ZERO)
+                .unwrap(),
+            &mut MeshBuffers::new(ChunkShape {}),
+            &mut expected,
+            &options,
+        );
+        let handle = app.world.get::<Handle<Mesh>>(entity).unwrap();
+        let mesh = app.world.resource::<Assets<Mesh>>().get(handle).unwrap();
+        assert_eq!(RawMesh::from_mesh(mesh, &options), Some(expected));
+    }
+
+    #[test]
+    fn meshes_are_applied_in_a_deterministic_order() {
+        // the order the tasks finish in must not leak into the order the meshes are applied in.
+        let applied_order = |spawn_order: Vec<IVec3>| {
+            let mut app = meshing_app();
+            let mut handles = Vec::new();
+            for key in spawn_order {
+                let handle = app
+                    .world
+                    .resource_mut::<Assets<Mesh>>()
+                    .add(Mesh::new(PrimitiveTopology::PointList));
+                let task = AsyncComputeTaskPool::get().spawn(async {
+                    (
+                        Some(Mesh::new(PrimitiveTopology::TriangleList)),
+                        Duration::ZERO,
+                    )
+                });
+                app.world.spawn((
+                    Chunk(key),
+                    ChunkState::Meshing,
+                    handle.clone(),
+                    ChunkMeshingTask {
+                        task,
+                        cancelled: Default::default(),
+                    },
+                ));
+                handles.push((handle, key));
+            }
+
+            // all the tasks are applied in the same frame.
+            for _ in 0..1000 {
+                let mut tasks = app.world.query::<&ChunkMeshingTask>();
+                if tasks.iter(&app.world).all(|task| task.task.is_finished()) {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            app.update();
+            assert_eq!(
+                app.world
+                    .query::<&ChunkMeshingTask>()
+                    .iter(&app.world)
+                    .len(),
+                0
+            );
+
+            app.world
+                .resource::<Events<AssetEvent<Mesh>>>()
+                .iter_current_update_events()
+                .filter_map(|event| match event {
+                    AssetEvent::Modified { handle } => handles
+                        .iter()
+                        .find(|(chunk_mesh, _)| chunk_mesh == handle)
+                        .map(|(_, key)| *key),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let keys: Vec<_> = (0..4)
+            .flat_map(|x| (0..4).map(move |z| IVec3::new(x, 0, z) * CHUNK_LENGTH as i32))
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort_unstable_by_key(|key| key.to_array());
+
+        assert_eq!(applied_order(keys.clone()), sorted);
+        assert_eq!(applied_order(keys.into_iter().rev().collect()), sorted);
+    }
+}
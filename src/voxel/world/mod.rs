@@ -1,30 +1,64 @@
 use bevy::{
     math::IVec3,
-    prelude::{Component, Plugin},
+    prelude::{Component, Plugin, Resource},
 };
 use ndshape::ConstShape3u32;
 
 use super::{storage::ChunkMap, terraingen, Voxel};
 
+mod archive;
+pub use archive::WorldArchive;
+
 /// Systems for dynamically loading / unloading regions (aka chunks) of the world according to camera position.
 mod chunks;
 pub use chunks::{
-    ChunkCommandQueue, ChunkEntities, ChunkLoadRadius, CurrentLocalPlayerChunk, DirtyChunks,
+    chunk_offset, offset_chunk_key, ChunkAnchor, ChunkAnchors, ChunkCommandQueue, ChunkEntities,
+    ChunkLoadRadius, ChunkLoadShape, ChunkMemoryBudget, CurrentLocalPlayerChunk, DirtyChunks,
+    ModifiedChunks,
 };
 
+mod chunk_tint;
+pub use chunk_tint::{ChunkTintMode, ChunkTintSettings};
+
 mod chunks_anim;
+pub mod diagnostics;
+pub mod editing;
+pub mod hud;
+pub mod interaction;
 pub mod materials;
 mod meshing;
+pub use meshing::{
+    ChunkMeshStatsQuery, ChunkMeshed, ChunkMeshingBacklog, ChunkMeshingBudget, ChunkMeshingMetrics,
+    ChunkMeshingSettings,
+};
 pub mod player;
+mod readback;
+pub use readback::{ChunkReadback, ChunkReadbackBudget, EncodedChunk};
+mod shutdown;
 mod sky;
-mod terrain;
+pub use sky::{SkyLightSettings, SunShadowSettings};
+pub mod terrain;
+pub use terrain::{ChunkSeeder, TerrainGenBudget, TerrainGenMetrics};
+mod task_pools;
+pub use task_pools::{VoxelTaskPoolSettings, VoxelTaskPools};
+mod worlds;
+pub use worlds::{VoxelWorlds, WorldId};
 
 /// Registers all resources and systems for simulating and rendering an editable and interactive voxel world.
 pub struct VoxelWorldPlugin;
 
 impl Plugin for VoxelWorldPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
+        let task_pool_settings = app
+            .world
+            .get_resource::<VoxelTaskPoolSettings>()
+            .copied()
+            .unwrap_or_default();
+
         app.insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
+            .insert_resource(VoxelTaskPools::new(&task_pool_settings))
+            .init_resource::<VoxelScale>()
+            .init_resource::<WorldHeightLimits>()
             .add_plugins(chunks::VoxelWorldChunkingPlugin)
             .add_plugins(meshing::VoxelWorldMeshingPlugin)
             // ordering of plugin insertion matters here.
@@ -34,9 +68,17 @@ impl Plugin for VoxelWorldPlugin {
             .add_plugins(super::render::ChunkMaterialPlugin)
             .add_plugins(materials::VoxelWorldBaseMaterialsPlugin)
             .add_plugins(chunks_anim::ChunkAppearanceAnimatorPlugin)
+            .add_plugins(chunk_tint::ChunkTintPlugin)
             .add_plugins(bevy_atmosphere::plugin::AtmospherePlugin)
             .add_plugins(player::VoxelWorldPlayerControllerPlugin)
-            .add_plugins(sky::InteractiveSkyboxPlugin);
+            .add_plugins(editing::VoxelEditingPlugin)
+            .add_plugins(interaction::VoxelWorldInteractionPlugin)
+            .add_plugins(hud::VoxelHudPlugin)
+            .add_plugins(sky::InteractiveSkyboxPlugin)
+            .add_plugins(readback::ChunkReadbackPlugin)
+            .add_plugins(shutdown::VoxelWorldShutdownPlugin)
+            .add_plugins(diagnostics::VoxelWorldDiagnosticsPlugin)
+            .add_plugins(worlds::VoxelWorldsPlugin);
     }
 }
 
@@ -47,3 +89,149 @@ pub type ChunkShape = ConstShape3u32<CHUNK_LENGTH, CHUNK_LENGTH, CHUNK_LENGTH>;
 // A component tagging an entity as a chunk.
 #[derive(Component)]
 pub struct Chunk(pub IVec3);
+
+/// The lifecycle state of a chunk entity.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChunkState {
+    /// The chunk entity was just spawned and has no voxel data yet.
+    Spawned,
+    /// A terrain generation task is running for this chunk.
+    Generating,
+    /// The voxel data of the chunk has been generated.
+    Generated,
+    /// The chunk voxel data changed and is waiting for a meshing task.
+    NeedsMeshing,
+    /// A meshing task is running for this chunk.
+    Meshing,
+    /// The chunk mesh is up to date with its voxel data.
+    Meshed,
+}
+
+impl ChunkState {
+    /// Returns whether the chunk can go from this state to the specified one.
+    pub const fn can_transition_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Spawned, Self::Generating)
+                | (Self::Generating, Self::Generated)
+                | (
+                    Self::Generated | Self::NeedsMeshing | Self::Meshing | Self::Meshed,
+                    Self::NeedsMeshing
+                )
+                | (Self::NeedsMeshing, Self::Meshing)
+                | (Self::Meshing, Self::Meshed)
+        )
+    }
+
+    /// Moves the chunk to the specified state.
+    pub fn transition(&mut self, next: Self) {
+        debug_assert!(
+            self.can_transition_to(next),
+            "illegal chunk state transition from {:?} to {:?}",
+            self,
+            next
+        );
+        *self = next;
+    }
+}
+
+/// The size of a single voxel in world units.
+/// Chunk transforms, bounding boxes and meshes are all derived from this value.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct VoxelScale(pub f32);
+
+impl Default for VoxelScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// The vertical extent of the world, in voxel coordinates.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldHeightLimits {
+    /// Voxels below this height are generated as bedrock.
+    pub floor: i32,
+    /// Voxels from this height up are always empty, chunks above it are never loaded.
+    pub ceiling: i32,
+}
+
+impl Default for WorldHeightLimits {
+    fn default() -> Self {
+        Self {
+            floor: 2,
+            ceiling: 288,
+        }
+    }
+}
+
+impl WorldHeightLimits {
+    /// Returns the minimum of the lowest chunk loaded, the one holding the bedrock layer right below the floor.
+    /// The chunks below it only hold bedrock and are never loaded, e.g. the lowest chunk is at -32 for a floor of 0.
+    pub const fn lowest_chunk(&self) -> i32 {
+        (self.floor - 1) & !(CHUNK_LENGTH as i32 - 1)
+    }
+
+    /// Returns whether the chunk with the specified minimum height lies within the limits.
+    pub const fn contains_chunk(&self, chunk_min: i32) -> bool {
+        chunk_min >= self.lowest_chunk() && chunk_min < self.ceiling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_state_transitions() {
+        use ChunkState::*;
+
+        let states = [
+            Spawned,
+            Generating,
+            Generated,
+            NeedsMeshing,
+            Meshing,
+            Meshed,
+        ];
+        let legal = [
+            (Spawned, Generating),
+            (Generating, Generated),
+            (Generated, NeedsMeshing),
+            (NeedsMeshing, NeedsMeshing),
+            (NeedsMeshing, Meshing),
+            (Meshing, NeedsMeshing),
+            (Meshing, Meshed),
+            (Meshed, NeedsMeshing),
+        ];
+        for from in states {
+            for to in states {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    legal.contains(&(from, to)),
+                    "{from:?} to {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "illegal chunk state transition from Spawned to Meshed")]
+    fn illegal_transitions_are_caught() {
+        let mut state = ChunkState::Spawned;
+        state.transition(ChunkState::Meshed);
+    }
+
+    #[test]
+    fn the_lowest_chunk_holds_the_voxels_right_below_the_floor() {
+        for (floor, lowest_chunk) in [(2, 0), (1, 0), (0, -32), (-31, -32), (33, 32)] {
+            let limits = WorldHeightLimits {
+                floor,
+                ceiling: 288,
+            };
+            assert_eq!(limits.lowest_chunk(), lowest_chunk, "floor {floor}");
+            assert!((lowest_chunk..lowest_chunk + CHUNK_LENGTH as i32).contains(&(floor - 1)));
+            assert!(limits.contains_chunk(lowest_chunk));
+            assert!(!limits.contains_chunk(lowest_chunk - CHUNK_LENGTH as i32));
+        }
+    }
+}
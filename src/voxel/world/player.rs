@@ -1,18 +1,60 @@
-use bevy::{input::mouse::MouseMotion, prelude::*, window::CursorGrabMode};
+use bevy::{
+    input::mouse::MouseMotion, prelude::*, render::camera::ScalingMode, window::CursorGrabMode,
+};
 use bevy_egui::EguiContexts;
-use std::f32::consts::FRAC_PI_2;
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    time::Duration,
+};
 
-use crate::debug::DebugUISet;
+use crate::{
+    debug::DebugUISet,
+    voxel::{
+        material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+        render::{ChunkMaterialSet, ChunkMaterialSingleton, GpuTerrainUniforms},
+        storage::ChunkMap,
+        Voxel,
+    },
+};
+
+use super::{
+    terrain::TerrainGenSet, ChunkLoadRadius, ChunkShape, VoxelScale, WorldHeightLimits,
+    CHUNK_LENGTH,
+};
 
 // Reusing the player controller impl for now.
 
 pub const DEFAULT_CAMERA_SENS: f32 = 0.005;
 
+/// The horizontal speed factor of the player when fully submerged in a liquid.
+pub const SWIM_SPEED_FACTOR: f32 = 0.4;
+
+/// How fast the movement blends between walking and swimming when entering or leaving a liquid.
+const SWIM_TRANSITION_RATE: f32 = 6.0;
+
 #[derive(Default, Component)]
 pub struct PlayerController {
     yaw: f32,
     pitch: f32,
     cursor_locked: bool,
+    // 0 when out of liquids, 1 when fully swimming.
+    swim_blend: f32,
+}
+
+impl PlayerController {
+    /// Returns whether the cursor is grabbed for controlling the camera.
+    pub const fn cursor_locked(&self) -> bool {
+        self.cursor_locked
+    }
+
+    /// Returns a controller with the cursor grabbed, as if the player had clicked in the window.
+    #[cfg(test)]
+    pub fn with_cursor_locked() -> Self {
+        Self {
+            cursor_locked: true,
+            ..Default::default()
+        }
+    }
 }
 
 pub fn handle_player_mouse_move(
@@ -53,14 +95,38 @@ pub fn handle_player_mouse_move(
         Quat::from_axis_angle(Vec3::Y, new_yaw) * Quat::from_axis_angle(-Vec3::X, new_pitch);
 }
 
+/// Moves the swim blend of the player towards 1 while `submerged`, or back towards 0, over `delta_seconds`.
+fn blend_swim(blend: f32, submerged: bool, delta_seconds: f32) -> f32 {
+    let target = if submerged { 1.0 } else { 0.0 };
+    blend + (target - blend) * (1.0 - (-SWIM_TRANSITION_RATE * delta_seconds).exp())
+}
+
+/// The horizontal speed factor for a swim blend, from 1 out of liquids to [`SWIM_SPEED_FACTOR`] when swimming.
+fn swim_speed_factor(blend: f32) -> f32 {
+    1.0 + (SWIM_SPEED_FACTOR - 1.0) * blend
+}
+
 pub fn handle_player_input(
     mut egui: EguiContexts,
     mut query: Query<(&mut PlayerController, &mut Transform)>,
     keys: Res<Input<KeyCode>>,
     btns: Res<Input<MouseButton>>,
+    height_limits: Res<WorldHeightLimits>,
+    scale: Res<VoxelScale>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    time: Res<Time>,
 ) {
     let (mut controller, mut transform) = query.single_mut();
 
+    // smoothly switch to swim mode while the player is inside a liquid.
+    let submerged = chunks
+        .voxel_at((transform.translation / scale.0).floor().as_ivec3())
+        .filter(|voxel| !voxel.is_empty())
+        .and_then(|voxel| materials.get_by_id(voxel.id))
+        .is_some_and(|mat| mat.flags.contains(VoxelMaterialFlags::LIQUID));
+    controller.swim_blend = blend_swim(controller.swim_blend, submerged, time.delta_seconds());
+
     // cursor grabbing
     // @todo: this should prevent cursor grabbing when the user is interacting with a debug UI. Why doesn't this work?
     if btns.just_pressed(MouseButton::Left) && !egui.ctx_mut().wants_pointer_input() {
@@ -110,10 +176,360 @@ pub fn handle_player_input(
         return;
     }
 
+    // swimming slows down horizontal movement only, so the player can still freely move up and down.
+    let horizontal_acceleration = acceleration * swim_speed_factor(controller.swim_blend);
+
     // hardcoding 0.10 as a factor for now to not go zoomin across the world.
-    transform.translation += direction.x * right * acceleration
-        + direction.z * forward * acceleration
+    transform.translation += direction.x * right * horizontal_acceleration
+        + direction.z * forward * horizontal_acceleration
         + direction.y * Vec3::Y * acceleration;
+
+    // keep the player within the vertical extent of the world.
+    transform.translation.y = transform.translation.y.clamp(
+        height_limits.floor as f32 * scale.0,
+        height_limits.ceiling as f32 * scale.0,
+    );
+}
+
+/// The kind of projection used by the player camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraProjectionMode {
+    #[default]
+    Perspective,
+    /// A parallel projection, mostly useful for screenshots and debugging.
+    Orthographic,
+}
+
+/// Settings for the player camera projection.
+/// The far clip plane is derived from the chunk loading radius.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraProjectionSettings {
+    pub mode: CameraProjectionMode,
+    /// The vertical field of view, in radians.
+    pub fov: f32,
+    /// The height of the area covered by the orthographic projection, in world units.
+    pub orthographic_height: f32,
+    /// Extra distance added on top of the loaded region extent.
+    pub margin: f32,
+    /// Upper bound of the perspective far plane, keeping depth precision reasonable.
+    /// The orthographic depth is linear so its far plane isn't bounded.
+    pub max_far: f32,
+    /// How long the far plane and fog take to follow a change of the chunk loading radius, see [`ViewDistance`].
+    pub transition: Duration,
+}
+
+impl Default for CameraProjectionSettings {
+    fn default() -> Self {
+        Self {
+            mode: CameraProjectionMode::default(),
+            fov: PI / 2.,
+            orthographic_height: 8.0 * CHUNK_LENGTH as f32,
+            margin: CHUNK_LENGTH as f32,
+            max_far: 4096.0,
+            transition: Duration::from_millis(500),
+        }
+    }
+}
+
+impl CameraProjectionSettings {
+    /// Returns the far plane distance covering the region loaded with the specified radius.
+    pub fn far_plane(&self, radius: &ChunkLoadRadius, scale: f32) -> f32 {
+        let extent = Vec2::new(radius.horizontal as f32, radius.vertical as f32).length()
+            * CHUNK_LENGTH as f32
+            * scale;
+
+        match self.mode {
+            CameraProjectionMode::Perspective => (extent + self.margin).min(self.max_far),
+            CameraProjectionMode::Orthographic => extent + self.margin,
+        }
+    }
+
+    /// Returns the distance at which the terrain is fully fogged, hiding the edge of the region loaded with the
+    /// specified radius.
+    pub fn fog_distance(&self, radius: &ChunkLoadRadius, scale: f32) -> f32 {
+        radius.horizontal as f32 * CHUNK_LENGTH as f32 * scale
+    }
+
+    /// Returns the camera projection described by the settings, for the region loaded with the specified radius.
+    pub fn projection(&self, radius: &ChunkLoadRadius, scale: f32) -> Projection {
+        let far = self.far_plane(radius, scale);
+
+        match self.mode {
+            CameraProjectionMode::Perspective => Projection::Perspective(PerspectiveProjection {
+                fov: self.fov,
+                far,
+                ..Default::default()
+            }),
+            CameraProjectionMode::Orthographic => {
+                Projection::Orthographic(OrthographicProjection {
+                    far,
+                    scaling_mode: ScalingMode::FixedVertical(self.orthographic_height * scale),
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    /// Switches between the perspective and orthographic projections.
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraProjectionMode::Perspective => CameraProjectionMode::Orthographic,
+            CameraProjectionMode::Orthographic => CameraProjectionMode::Perspective,
+        };
+    }
+}
+
+/// Resource holding the far plane and fog distances in use, easing towards the ones derived from the chunk loading
+/// radius so that render distance changes don't make the horizon jump.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct ViewDistance {
+    /// The distance of the camera far plane.
+    pub far: f32,
+    /// The distance at which the terrain is fully fogged.
+    pub fog: f32,
+    // the distances at the start of the transition and the ones it ends at, none before the first update.
+    from: Vec2,
+    target: Option<Vec2>,
+    elapsed: Duration,
+}
+
+impl ViewDistance {
+    /// Returns whether the distances reached their target.
+    pub fn is_settled(&self) -> bool {
+        self.target == Some(Vec2::new(self.far, self.fog))
+    }
+
+    /// Starts easing from the current distances towards new target far plane and fog distances.
+    /// The first target is applied right away.
+    pub fn set_target(&mut self, far: f32, fog: f32) {
+        let target = Vec2::new(far, fog);
+        if self.target == Some(target) {
+            return;
+        }
+
+        self.from = match self.target {
+            Some(_) => Vec2::new(self.far, self.fog),
+            None => target,
+        };
+        self.target = Some(target);
+        self.elapsed = Duration::ZERO;
+    }
+
+    /// Advances the transition towards the target distances, which takes `duration` in total.
+    pub fn advance(&mut self, delta: Duration, duration: Duration) {
+        let Some(target) = self.target else {
+            return;
+        };
+
+        self.elapsed = (self.elapsed + delta).min(duration);
+        let t = match duration.is_zero() {
+            true => 1.0,
+            false => self.elapsed.as_secs_f32() / duration.as_secs_f32(),
+        };
+
+        let distances = match t >= 1.0 {
+            true => target,
+            // smoothstep, so the distances start and stop changing gently.
+            false => self.from.lerp(target, t * t * (3.0 - 2.0 * t)),
+        };
+        self.far = distances.x;
+        self.fog = distances.y;
+    }
+}
+
+/// Eases the [`ViewDistance`] towards the distances derived from the settings and chunk loading radius.
+pub fn update_view_distance(
+    time: Res<Time>,
+    radius: Res<ChunkLoadRadius>,
+    scale: Res<VoxelScale>,
+    settings: Res<CameraProjectionSettings>,
+    mut view_distance: ResMut<ViewDistance>,
+) {
+    if radius.is_changed() || scale.is_changed() || settings.is_changed() {
+        view_distance.set_target(
+            settings.far_plane(&radius, scale.0),
+            settings.fog_distance(&radius, scale.0),
+        );
+    }
+
+    if !view_distance.is_settled() {
+        view_distance.advance(time.delta(), settings.transition);
+    }
+}
+
+/// Keeps the player camera projection in sync with the settings, and its far plane with the [`ViewDistance`].
+/// The aspect ratio is kept up to date on window resizes by bevy's camera system, which also derives the culling
+/// frustum from whichever projection is in use.
+pub fn update_camera_projection(
+    radius: Res<ChunkLoadRadius>,
+    scale: Res<VoxelScale>,
+    settings: Res<CameraProjectionSettings>,
+    view_distance: Res<ViewDistance>,
+    mut cameras: Query<&mut Projection, With<PlayerController>>,
+) {
+    if !view_distance.is_changed() && !scale.is_changed() && !settings.is_changed() {
+        return;
+    }
+
+    let new_projection = settings.projection(&radius, scale.0);
+
+    for mut projection in &mut cameras {
+        match (projection.as_mut(), &new_projection) {
+            // keep the aspect ratio and area already computed for the window.
+            (Projection::Perspective(perspective), Projection::Perspective(new)) => {
+                perspective.fov = new.fov;
+            }
+            (Projection::Orthographic(orthographic), Projection::Orthographic(new)) => {
+                orthographic.scaling_mode = new.scaling_mode.clone();
+            }
+            _ => *projection = new_projection.clone(),
+        }
+
+        match projection.as_mut() {
+            Projection::Perspective(perspective) => perspective.far = view_distance.far,
+            Projection::Orthographic(orthographic) => orthographic.far = view_distance.far,
+        }
+    }
+}
+
+/// Applies the fog distance of the [`ViewDistance`] to the terrain material.
+fn update_terrain_fog(
+    view_distance: Res<ViewDistance>,
+    chunk_material: Res<ChunkMaterialSingleton>,
+    mut materials: ResMut<Assets<GpuTerrainUniforms>>,
+) {
+    if !view_distance.is_changed() && !chunk_material.is_changed() {
+        return;
+    }
+
+    if let Some(uniforms) = materials.get_mut(&chunk_material) {
+        uniforms.fog_distance = view_distance.fog;
+    }
+}
+
+/// Resource describing where the player starts and where it looks at, applied at startup.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlayerSpawnSettings {
+    /// The initial position of the player in world coordinates.
+    pub position: Vec3,
+    /// The initial rotation of the player around the vertical axis, in radians.
+    pub yaw: f32,
+    /// The initial tilt of the player view, in radians, positive values looking down.
+    pub pitch: f32,
+    /// Place the player on the ground of the column below the configured position, ignoring its height.
+    pub on_ground: bool,
+}
+
+impl Default for PlayerSpawnSettings {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 160.0, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: true,
+        }
+    }
+}
+
+/// Moves and orients the players as configured by the [`PlayerSpawnSettings`], before any chunk gets loaded.
+fn apply_player_spawn_settings(
+    mut commands: Commands,
+    mut players: Query<(Entity, &mut PlayerController, &mut Transform)>,
+    settings: Res<PlayerSpawnSettings>,
+    scale: Res<VoxelScale>,
+) {
+    for (entity, mut controller, mut transform) in &mut players {
+        controller.yaw = settings.yaw;
+        controller.pitch = settings.pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+
+        transform.translation = settings.position;
+        transform.rotation = Quat::from_axis_angle(Vec3::Y, controller.yaw)
+            * Quat::from_axis_angle(-Vec3::X, controller.pitch);
+
+        if settings.on_ground {
+            commands.entity(entity).insert(PlayerSpawn {
+                column: IVec2::new(
+                    (settings.position.x / scale.0).floor() as i32,
+                    (settings.position.z / scale.0).floor() as i32,
+                ),
+            });
+        }
+    }
+}
+
+/// Requests the player to be placed on the ground surface of a voxel column.
+/// The component is removed once the spawn height has been resolved.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct PlayerSpawn {
+    /// The XZ voxel coordinates of the column to spawn on.
+    pub column: IVec2,
+}
+
+/// Places the players waiting to spawn just above the topmost solid voxel of their spawn column.
+/// The column is only scanned once all of its chunks in the loading radius are generated.
+pub fn resolve_player_spawn(
+    mut commands: Commands,
+    mut players: Query<(Entity, &PlayerSpawn, &mut Transform), With<PlayerController>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    radius: Res<ChunkLoadRadius>,
+    scale: Res<VoxelScale>,
+    height_limits: Res<WorldHeightLimits>,
+    materials: Res<VoxelMaterialRegistry>,
+) {
+    for (entity, spawn, mut transform) in &mut players {
+        // move over the center of the column first so the chunks loading around the player include it.
+        let column_pos = (spawn.column.as_vec2() + 0.5) * scale.0;
+        if transform.translation.x != column_pos.x || transform.translation.z != column_pos.y {
+            transform.translation.x = column_pos.x;
+            transform.translation.z = column_pos.y;
+        }
+
+        let player_chunk_y =
+            (transform.translation.y / scale.0) as i32 & !(CHUNK_LENGTH as i32 - 1);
+
+        // the chunks of the column loaded around the player, from the top down.
+        let mut column_chunks: Vec<_> = (-radius.vertical..radius.vertical)
+            .map(|y| {
+                player_chunk_y
+                    .saturating_add(y * CHUNK_LENGTH as i32)
+                    .max(height_limits.lowest_chunk())
+            })
+            .filter(|y| *y < height_limits.ceiling)
+            .collect();
+        column_chunks.sort_unstable_by(|a, b| b.cmp(a));
+        column_chunks.dedup();
+
+        let column_key = |y: i32| IVec3::new(spawn.column.x, y, spawn.column.y);
+
+        if !column_chunks
+            .iter()
+            .all(|y| chunks.exists(column_key(*y) & !(CHUNK_LENGTH as i32 - 1)))
+        {
+            continue;
+        }
+
+        let is_solid = |voxel: Voxel| {
+            !voxel.is_empty()
+                && materials
+                    .get_by_id(voxel.id)
+                    .is_none_or(|mat| !mat.flags.contains(VoxelMaterialFlags::LIQUID))
+        };
+
+        let ground = column_chunks
+            .iter()
+            .flat_map(|chunk_y| (*chunk_y..=*chunk_y + (CHUNK_LENGTH as i32 - 1)).rev())
+            .find(|y| chunks.voxel_at(column_key(*y)).is_some_and(is_solid));
+
+        match ground {
+            Some(y) => transform.translation.y = (y + 1) as f32 * scale.0,
+            None => warn!(
+                "No solid ground found in spawn column {}, keeping the current height.",
+                spawn.column
+            ),
+        }
+
+        commands.entity(entity).remove::<PlayerSpawn>();
+    }
 }
 
 #[derive(Hash, Copy, Clone, PartialEq, Eq, Debug, SystemSet)]
@@ -124,11 +540,373 @@ pub struct VoxelWorldPlayerControllerPlugin;
 
 impl Plugin for VoxelWorldPlayerControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.init_resource::<CameraProjectionSettings>()
+            .init_resource::<ViewDistance>()
+            .init_resource::<PlayerSpawnSettings>()
+            // the players are spawned during startup.
+            .add_systems(PostStartup, apply_player_spawn_settings)
+            .add_systems(
+                Update,
+                (handle_player_input, handle_player_mouse_move)
+                    .chain()
+                    .in_set(PlayerControllerSet)
+                    .after(DebugUISet::Display),
+            )
+            .add_systems(
+                Update,
+                (
+                    update_view_distance,
+                    (
+                        update_camera_projection,
+                        update_terrain_fog.after(ChunkMaterialSet),
+                    ),
+                )
+                    .chain(),
+            )
+            .add_systems(Update, resolve_player_spawn.after(TerrainGenSet));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::world::ChunkLoadShape;
+    use crate::voxel::{
+        material::VoxelMaterial,
+        materials::{Rock, VoxelWorldBaseMaterialsPlugin, Water},
+    };
+
+    #[test]
+    fn far_plane_covers_the_loaded_region() {
+        let settings = CameraProjectionSettings::default();
+        let radius = ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
+            horizontal: 16,
+            vertical: 4,
+            unload_horizontal: 16,
+            unload_vertical: 4,
+        };
+
+        for scale in [0.5, 1.0, 2.0] {
+            let corner = Vec2::new(16.0, 4.0) * (CHUNK_LENGTH as f32 * scale);
+            assert!(settings.far_plane(&radius, scale) >= corner.length());
+        }
+    }
+
+    #[test]
+    fn far_plane_respects_max_far() {
+        let settings = CameraProjectionSettings {
+            margin: 32.0,
+            max_far: 1000.0,
+            ..Default::default()
+        };
+        let radius = ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
+            horizontal: 64,
+            vertical: 8,
+            unload_horizontal: 64,
+            unload_vertical: 8,
+        };
+
+        assert_eq!(settings.far_plane(&radius, 1.0), 1000.0);
+        assert_eq!(
+            settings.far_plane(
+                &ChunkLoadRadius {
+                    shape: ChunkLoadShape::default(),
+                    horizontal: 1,
+                    vertical: 0,
+                    unload_horizontal: 1,
+                    unload_vertical: 0,
+                },
+                1.0
+            ),
+            CHUNK_LENGTH as f32 + 32.0
+        );
+
+        // the orthographic depth is linear, so it isn't bounded.
+        let orthographic = CameraProjectionSettings {
+            mode: CameraProjectionMode::Orthographic,
+            ..settings
+        };
+        assert!(orthographic.far_plane(&radius, 1.0) > 1000.0);
+    }
+
+    // an app resolving the spawn of a player 40 voxels up, over the chunks of the column at the origin.
+    fn spawn_app(column: impl Fn(i32) -> Voxel) -> App {
+        let mut app = App::new();
+        app.init_resource::<VoxelMaterialRegistry>()
+            .add_plugins(VoxelWorldBaseMaterialsPlugin)
+            .init_resource::<WorldHeightLimits>()
+            .insert_resource(VoxelScale(2.0))
+            .insert_resource(ChunkLoadRadius {
+                shape: ChunkLoadShape::default(),
+                horizontal: 1,
+                vertical: 1,
+                unload_horizontal: 1,
+                unload_vertical: 1,
+            })
+            .add_systems(Update, resolve_player_spawn);
+
+        let mut chunks = ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {});
+        for y in [0, 32] {
+            chunks.insert_empty(IVec3::Y * y);
+        }
+        for y in 0..64 {
+            *chunks.voxel_at_mut(IVec3::new(2, y, 3)).unwrap() = column(y);
+        }
+        app.insert_resource(chunks);
+
+        app.world.spawn((
+            PlayerController::default(),
+            PlayerSpawn {
+                column: IVec2::new(2, 3),
+            },
+            Transform::from_xyz(0.0, 80.0, 0.0),
+        ));
+        app.update();
+        app
+    }
+
+    fn spawned_player(app: &mut App) -> Vec3 {
+        let (transform, spawn) = app
+            .world
+            .query::<(&Transform, Option<&PlayerSpawn>)>()
+            .single(&app.world);
+        assert!(spawn.is_none());
+        transform.translation
+    }
+
+    #[test]
+    fn players_spawn_above_the_ground() {
+        let mut app = spawn_app(|y| {
+            if y <= 10 {
+                Rock::into_voxel()
+            } else {
+                Voxel::EMPTY_VOXEL
+            }
+        });
+        assert_eq!(spawned_player(&mut app), Vec3::new(5.0, 22.0, 7.0));
+    }
+
+    #[test]
+    fn players_dont_spawn_on_liquids() {
+        let mut app = spawn_app(|y| match y {
+            0..=5 => Rock::into_voxel(),
+            6..=10 => Water::into_voxel(),
+            _ => Voxel::EMPTY_VOXEL,
+        });
+        assert_eq!(spawned_player(&mut app), Vec3::new(5.0, 12.0, 7.0));
+    }
+
+    #[test]
+    fn players_keep_their_height_without_ground() {
+        let mut app = spawn_app(|_| Voxel::EMPTY_VOXEL);
+        assert_eq!(spawned_player(&mut app), Vec3::new(5.0, 80.0, 7.0));
+    }
+
+    #[test]
+    fn camera_projection_follows_the_settings() {
+        let mut app = App::new();
+        // the far plane follows the settings right away.
+        app.insert_resource(CameraProjectionSettings {
+            transition: Duration::ZERO,
+            ..Default::default()
+        })
+        .init_resource::<Time>()
+        .init_resource::<VoxelScale>()
+        .init_resource::<ViewDistance>()
+        .insert_resource(ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
+            horizontal: 4,
+            vertical: 2,
+            unload_horizontal: 4,
+            unload_vertical: 2,
+        })
+        .add_systems(
             Update,
-            (handle_player_input, handle_player_mouse_move)
-                .chain()
-                .after(DebugUISet::Display),
+            (update_view_distance, update_camera_projection).chain(),
+        );
+        let camera = app
+            .world
+            .spawn((PlayerController::default(), Projection::default()))
+            .id();
+
+        app.world.resource_mut::<CameraProjectionSettings>().fov = 1.2;
+        app.update();
+
+        let settings = *app.world.resource::<CameraProjectionSettings>();
+        let Some(Projection::Perspective(perspective)) = app.world.get::<Projection>(camera) else {
+            panic!("the camera projection isn't a perspective one");
+        };
+        assert_eq!(perspective.fov, 1.2);
+        assert_eq!(
+            perspective.far,
+            settings.far_plane(app.world.resource::<ChunkLoadRadius>(), 1.0)
+        );
+
+        app.world
+            .resource_mut::<CameraProjectionSettings>()
+            .toggle_mode();
+        app.update();
+
+        let settings = *app.world.resource::<CameraProjectionSettings>();
+        let Some(Projection::Orthographic(orthographic)) = app.world.get::<Projection>(camera)
+        else {
+            panic!("the camera projection wasn't switched to an orthographic one");
+        };
+        assert!(matches!(
+            orthographic.scaling_mode,
+            ScalingMode::FixedVertical(height) if height == settings.orthographic_height
+        ));
+        assert_eq!(
+            orthographic.far,
+            settings.far_plane(app.world.resource::<ChunkLoadRadius>(), 1.0)
+        );
+
+        app.world
+            .resource_mut::<CameraProjectionSettings>()
+            .toggle_mode();
+        app.update();
+        assert!(matches!(
+            app.world.get::<Projection>(camera),
+            Some(Projection::Perspective(perspective)) if perspective.fov == 1.2
+        ));
+    }
+
+    #[test]
+    fn swim_speed_blends_in_and_out_of_liquids() {
+        const FRAME: f32 = 1.0 / 60.0;
+
+        let mut blend = 0.0;
+        let mut previous = swim_speed_factor(blend);
+        for _ in 0..120 {
+            blend = blend_swim(blend, true, FRAME);
+            let factor = swim_speed_factor(blend);
+            assert!(factor <= previous && factor >= SWIM_SPEED_FACTOR);
+            previous = factor;
+        }
+        assert!((swim_speed_factor(blend) - SWIM_SPEED_FACTOR).abs() < 1e-3);
+
+        for _ in 0..120 {
+            blend = blend_swim(blend, false, FRAME);
+            let factor = swim_speed_factor(blend);
+            assert!(factor >= previous && factor <= 1.0);
+            previous = factor;
+        }
+        assert!((swim_speed_factor(blend) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn swim_blend_is_frame_rate_independent() {
+        let mut fine = 0.0;
+        for _ in 0..4 {
+            fine = blend_swim(fine, true, 0.025);
+        }
+        let coarse = blend_swim(0.0, true, 0.1);
+        assert!((fine - coarse).abs() < 1e-5);
+    }
+
+    fn spawn_with_settings(settings: PlayerSpawnSettings) -> App {
+        let mut app = App::new();
+        app.insert_resource(settings)
+            .insert_resource(VoxelScale(2.0))
+            .add_systems(PostStartup, apply_player_spawn_settings);
+        app.world
+            .spawn((PlayerController::default(), Transform::default()));
+        app.update();
+        app
+    }
+
+    #[test]
+    fn players_start_as_configured() {
+        let mut app = spawn_with_settings(PlayerSpawnSettings {
+            position: Vec3::new(5.5, 100.0, -3.0),
+            yaw: 1.0,
+            pitch: 3.0,
+            on_ground: true,
+        });
+
+        let (controller, transform, spawn) = app
+            .world
+            .query::<(&PlayerController, &Transform, &PlayerSpawn)>()
+            .single(&app.world);
+        assert_eq!(transform.translation, Vec3::new(5.5, 100.0, -3.0));
+        assert_eq!((controller.yaw, controller.pitch), (1.0, FRAC_PI_2));
+        assert!(transform.rotation.abs_diff_eq(
+            Quat::from_axis_angle(Vec3::Y, 1.0) * Quat::from_axis_angle(-Vec3::X, FRAC_PI_2),
+            1e-6
+        ));
+        // the column below the player, in voxels.
+        assert_eq!(spawn.column, IVec2::new(2, -2));
+    }
+
+    #[test]
+    fn players_stay_in_the_air_unless_spawned_on_the_ground() {
+        let mut app = spawn_with_settings(PlayerSpawnSettings {
+            on_ground: false,
+            ..Default::default()
+        });
+
+        let (transform, spawn) = app
+            .world
+            .query::<(&Transform, Option<&PlayerSpawn>)>()
+            .single(&app.world);
+        assert_eq!(
+            transform.translation,
+            PlayerSpawnSettings::default().position
         );
+        assert!(spawn.is_none());
+    }
+
+    #[test]
+    fn fog_covers_the_horizontal_edge_of_the_loaded_region() {
+        let settings = CameraProjectionSettings::default();
+        let radius = ChunkLoadRadius {
+            shape: ChunkLoadShape::default(),
+            horizontal: 12,
+            vertical: 4,
+            unload_horizontal: 12,
+            unload_vertical: 4,
+        };
+
+        assert_eq!(
+            settings.fog_distance(&radius, 1.0),
+            12.0 * CHUNK_LENGTH as f32
+        );
+        assert_eq!(
+            settings.fog_distance(&radius, 0.5),
+            6.0 * CHUNK_LENGTH as f32
+        );
+    }
+
+    #[test]
+    fn view_distances_ease_towards_their_target() {
+        let duration = Duration::from_millis(500);
+        let mut view = ViewDistance::default();
+        view.advance(Duration::from_millis(100), duration);
+        assert_eq!((view.far, view.fog), (0.0, 0.0));
+
+        // the first target is applied right away.
+        view.set_target(100.0, 50.0);
+        view.advance(Duration::ZERO, duration);
+        assert_eq!((view.far, view.fog), (100.0, 50.0));
+        assert!(view.is_settled());
+
+        view.set_target(200.0, 150.0);
+        view.advance(Duration::from_millis(250), duration);
+        assert_eq!((view.far, view.fog), (150.0, 100.0));
+        assert!(!view.is_settled());
+        view.advance(Duration::from_millis(125), duration);
+        assert!(view.far > 150.0 && view.far < 200.0);
+
+        // past the duration the distances stay at the target.
+        view.advance(Duration::from_secs(1), duration);
+        assert_eq!((view.far, view.fog), (200.0, 150.0));
+        assert!(view.is_settled());
+
+        // a zero duration jumps to the target.
+        view.set_target(10.0, 5.0);
+        view.advance(Duration::ZERO, Duration::ZERO);
+        assert_eq!((view.far, view.fog), (10.0, 5.0));
     }
 }
@@ -0,0 +1,293 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+use bevy::{
+    math::IVec3,
+    prelude::{Plugin, Res, ResMut, Resource, Update},
+    tasks::AsyncComputeTaskPool,
+};
+
+use super::{ChunkShape, Voxel, CHUNK_LENGTH};
+use crate::voxel::storage::ChunkMap;
+
+/// The voxels of a chunk, encoded with [`crate::voxel::storage::VoxelBuffer::encode_rle`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodedChunk {
+    pub key: IVec3,
+    pub data: Vec<u8>,
+}
+
+/// A request for the chunks of a region, whose keys are served in order.
+struct ReadbackRequest {
+    min: IVec3,
+    max: IVec3,
+    // the next key to serve, `None` once the whole region is served.
+    next: Option<IVec3>,
+    sender: Sender<EncodedChunk>,
+}
+
+impl ReadbackRequest {
+    /// Returns the next key of the region, x first, then y, then z.
+    fn next_key(&mut self) -> Option<IVec3> {
+        let key = self.next?;
+        let step =
+            |coord: i32, max: i32| coord.checked_add(CHUNK_LENGTH as i32).filter(|c| *c <= max);
+
+        self.next = if let Some(x) = step(key.x, self.max.x) {
+            Some(IVec3::new(x, key.y, key.z))
+        } else if let Some(y) = step(key.y, self.max.y) {
+            Some(IVec3::new(self.min.x, y, key.z))
+        } else {
+            step(key.z, self.max.z).map(|z| IVec3::new(self.min.x, self.min.y, z))
+        };
+
+        Some(key)
+    }
+}
+
+/// Resource handing out snapshots of the voxels of the loaded chunks, e.g. to external tools.
+///
+/// It can be cloned and used from any thread: requests are served over the following frames without blocking the
+/// simulation, the chunks being encoded on the async compute task pool.
+#[derive(Resource, Clone)]
+pub struct ChunkReadback {
+    requests: Sender<ReadbackRequest>,
+}
+
+impl ChunkReadback {
+    /// Requests the voxels of the chunks whose keys lie within `min..=max`.
+    ///
+    /// The returned channel receives the encoded chunks as they're ready, chunks which aren't loaded when their turn
+    /// comes being skipped. It disconnects once the whole region is served.
+    pub fn request_region(&self, min: IVec3, max: IVec3) -> Receiver<EncodedChunk> {
+        let min = min & !(CHUNK_LENGTH as i32 - 1);
+        let next = min.cmple(max).all().then_some(min);
+
+        let (sender, receiver) = mpsc::channel();
+        // the world is gone if this fails, which disconnects the channel as well.
+        let _ = self.requests.send(ReadbackRequest {
+            min,
+            max,
+            next,
+            sender,
+        });
+        receiver
+    }
+}
+
+/// The readback requests being served.
+#[derive(Resource)]
+struct PendingReadbacks {
+    incoming: Mutex<Receiver<ReadbackRequest>>,
+    requests: VecDeque<ReadbackRequest>,
+}
+
+/// Resource bounding the work done for readback requests each frame.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkReadbackBudget {
+    /// The number of loaded chunks copied each frame.
+    pub chunks_per_frame: usize,
+    /// The number of chunk keys looked up each frame, loaded or not, so that large regions of mostly unloaded chunks
+    /// don't stall the frame.
+    pub keys_per_frame: usize,
+}
+
+impl Default for ChunkReadbackBudget {
+    fn default() -> Self {
+        Self {
+            chunks_per_frame: 64,
+            keys_per_frame: 4096,
+        }
+    }
+}
+
+/// Copies the requested chunks and encodes them in the background, the oldest requests first.
+fn serve_chunk_readbacks(
+    mut pending: ResMut<PendingReadbacks>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    budget: Res<ChunkReadbackBudget>,
+) {
+    let pending = &mut *pending;
+    pending
+        .requests
+        .extend(pending.incoming.get_mut().unwrap().try_iter());
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let mut remaining = budget.chunks_per_frame;
+    let mut remaining_keys = budget.keys_per_frame;
+
+    while remaining > 0 && remaining_keys > 0 {
+        let Some(request) = pending.requests.front_mut() else {
+            break;
+        };
+        let Some(key) = request.next_key() else {
+            // dropping the request disconnects the channel once the last encoding task is done.
+            pending.requests.pop_front();
+            continue;
+        };
+
+        // unloaded chunks are skipped, only counting against the keys budget.
+        remaining_keys -= 1;
+        let Some(buffer) = chunks.buffer_at(key) else {
+            continue;
+        };
+
+        remaining -= 1;
+        let buffer = buffer.clone();
+        let sender = request.sender.clone();
+        task_pool
+            .spawn(async move {
+                let _ = sender.send(EncodedChunk {
+                    key,
+                    data: buffer.encode_rle(),
+                });
+            })
+            .detach();
+    }
+}
+
+/// Serves the [`ChunkReadback`] requests.
+pub struct ChunkReadbackPlugin;
+
+impl Plugin for ChunkReadbackPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let (sender, receiver) = mpsc::channel();
+
+        app.insert_resource(ChunkReadback { requests: sender })
+            .insert_resource(PendingReadbacks {
+                incoming: Mutex::new(receiver),
+                requests: VecDeque::new(),
+            })
+            .init_resource::<ChunkReadbackBudget>()
+            .add_systems(Update, serve_chunk_readbacks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::storage::VoxelBuffer;
+    use bevy::prelude::{App, MinimalPlugins};
+    use std::{sync::mpsc::RecvTimeoutError, time::Duration};
+
+    fn readback_app(budget: ChunkReadbackBudget) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, ChunkReadbackPlugin))
+            .insert_resource(budget)
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}));
+        app
+    }
+
+    fn insert_marked_chunk(app: &mut App, key: IVec3, marker: u8) -> Vec<u8> {
+        let buffer = VoxelBuffer::new(ChunkShape {}, Voxel::new(marker));
+        let encoded = buffer.encode_rle();
+        app.world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert(key, buffer);
+        encoded
+    }
+
+    // updates the app until the request is fully served, returning the chunks received.
+    fn serve(app: &mut App, receiver: &Receiver<EncodedChunk>) -> Vec<EncodedChunk> {
+        let mut received = Vec::new();
+        loop {
+            app.update();
+            match receiver.recv_timeout(Duration::from_millis(10)) {
+                Ok(chunk) => received.push(chunk),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return received,
+            }
+        }
+    }
+
+    #[test]
+    fn loaded_chunks_of_the_region_are_served() {
+        let mut app = readback_app(ChunkReadbackBudget::default());
+        let first = insert_marked_chunk(&mut app, IVec3::ZERO, 1);
+        let second = insert_marked_chunk(&mut app, IVec3::new(0, 32, 0), 2);
+        insert_marked_chunk(&mut app, IVec3::new(64, 0, 0), 3);
+
+        let receiver = app
+            .world
+            .resource::<ChunkReadback>()
+            .request_region(IVec3::new(5, 5, 5), IVec3::new(40, 40, 0));
+        let mut received = serve(&mut app, &receiver);
+        received.sort_by_key(|chunk| chunk.key.to_array());
+
+        assert_eq!(
+            received,
+            [
+                EncodedChunk {
+                    key: IVec3::ZERO,
+                    data: first,
+                },
+                EncodedChunk {
+                    key: IVec3::new(0, 32, 0),
+                    data: second,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unloaded_keys_count_against_the_frame_budget() {
+        let mut app = readback_app(ChunkReadbackBudget {
+            chunks_per_frame: 64,
+            keys_per_frame: 3,
+        });
+
+        // 8 keys, none of them loaded.
+        let receiver = app
+            .world
+            .resource::<ChunkReadback>()
+            .request_region(IVec3::ZERO, IVec3::splat(32));
+        app.update();
+        app.update();
+        assert!(receiver
+            .try_recv()
+            .is_err_and(|err| err == mpsc::TryRecvError::Empty));
+
+        app.update();
+        assert!(receiver
+            .try_recv()
+            .is_err_and(|err| err == mpsc::TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn huge_regions_are_served_lazily() {
+        let mut app = readback_app(ChunkReadbackBudget::default());
+
+        let receiver = app
+            .world
+            .resource::<ChunkReadback>()
+            .request_region(IVec3::splat(i32::MIN), IVec3::splat(i32::MAX));
+        app.update();
+        assert!(receiver
+            .try_recv()
+            .is_err_and(|err| err == mpsc::TryRecvError::Empty));
+    }
+
+    #[test]
+    fn regions_can_end_on_the_last_chunk_of_the_coordinate_range() {
+        let mut app = readback_app(ChunkReadbackBudget::default());
+        let last_key = IVec3::new(i32::MAX & !(CHUNK_LENGTH as i32 - 1), 0, 0);
+        let encoded = insert_marked_chunk(&mut app, last_key, 1);
+
+        let receiver = app
+            .world
+            .resource::<ChunkReadback>()
+            .request_region(IVec3::new(i32::MAX - 40, 0, 0), IVec3::new(i32::MAX, 0, 0));
+        assert_eq!(
+            serve(&mut app, &receiver),
+            [EncodedChunk {
+                key: last_key,
+                data: encoded,
+            }]
+        );
+    }
+}
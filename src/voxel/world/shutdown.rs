@@ -0,0 +1,103 @@
+use bevy::{
+    app::AppExit,
+    prelude::{
+        Commands, Entity, EventReader, IntoSystemConfigs, Last, Or, Plugin, Query, ResMut,
+        SystemSet, With,
+    },
+};
+
+use super::{chunks::ChunkCommandQueue, meshing::ChunkMeshingTask, terrain::TerrainGenTask, Chunk};
+
+/// Cancels the in-flight generation and meshing tasks and drops any pending chunk commands once the app is exiting.
+fn drain_chunk_tasks(
+    mut exit_events: EventReader<AppExit>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    busy_chunks: Query<
+        Entity,
+        (
+            With<Chunk>,
+            Or<(With<TerrainGenTask>, With<ChunkMeshingTask>)>,
+        ),
+    >,
+    mut commands: Commands,
+) {
+    if exit_events.iter().last().is_none() {
+        return;
+    }
+
+    // dropping a task cancels it.
+    busy_chunks.for_each(|entity| {
+        commands
+            .entity(entity)
+            .remove::<(TerrainGenTask, ChunkMeshingTask)>();
+    });
+
+    chunk_command_queue.clear();
+}
+
+/// Systems winding down the voxel world when the app exits.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemSet)]
+pub struct WorldShutdownSet;
+
+/// Handles cleanly shutting down the voxel world.
+pub struct VoxelWorldShutdownPlugin;
+
+impl Plugin for VoxelWorldShutdownPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_systems(Last, drain_chunk_tasks.in_set(WorldShutdownSet));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        storage::ChunkMap,
+        terraingen::TerrainGeneratorPlugin,
+        world::{
+            chunks::{ChunkEntities, DirtyChunks, ModifiedChunks},
+            terrain::VoxelWorldTerrainGenPlugin,
+            ChunkShape, ChunkState, VoxelTaskPoolSettings, VoxelTaskPools, WorldHeightLimits,
+        },
+        Voxel,
+    };
+    use bevy::prelude::{AddAsset, App, AssetPlugin, Events, IVec3, Image, MinimalPlugins};
+
+    #[test]
+    fn exiting_mid_load_leaves_no_pending_tasks() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            TerrainGeneratorPlugin,
+            VoxelWorldTerrainGenPlugin,
+            VoxelWorldShutdownPlugin,
+        ))
+        .add_asset::<Image>()
+        .add_event::<AppExit>()
+        .init_resource::<ChunkEntities>()
+        .init_resource::<ChunkCommandQueue>()
+        .init_resource::<DirtyChunks>()
+        .init_resource::<ModifiedChunks>()
+        .init_resource::<WorldHeightLimits>()
+        .insert_resource(VoxelTaskPools::new(&VoxelTaskPoolSettings::default()))
+        .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}));
+
+        for x in 0..16 {
+            app.world
+                .spawn((Chunk(IVec3::new(x * 32, 0, 0)), ChunkState::Spawned));
+        }
+
+        // the generation tasks are queued in the same frame the app starts exiting.
+        app.world.resource_mut::<Events<AppExit>>().send(AppExit);
+        app.update();
+
+        assert_eq!(
+            app.world
+                .query_filtered::<(), Or<(With<TerrainGenTask>, With<ChunkMeshingTask>)>>()
+                .iter(&app.world)
+                .count(),
+            0
+        );
+    }
+}
@@ -1,21 +1,100 @@
-use bevy::prelude::{
-    Color, Commands, Deref, DirectionalLight, DirectionalLightBundle, Entity, ParamSet, Plugin,
-    Query, Res, Resource, Startup, Transform, Update, Vec3, With,
+use bevy::{
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder},
+    prelude::{
+        resource_changed, AmbientLight, ClearColor, Color, Commands, Deref, DetectChanges,
+        DirectionalLight, DirectionalLightBundle, Entity, IntoSystemConfigs, Plugin, Query, Res,
+        ResMut, Resource, Startup, Transform, Update, Vec3,
+    },
 };
 
-use super::player::PlayerController;
+use super::{
+    chunks::ChunkLoadingSet, ChunkLoadRadius, CurrentLocalPlayerChunk, VoxelScale, CHUNK_LENGTH,
+};
 
 #[derive(Resource, Deref)]
 struct SkyLightEntity(Entity);
 
-fn setup_sky_lighting(mut cmds: Commands) {
+/// Settings for the static ambient and sun lighting and the background of the world.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SkyLightSettings {
+    /// The color the cameras clear to, visible wherever the skybox isn't drawn.
+    pub clear_color: Color,
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    /// The direction the sun light is shining towards.
+    pub sun_direction: Vec3,
+    pub sun_color: Color,
+    pub sun_illuminance: f32,
+}
+
+impl Default for SkyLightSettings {
+    fn default() -> Self {
+        Self {
+            clear_color: ClearColor::default().0,
+            ambient_color: Color::WHITE,
+            ambient_brightness: 1.0,
+            sun_direction: Vec3::new(-1.0, -0.6, -1.0),
+            sun_color: Color::WHITE,
+            sun_illuminance: DirectionalLight::default().illuminance,
+        }
+    }
+}
+
+/// Settings for the shadows cast by the sun light on the terrain.
+/// Shadows are costly over large view distances, the cascades trade their sharpness near the player for performance.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SunShadowSettings {
+    pub enabled: bool,
+    /// The number of shadow cascades, more cascades give sharper shadows at a higher rendering cost.
+    pub num_cascades: usize,
+    /// The distance covered by the first and most detailed cascade.
+    pub first_cascade_far_bound: f32,
+    /// The distance up to which shadows are drawn, the horizontal extent of the loaded chunks when unset.
+    pub maximum_distance: Option<f32>,
+}
+
+impl Default for SunShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            num_cascades: 4,
+            first_cascade_far_bound: CHUNK_LENGTH as f32,
+            maximum_distance: None,
+        }
+    }
+}
+
+impl SunShadowSettings {
+    /// Returns the shadow cascades covering the region loaded with the specified radius.
+    pub fn cascade_config(&self, radius: &ChunkLoadRadius, scale: f32) -> CascadeShadowConfig {
+        let builder = CascadeShadowConfigBuilder::default();
+        let first_cascade_far_bound = self
+            .first_cascade_far_bound
+            .max(builder.minimum_distance * 2.0);
+        let maximum_distance = self
+            .maximum_distance
+            .unwrap_or(radius.horizontal as f32 * CHUNK_LENGTH as f32 * scale)
+            .max(first_cascade_far_bound);
+
+        CascadeShadowConfigBuilder {
+            num_cascades: self.num_cascades.max(1),
+            first_cascade_far_bound,
+            maximum_distance,
+            ..builder
+        }
+        .build()
+    }
+}
+
+fn setup_sky_lighting(mut cmds: Commands, settings: Res<SkyLightSettings>) {
     const _SIZE: f32 = 200.0; //make this dynamic according to view distance???
 
     let sky_light_entity = cmds
         .spawn(DirectionalLightBundle {
-            transform: Transform::IDENTITY.looking_to(Vec3::new(-1.0, -0.6, -1.0), Vec3::Y),
+            transform: Transform::IDENTITY.looking_to(settings.sun_direction, Vec3::Y),
             directional_light: DirectionalLight {
-                color: Color::WHITE,
+                color: settings.sun_color,
+                illuminance: settings.sun_illuminance,
                 shadows_enabled: true,
                 // shadow_projection: OrthographicProjection {
                 //     // left: -SIZE,
@@ -35,31 +114,187 @@ fn setup_sky_lighting(mut cmds: Commands) {
     cmds.insert_resource(SkyLightEntity(sky_light_entity));
 }
 
-fn update_light_position(
+/// Applies the sky light settings to the clear color, ambient and sun lights whenever they change.
+fn apply_sky_light_settings(
+    settings: Res<SkyLightSettings>,
+    sky_light_entity: Res<SkyLightEntity>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut lights: Query<(&mut DirectionalLight, &mut Transform)>,
+) {
+    clear_color.0 = settings.clear_color;
+    ambient_light.color = settings.ambient_color;
+    ambient_light.brightness = settings.ambient_brightness;
+
+    if let Ok((mut light, mut transform)) = lights.get_mut(**sky_light_entity) {
+        light.color = settings.sun_color;
+        light.illuminance = settings.sun_illuminance;
+        transform.look_to(settings.sun_direction, Vec3::Y);
+    }
+}
+
+/// Keeps the sun shadows in sync with their settings and the chunk loading radius.
+fn apply_sun_shadow_settings(
+    settings: Res<SunShadowSettings>,
+    radius: Res<ChunkLoadRadius>,
+    scale: Res<VoxelScale>,
     sky_light_entity: Res<SkyLightEntity>,
-    mut queries: ParamSet<(
-        Query<&mut Transform>,
-        Query<&Transform, With<PlayerController>>,
-    )>,
+    mut lights: Query<(&mut DirectionalLight, &mut CascadeShadowConfig)>,
 ) {
-    let sky_light_entity = **sky_light_entity;
-    let player_translation = queries
-        .p1()
-        .get_single()
-        .map_or_else(|_| Default::default(), |ply| ply.translation);
-
-    {
-        let mut binding = queries.p0();
-        let mut sky_light_transform = binding.get_mut(sky_light_entity).unwrap();
-        sky_light_transform.translation = player_translation;
+    if !settings.is_changed() && !radius.is_changed() && !scale.is_changed() {
+        return;
+    }
+
+    if let Ok((mut light, mut cascades)) = lights.get_mut(**sky_light_entity) {
+        light.shadows_enabled = settings.enabled;
+        *cascades = settings.cascade_config(&radius, scale.0);
     }
 }
 
+fn update_light_position(
+    sky_light_entity: Res<SkyLightEntity>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let mut sky_light_transform = transforms.get_mut(**sky_light_entity).unwrap();
+    sky_light_transform.translation = player_pos.translation;
+}
+
 pub struct InteractiveSkyboxPlugin;
 
 impl Plugin for InteractiveSkyboxPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Startup, setup_sky_lighting)
-            .add_systems(Update, update_light_position);
+        app.init_resource::<SkyLightSettings>()
+            .init_resource::<SunShadowSettings>()
+            .add_systems(Startup, setup_sky_lighting)
+            .add_systems(
+                Update,
+                (
+                    update_light_position.after(ChunkLoadingSet),
+                    apply_sky_light_settings.run_if(resource_changed::<SkyLightSettings>()),
+                    apply_sun_shadow_settings,
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::world::ChunkLoadShape;
+    use bevy::prelude::{App, IVec3, MinimalPlugins};
+
+    // an app running the sky systems for a player standing at the specified translation.
+    fn sky_app(translation: Vec3) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, InteractiveSkyboxPlugin))
+            .init_resource::<AmbientLight>()
+            .init_resource::<ClearColor>()
+            .insert_resource(ChunkLoadRadius {
+                shape: ChunkLoadShape::default(),
+                horizontal: 8,
+                vertical: 2,
+                unload_horizontal: 8,
+                unload_vertical: 2,
+            })
+            .init_resource::<VoxelScale>()
+            .insert_resource(CurrentLocalPlayerChunk {
+                chunk_min: IVec3::ZERO,
+                world_pos: IVec3::ZERO,
+                translation,
+            });
+        app
+    }
+
+    #[test]
+    fn lights_and_clear_color_follow_the_settings() {
+        let mut app = sky_app(Vec3::ZERO);
+        app.insert_resource(ClearColor(Color::RED));
+        app.update();
+
+        let sun = **app.world.resource::<SkyLightEntity>();
+        assert_eq!(app.world.resource::<AmbientLight>().brightness, 1.0);
+        assert_eq!(
+            app.world.resource::<ClearColor>().0,
+            ClearColor::default().0
+        );
+
+        *app.world.resource_mut::<SkyLightSettings>() = SkyLightSettings {
+            ambient_color: Color::BLUE,
+            ambient_brightness: 0.25,
+            sun_direction: Vec3::NEG_Y,
+            sun_color: Color::ORANGE,
+            sun_illuminance: 1000.0,
+            clear_color: Color::BLACK,
+        };
+        app.update();
+
+        let ambient_light = app.world.resource::<AmbientLight>();
+        assert_eq!(ambient_light.color, Color::BLUE);
+        assert_eq!(ambient_light.brightness, 0.25);
+
+        assert_eq!(app.world.resource::<ClearColor>().0, Color::BLACK);
+
+        let light = app.world.get::<DirectionalLight>(sun).unwrap();
+        assert_eq!(light.color, Color::ORANGE);
+        assert_eq!(light.illuminance, 1000.0);
+        let transform = app.world.get::<Transform>(sun).unwrap();
+        assert!(transform.forward().abs_diff_eq(Vec3::NEG_Y, 1e-5));
+    }
+
+    #[test]
+    fn sun_follows_the_player() {
+        let mut app = sky_app(Vec3::new(-3.5, 40.0, 12.0));
+        app.update();
+
+        let sun = **app.world.resource::<SkyLightEntity>();
+        assert_eq!(
+            app.world.get::<Transform>(sun).unwrap().translation,
+            Vec3::new(-3.5, 40.0, 12.0)
+        );
+    }
+
+    #[test]
+    fn shadow_cascades_follow_the_settings() {
+        let mut app = sky_app(Vec3::ZERO);
+        app.insert_resource(VoxelScale(0.5));
+        app.update();
+
+        let sun = **app.world.resource::<SkyLightEntity>();
+        let cascades = |app: &App| {
+            app.world
+                .get::<CascadeShadowConfig>(sun)
+                .unwrap()
+                .bounds
+                .clone()
+        };
+        assert!(
+            app.world
+                .get::<DirectionalLight>(sun)
+                .unwrap()
+                .shadows_enabled
+        );
+        // the shadows reach the horizontal extent of the loaded chunks.
+        let bounds = cascades(&app);
+        assert_eq!(bounds.len(), 4);
+        assert!((bounds.last().unwrap() - 8.0 * CHUNK_LENGTH as f32 * 0.5).abs() < 1e-3);
+
+        *app.world.resource_mut::<SunShadowSettings>() = SunShadowSettings {
+            enabled: false,
+            num_cascades: 0,
+            first_cascade_far_bound: 10.0,
+            maximum_distance: Some(50.0),
+        };
+        app.update();
+        assert!(
+            !app.world
+                .get::<DirectionalLight>(sun)
+                .unwrap()
+                .shadows_enabled
+        );
+        // at least one cascade is kept.
+        let bounds = cascades(&app);
+        assert_eq!(bounds.len(), 1);
+        assert!((bounds[0] - 50.0).abs() < 1e-3);
     }
 }
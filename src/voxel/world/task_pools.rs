@@ -0,0 +1,105 @@
+use std::{ops::Deref, sync::Arc};
+
+use bevy::{
+    prelude::Resource,
+    tasks::{AsyncComputeTaskPool, TaskPool, TaskPoolBuilder},
+};
+
+/// Resource selecting the threads running the terrain generation and meshing tasks.
+/// It must be inserted before the [`super::VoxelWorldPlugin`], which reads it when building.
+/// The size of the shared async compute pool is set through the `TaskPoolPlugin` of bevy's `DefaultPlugins`.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct VoxelTaskPoolSettings {
+    /// The number of threads of a pool dedicated to meshing, or `None` to share bevy's async compute pool.
+    pub meshing_threads: Option<usize>,
+    /// The number of threads of a pool dedicated to terrain generation, or `None` to share bevy's async compute pool.
+    pub generation_threads: Option<usize>,
+}
+
+/// One of the [`VoxelTaskPools`], which can be moved into the tasks running on it to spawn more work there.
+/// It dereferences to bevy's async compute pool when the pool isn't dedicated.
+#[derive(Clone)]
+pub struct VoxelTaskPool(Option<Arc<TaskPool>>);
+
+impl Deref for VoxelTaskPool {
+    type Target = TaskPool;
+
+    fn deref(&self) -> &TaskPool {
+        self.0
+            .as_deref()
+            .unwrap_or_else(|| AsyncComputeTaskPool::get())
+    }
+}
+
+/// The task pools the terrain generation and meshing tasks are spawned on.
+#[derive(Resource)]
+pub struct VoxelTaskPools {
+    meshing: VoxelTaskPool,
+    generation: VoxelTaskPool,
+}
+
+impl VoxelTaskPools {
+    pub fn new(settings: &VoxelTaskPoolSettings) -> Self {
+        let dedicated_pool = |threads: Option<usize>, name: &str| {
+            VoxelTaskPool(threads.map(|threads| {
+                Arc::new(
+                    TaskPoolBuilder::new()
+                        .num_threads(threads.max(1))
+                        .thread_name(name.to_string())
+                        .build(),
+                )
+            }))
+        };
+
+        Self {
+            meshing: dedicated_pool(settings.meshing_threads, "Voxel Meshing"),
+            generation: dedicated_pool(settings.generation_threads, "Voxel Terrain Generation"),
+        }
+    }
+
+    /// Returns the pool running the meshing tasks.
+    pub fn meshing(&self) -> &VoxelTaskPool {
+        &self.meshing
+    }
+
+    /// Returns the pool running the terrain generation tasks.
+    pub fn generation(&self) -> &VoxelTaskPool {
+        &self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future;
+
+    #[test]
+    fn pools_are_shared_unless_dedicated() {
+        AsyncComputeTaskPool::init(TaskPool::new);
+
+        let shared = VoxelTaskPools::new(&VoxelTaskPoolSettings::default());
+        assert!(std::ptr::eq(
+            &**shared.meshing(),
+            &**AsyncComputeTaskPool::get()
+        ));
+        assert!(std::ptr::eq(
+            &**shared.generation(),
+            &**AsyncComputeTaskPool::get()
+        ));
+
+        let dedicated = VoxelTaskPools::new(&VoxelTaskPoolSettings {
+            meshing_threads: Some(2),
+            generation_threads: Some(0),
+        });
+        assert_eq!(dedicated.meshing().thread_num(), 2);
+        // a dedicated pool always has a thread.
+        assert_eq!(dedicated.generation().thread_num(), 1);
+
+        let thread_name = future::block_on(
+            dedicated
+                .meshing()
+                .spawn(async { std::thread::current().name().map(String::from) }),
+        );
+        assert!(thread_name.is_some_and(|name| name.starts_with("Voxel Meshing")));
+    }
+}
@@ -1,6 +1,6 @@
 use super::{
-    chunks::{ChunkLoadingSet, DirtyChunks},
-    Chunk, ChunkShape,
+    chunks::{ChunkEntities, ChunkLoadingSet, DirtyChunks},
+    Chunk, ChunkShape, ChunkState, VoxelTaskPools, WorldHeightLimits, CHUNK_LENGTH,
 };
 use crate::voxel::{
     storage::{ChunkMap, VoxelBuffer},
@@ -8,32 +8,73 @@ use crate::voxel::{
     Voxel,
 };
 use bevy::{
+    math::IVec3,
     prelude::{
-        Added, Commands, Component, Entity, IntoSystemConfigs, IntoSystemSetConfig, Plugin, Query,
-        ResMut, SystemSet, Update,
+        Commands, Component, Entity, IntoSystemConfigs, IntoSystemSetConfig, Plugin, Query, Res,
+        ResMut, Resource, SystemSet, Update, Without, World,
     },
-    tasks::{AsyncComputeTaskPool, Task},
+    tasks::Task,
+    utils::{Duration, Instant},
 };
 use futures_lite::future;
+use std::sync::Arc;
 
-/// Queues the terrain gen async tasks for the newly created chunks.
-fn queue_terrain_gen(mut commands: Commands, new_chunks: Query<(Entity, &Chunk), Added<Chunk>>) {
-    let task_pool = AsyncComputeTaskPool::get();
+/// Returns the voxel data of a chunk, from the seeder if it provides the chunk or else from the terrain generator.
+fn generate_chunk(
+    chunk_key: IVec3,
+    seeder: &ChunkSeeder,
+    height_limits: &WorldHeightLimits,
+) -> VoxelBuffer<Voxel, ChunkShape> {
+    seeder.seed(chunk_key).unwrap_or_else(|| {
+        let mut chunk_data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        TERRAIN_GENERATOR
+            .read()
+            .unwrap()
+            .generate(chunk_key, &mut chunk_data, height_limits);
+        chunk_data
+    })
+}
+
+/// Queues the terrain gen async tasks for the spawned chunks, deferring the rest once the frame budget is spent.
+fn queue_terrain_gen(
+    mut commands: Commands,
+    mut new_chunks: Query<(Entity, &Chunk, &mut ChunkState), Without<TerrainGenTask>>,
+    chunk_data: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    height_limits: Res<WorldHeightLimits>,
+    budget: Res<TerrainGenBudget>,
+    seeder: Res<ChunkSeeder>,
+    task_pools: Res<VoxelTaskPools>,
+) {
+    let task_pool = task_pools.generation();
+    let height_limits = *height_limits;
+    let start = Instant::now();
 
     new_chunks
-        .iter()
-        .filter(|(_, key)| key.0.y < 288)
-        .map(|(entity, key)| (entity, key.0))
+        .iter_mut()
+        .filter(|(_, _, state)| **state == ChunkState::Spawned)
+        .filter(|(_, key, _)| height_limits.contains_chunk(key.0.y))
+        .take_while(|_| start.elapsed() < budget.frame_time)
+        .filter_map(|(entity, key, mut state)| {
+            state.transition(ChunkState::Generating);
+
+            // the voxel data may already be there if the chunk was force loaded.
+            if chunk_data.exists(key.0) {
+                state.transition(ChunkState::Generated);
+                dirty_chunks.mark_dirty(key.0);
+                return None;
+            }
+
+            Some((entity, key.0))
+        })
         .map(|(entity, key)| {
+            let seeder = seeder.clone();
             (
                 entity,
                 (TerrainGenTask(task_pool.spawn(async move {
-                    let mut chunk_data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
-                    TERRAIN_GENERATOR
-                        .read()
-                        .unwrap()
-                        .generate(key, &mut chunk_data);
-                    chunk_data
+                    let start = Instant::now();
+                    let data = generate_chunk(key, &seeder, &height_limits);
+                    (data, start.elapsed())
                 }))),
             )
         })
@@ -42,20 +83,125 @@ fn queue_terrain_gen(mut commands: Commands, new_chunks: Query<(Entity, &Chunk),
         });
 }
 
-/// Polls for finished gen tasks and put back the generated terrain into the voxel map
+/// Polls for finished gen tasks and put back the generated terrain into the voxel map.
+/// Tasks left over once the frame budget is spent are polled again on the next frame.
 pub fn process_terrain_gen(
     mut chunk_data: ResMut<ChunkMap<Voxel, ChunkShape>>,
     mut commands: Commands,
     mut dirty_chunks: ResMut<DirtyChunks>,
-    mut gen_chunks: Query<(Entity, &Chunk, &mut TerrainGenTask)>,
+    mut gen_chunks: Query<(Entity, &Chunk, &mut ChunkState, &mut TerrainGenTask)>,
+    budget: Res<TerrainGenBudget>,
+    mut metrics: ResMut<TerrainGenMetrics>,
 ) {
-    gen_chunks.for_each_mut(|(entity, chunk, mut gen_task)| {
-        if let Some(data) = future::block_on(future::poll_once(&mut gen_task.0)) {
+    let start = Instant::now();
+    metrics.tasks_finished = 0;
+    metrics.task_time = Duration::ZERO;
+
+    for (entity, chunk, mut state, mut gen_task) in &mut gen_chunks {
+        if start.elapsed() >= budget.frame_time {
+            break;
+        }
+
+        if let Some((data, time)) = future::block_on(future::poll_once(&mut gen_task.0)) {
             chunk_data.insert(chunk.0, data);
+            state.transition(ChunkState::Generated);
             dirty_chunks.mark_dirty(chunk.0);
             commands.entity(entity).remove::<TerrainGenTask>();
+            metrics.tasks_finished += 1;
+            metrics.task_time += time;
         }
-    });
+    }
+
+    metrics.tasks_pending = gen_chunks.iter().len() - metrics.tasks_finished;
+}
+
+/// Resource measuring the terrain generation work of the last frame.
+/// See [`super::diagnostics::ChunkPipelineLogSettings`].
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct TerrainGenMetrics {
+    /// The number of generation tasks whose voxel data was collected.
+    pub tasks_finished: usize,
+    /// The wall-clock time the collected tasks took to generate their chunk, summed.
+    pub task_time: Duration,
+    /// The number of generation tasks left running or waiting for the next frames.
+    pub tasks_pending: usize,
+}
+
+/// Generates the chunk holding `chunk_key` right away if its voxel data isn't loaded yet, and returns it.
+///
+/// This blocks the calling thread for the whole terrain generation of the chunk, stalling the frame when called from
+/// the app. It is meant for tools and tests needing voxel data before the chunk is streamed in, not for gameplay.
+/// A force loaded chunk stays in the [`ChunkMap`] until it is streamed in and unloaded again.
+pub fn force_load_chunk(world: &mut World, chunk_key: IVec3) -> &VoxelBuffer<Voxel, ChunkShape> {
+    let chunk_key = chunk_key & !(CHUNK_LENGTH as i32 - 1);
+
+    if !world
+        .resource::<ChunkMap<Voxel, ChunkShape>>()
+        .exists(chunk_key)
+    {
+        let height_limits = *world.resource::<WorldHeightLimits>();
+        let chunk_data = generate_chunk(chunk_key, world.resource::<ChunkSeeder>(), &height_limits);
+
+        world
+            .resource_mut::<ChunkMap<Voxel, ChunkShape>>()
+            .insert(chunk_key, chunk_data);
+        world.resource_mut::<DirtyChunks>().mark_dirty(chunk_key);
+
+        // a pending generation task for this chunk would only overwrite the data.
+        let chunk_entity = world
+            .resource::<ChunkEntities>()
+            .entity(chunk_key)
+            .filter(|entity| world.get::<TerrainGenTask>(*entity).is_some());
+
+        if let Some(entity) = chunk_entity {
+            world.entity_mut(entity).remove::<TerrainGenTask>();
+            if let Some(mut state) = world.get_mut::<ChunkState>(entity) {
+                state.transition(ChunkState::Generated);
+            }
+        }
+    }
+
+    world
+        .resource::<ChunkMap<Voxel, ChunkShape>>()
+        .buffer_at(chunk_key)
+        .unwrap()
+}
+
+/// Resource bounding the main thread time spent on terrain generation each frame.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TerrainGenBudget {
+    /// The time each of the terrain generation systems may spend per frame before deferring its remaining work.
+    pub frame_time: Duration,
+}
+
+impl Default for TerrainGenBudget {
+    fn default() -> Self {
+        Self {
+            frame_time: Duration::from_millis(4),
+        }
+    }
+}
+
+type ChunkSeedFn = dyn Fn(IVec3) -> Option<VoxelBuffer<Voxel, ChunkShape>> + Send + Sync;
+
+/// Resource holding a callback providing hand-authored voxel data for chunks, consulted before the terrain generator.
+/// Chunks for which the callback returns `None` are generated as usual.
+#[derive(Resource, Clone, Default)]
+pub struct ChunkSeeder(Option<Arc<ChunkSeedFn>>);
+
+impl ChunkSeeder {
+    /// Creates a seeder from a callback receiving the key of the chunk being generated.
+    /// The callback runs on the terrain generation task pool.
+    pub fn new(
+        seed: impl Fn(IVec3) -> Option<VoxelBuffer<Voxel, ChunkShape>> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Some(Arc::new(seed)))
+    }
+
+    /// Returns the voxel data provided for the chunk, if any.
+    pub fn seed(&self, chunk_key: IVec3) -> Option<VoxelBuffer<Voxel, ChunkShape>> {
+        self.0.as_ref().and_then(|seed| seed(chunk_key))
+    }
 }
 
 /// Handles terrain generation.
@@ -67,7 +213,10 @@ pub struct TerrainGenSet;
 
 impl Plugin for VoxelWorldTerrainGenPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.configure_set(Update, TerrainGenSet.after(ChunkLoadingSet))
+        app.init_resource::<TerrainGenBudget>()
+            .init_resource::<TerrainGenMetrics>()
+            .init_resource::<ChunkSeeder>()
+            .configure_set(Update, TerrainGenSet.after(ChunkLoadingSet))
             .add_systems(
                 Update,
                 (queue_terrain_gen, process_terrain_gen)
@@ -78,4 +227,235 @@ impl Plugin for VoxelWorldTerrainGenPlugin {
 }
 
 #[derive(Component)]
-pub struct TerrainGenTask(Task<VoxelBuffer<Voxel, ChunkShape>>);
+pub struct TerrainGenTask(Task<(VoxelBuffer<Voxel, ChunkShape>, Duration)>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{
+        terraingen::TerrainGeneratorPlugin,
+        world::{
+            ChunkCommandQueue, ChunkEntities, ModifiedChunks, VoxelTaskPoolSettings,
+            WorldHeightLimits,
+        },
+    };
+    use bevy::prelude::{AddAsset, App, AssetPlugin, Image, MinimalPlugins, With};
+
+    // an app generating the terrain of the spawned chunks.
+    fn terrain_gen_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            TerrainGeneratorPlugin,
+            VoxelWorldTerrainGenPlugin,
+        ))
+        .add_asset::<Image>()
+        .init_resource::<ChunkEntities>()
+        .init_resource::<ChunkCommandQueue>()
+        .init_resource::<TerrainGenBudget>()
+        .init_resource::<WorldHeightLimits>()
+        .init_resource::<DirtyChunks>()
+        .init_resource::<ModifiedChunks>()
+        .insert_resource(VoxelTaskPools::new(&VoxelTaskPoolSettings::default()))
+        .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}));
+        app
+    }
+
+    // spawns the entity of a chunk waiting for its terrain.
+    fn spawn_chunk(app: &mut App, key: IVec3) -> Entity {
+        let entity = app.world.spawn((Chunk(key), ChunkState::Spawned)).id();
+        app.world
+            .resource_mut::<ChunkEntities>()
+            .attach_entity(key, entity);
+        entity
+    }
+
+    #[test]
+    fn generation_is_spread_over_frames_past_the_budget() {
+        let mut app = terrain_gen_app();
+        app.insert_resource(TerrainGenBudget {
+            frame_time: Duration::from_micros(1),
+        });
+
+        for x in 0..256 {
+            app.world
+                .spawn((Chunk(IVec3::new(x * 32, 32, 0)), ChunkState::Spawned));
+        }
+
+        let mut frames = 0;
+        let mut finished = 0;
+        while app
+            .world
+            .query::<&ChunkState>()
+            .iter(&app.world)
+            .any(|state| *state != ChunkState::Generated)
+        {
+            assert!(frames < 10_000, "the chunks were never all generated");
+            app.update();
+            frames += 1;
+            let metrics = app.world.resource::<TerrainGenMetrics>();
+            finished += metrics.tasks_finished;
+            assert!(metrics.tasks_pending <= 256 - finished);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert!(frames > 1);
+        assert_eq!(finished, 256);
+        assert_eq!(app.world.resource::<TerrainGenMetrics>().tasks_pending, 0);
+        assert_eq!(
+            app.world
+                .query_filtered::<(), With<TerrainGenTask>>()
+                .iter(&app.world)
+                .count(),
+            0
+        );
+        assert_eq!(app.world.resource::<DirtyChunks>().num_dirty(), 256);
+    }
+
+    fn chunk_state(app: &App, entity: Entity) -> ChunkState {
+        *app.world.get::<ChunkState>(entity).unwrap()
+    }
+
+    #[test]
+    fn force_loaded_chunks_match_the_generated_ones() {
+        let key = IVec3::new(-64, 32, 96);
+
+        let mut streamed = terrain_gen_app();
+        let entity = spawn_chunk(&mut streamed, key);
+        for _ in 0..1000 {
+            if chunk_state(&streamed, entity) == ChunkState::Generated {
+                break;
+            }
+            streamed.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let generated = streamed
+            .world
+            .resource::<ChunkMap<Voxel, ChunkShape>>()
+            .buffer_at(key)
+            .expect("the chunk was never generated")
+            .slice()
+            .to_vec();
+
+        // any position within the chunk force loads the whole chunk.
+        let mut forced = terrain_gen_app();
+        let buffer = force_load_chunk(&mut forced.world, key + IVec3::new(5, 31, 2));
+        assert!(buffer.slice() == generated.as_slice());
+        assert!(forced.world.resource::<DirtyChunks>().is_dirty(key));
+    }
+
+    #[test]
+    fn force_loading_drops_the_pending_generation_task() {
+        let mut app = terrain_gen_app();
+        let entity = spawn_chunk(&mut app, IVec3::ZERO);
+        app.update();
+        assert!(app.world.get::<TerrainGenTask>(entity).is_some());
+
+        let forced = force_load_chunk(&mut app.world, IVec3::ZERO)
+            .slice()
+            .to_vec();
+        assert!(app.world.get::<TerrainGenTask>(entity).is_none());
+        assert_eq!(chunk_state(&app, entity), ChunkState::Generated);
+
+        // the chunk isn't generated again once the entity is past the spawned state.
+        app.update();
+        assert!(app.world.get::<TerrainGenTask>(entity).is_none());
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert!(chunks.buffer_at(IVec3::ZERO).unwrap().slice() == forced.as_slice());
+    }
+
+    #[test]
+    fn every_generated_chunk_is_visited() {
+        let mut app = terrain_gen_app();
+        let keys: Vec<_> = (0..6)
+            .map(|x| IVec3::new(x * CHUNK_LENGTH as i32, 32, 0))
+            .collect();
+        let entities: Vec<_> = keys.iter().map(|key| spawn_chunk(&mut app, *key)).collect();
+        for _ in 0..1000 {
+            if entities
+                .iter()
+                .all(|entity| chunk_state(&app, *entity) == ChunkState::Generated)
+            {
+                break;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let mut visited = Vec::new();
+        app.world
+            .resource::<ChunkMap<Voxel, ChunkShape>>()
+            .for_each_loaded(|key, _| visited.push(key));
+        visited.sort_unstable_by_key(|key| key.to_array());
+        assert_eq!(visited.len(), app.world.resource::<ChunkEntities>().len());
+        assert_eq!(visited, keys);
+    }
+
+    #[test]
+    fn chunks_generate_on_a_dedicated_pool() {
+        let mut app = terrain_gen_app();
+        app.insert_resource(VoxelTaskPools::new(&VoxelTaskPoolSettings {
+            meshing_threads: None,
+            generation_threads: Some(2),
+        }));
+        let entity = spawn_chunk(&mut app, IVec3::new(0, 32, 0));
+
+        for _ in 0..10_000 {
+            if chunk_state(&app, entity) == ChunkState::Generated {
+                break;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert_eq!(chunk_state(&app, entity), ChunkState::Generated);
+        assert!(app
+            .world
+            .resource::<ChunkMap<Voxel, ChunkShape>>()
+            .exists(IVec3::new(0, 32, 0)));
+    }
+
+    #[test]
+    fn seeded_chunks_skip_the_terrain_generator() {
+        const MARKER: Voxel = Voxel::new(200);
+        let seeded = IVec3::new(0, 32, 0);
+        let generated = IVec3::new(32, 32, 0);
+
+        let mut app = terrain_gen_app();
+        app.insert_resource(ChunkSeeder::new(move |key| {
+            (key == seeded).then(|| {
+                let mut buffer = VoxelBuffer::new_empty(ChunkShape {});
+                *buffer.voxel_at_mut([1, 2, 3].into()) = MARKER;
+                buffer
+            })
+        }));
+        let entities = [seeded, generated].map(|key| spawn_chunk(&mut app, key));
+
+        for _ in 0..10_000 {
+            if entities
+                .iter()
+                .all(|entity| chunk_state(&app, *entity) == ChunkState::Generated)
+            {
+                break;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let chunks = app.world.resource::<ChunkMap<Voxel, ChunkShape>>();
+        assert_eq!(chunks.voxel_at(seeded + IVec3::new(1, 2, 3)), Some(MARKER));
+        assert_eq!(chunks.voxel_at(seeded), Some(Voxel::EMPTY_VOXEL));
+        assert!(chunks.exists(generated));
+        assert_ne!(
+            chunks.voxel_at(generated + IVec3::new(1, 2, 3)),
+            Some(MARKER)
+        );
+
+        // force loading asks the seeder too.
+        let mut world = terrain_gen_app().world;
+        world.insert_resource(app.world.resource::<ChunkSeeder>().clone());
+        let buffer = force_load_chunk(&mut world, seeded);
+        assert_eq!(buffer.voxel_at([1, 2, 3].into()), MARKER);
+    }
+}
@@ -0,0 +1,213 @@
+use bevy::{
+    prelude::{apply_deferred, Commands, IntoSystemConfigs, Plugin, ResMut, Resource, Update},
+    utils::HashMap,
+};
+
+use super::{
+    chunks::{ChunkCommandQueue, ChunkEntities, ChunkLoadingSet, ModifiedChunks},
+    ChunkShape, Voxel,
+};
+use crate::voxel::storage::ChunkMap;
+
+/// Identifies one of the voxel worlds, the world the app starts in being the default one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorldId(pub u32);
+
+/// The voxel data of a world while it isn't active.
+struct StoredWorld {
+    chunks: ChunkMap<Voxel, ChunkShape>,
+    modified_chunks: ModifiedChunks,
+}
+
+impl Default for StoredWorld {
+    fn default() -> Self {
+        Self {
+            chunks: ChunkMap::new(ChunkShape {}),
+            modified_chunks: ModifiedChunks::default(),
+        }
+    }
+}
+
+/// Resource keeping track of the voxel worlds.
+///
+/// Only the active world lives in the [`ChunkMap`] resource, where it is loaded around the player, simulated and
+/// rendered. The others keep the chunks that were loaded when they were left, untouched until they're active again.
+///
+/// All the worlds share the global [`TERRAIN_GENERATOR`](crate::voxel::terraingen::TERRAIN_GENERATOR): the chunks
+/// a world never held are generated like those of any other world.
+#[derive(Resource, Default)]
+pub struct VoxelWorlds {
+    active: WorldId,
+    requested: Option<WorldId>,
+    inactive: HashMap<WorldId, StoredWorld>,
+}
+
+impl VoxelWorlds {
+    /// Returns the world currently loaded around the player.
+    pub const fn active(&self) -> WorldId {
+        self.active
+    }
+
+    /// Requests the active world to be swapped for the specified one, which starts empty if it never was active.
+    pub fn switch_to(&mut self, world: WorldId) {
+        self.requested = Some(world);
+    }
+
+    /// Returns the voxel data of an inactive world, if it holds any.
+    pub fn inactive_chunks(&self, world: WorldId) -> Option<&ChunkMap<Voxel, ChunkShape>> {
+        self.inactive.get(&world).map(|stored| &stored.chunks)
+    }
+
+    /// Returns the voxel data of an inactive world for editing, creating it if needed.
+    /// Returns `None` for the active world, which is edited through the [`ChunkMap`] resource.
+    pub fn inactive_chunks_mut(
+        &mut self,
+        world: WorldId,
+    ) -> Option<&mut ChunkMap<Voxel, ChunkShape>> {
+        (world != self.active).then(|| &mut self.inactive.entry(world).or_default().chunks)
+    }
+}
+
+/// Swaps the voxel data of the active world for the requested one and respawns the chunks around the player.
+/// The chunks already holding voxel data in the new world are remeshed from it instead of being generated again.
+fn switch_active_world(
+    mut worlds: ResMut<VoxelWorlds>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut modified_chunks: ResMut<ModifiedChunks>,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut commands: Commands,
+) {
+    let Some(next) = worlds
+        .requested
+        .take()
+        .filter(|world| *world != worlds.active)
+    else {
+        return;
+    };
+
+    let next_world = worlds.inactive.remove(&next).unwrap_or_default();
+    let previous_world = StoredWorld {
+        chunks: std::mem::replace(&mut *chunks, next_world.chunks),
+        modified_chunks: std::mem::replace(&mut *modified_chunks, next_world.modified_chunks),
+    };
+    let previous = worlds.active;
+    worlds.inactive.insert(previous, previous_world);
+    worlds.active = next;
+
+    // despawning the chunk entities drops their running tasks, without touching the stored voxel data.
+    let keys: Vec<_> = chunk_entities.iter_keys().copied().collect();
+    for key in keys {
+        if let Some(entity) = chunk_entities.detach_entity(key) {
+            commands.entity(entity).despawn();
+        }
+    }
+    chunk_command_queue.clear();
+}
+
+/// Handles switching between several voxel worlds.
+pub struct VoxelWorldsPlugin;
+
+impl Plugin for VoxelWorldsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<VoxelWorlds>().add_systems(
+            Update,
+            // the old chunk entities must be gone before the chunks of the new world get loaded.
+            (switch_active_world, apply_deferred)
+                .chain()
+                .before(ChunkLoadingSet),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::world::Chunk;
+    use bevy::prelude::{App, IVec3, MinimalPlugins};
+
+    const STONE: Voxel = Voxel::new(1);
+    const WATER: Voxel = Voxel::new(2);
+
+    fn worlds_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelWorldsPlugin))
+            .init_resource::<ChunkEntities>()
+            .init_resource::<ChunkCommandQueue>()
+            .init_resource::<ModifiedChunks>()
+            .insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}));
+        app
+    }
+
+    // loads an edited chunk at the origin of the active world, with a chunk entity.
+    fn edit_active_world(app: &mut App, voxel: Voxel) {
+        let mut chunks = app.world.resource_mut::<ChunkMap<Voxel, ChunkShape>>();
+        chunks.insert_empty(IVec3::ZERO);
+        *chunks.voxel_at_mut(IVec3::ONE).unwrap() = voxel;
+        app.world
+            .resource_mut::<ModifiedChunks>()
+            .mark_modified(IVec3::ZERO);
+        let entity = app.world.spawn(Chunk(IVec3::ZERO)).id();
+        app.world
+            .resource_mut::<ChunkEntities>()
+            .attach_entity(IVec3::ZERO, entity);
+    }
+
+    fn switch_to(app: &mut App, world: WorldId) {
+        app.world.resource_mut::<VoxelWorlds>().switch_to(world);
+        app.update();
+        assert_eq!(app.world.resource::<VoxelWorlds>().active(), world);
+    }
+
+    fn active_voxel(app: &App) -> Option<Voxel> {
+        app.world
+            .resource::<ChunkMap<Voxel, ChunkShape>>()
+            .voxel_at(IVec3::ONE)
+    }
+
+    #[test]
+    fn worlds_keep_their_own_edits() {
+        let mut app = worlds_app();
+        edit_active_world(&mut app, STONE);
+
+        switch_to(&mut app, WorldId(1));
+        assert_eq!(active_voxel(&app), None);
+        assert!(!app
+            .world
+            .resource::<ModifiedChunks>()
+            .is_modified(IVec3::ZERO));
+        // the chunk entities of the previous world are gone.
+        assert_eq!(app.world.query::<&Chunk>().iter(&app.world).count(), 0);
+
+        edit_active_world(&mut app, WATER);
+        switch_to(&mut app, WorldId::default());
+        assert_eq!(active_voxel(&app), Some(STONE));
+        assert!(app
+            .world
+            .resource::<ModifiedChunks>()
+            .is_modified(IVec3::ZERO));
+
+        let worlds = app.world.resource::<VoxelWorlds>();
+        assert_eq!(
+            worlds
+                .inactive_chunks(WorldId(1))
+                .and_then(|chunks| chunks.voxel_at(IVec3::ONE)),
+            Some(WATER)
+        );
+        assert!(worlds.inactive_chunks(WorldId::default()).is_none());
+    }
+
+    #[test]
+    fn the_active_world_cant_be_edited_as_an_inactive_one() {
+        let mut worlds = VoxelWorlds::default();
+        assert!(worlds.inactive_chunks_mut(WorldId::default()).is_none());
+
+        worlds
+            .inactive_chunks_mut(WorldId(2))
+            .unwrap()
+            .insert_empty(IVec3::ZERO);
+        assert!(worlds
+            .inactive_chunks(WorldId(2))
+            .is_some_and(|chunks| chunks.exists(IVec3::ZERO)));
+    }
+}